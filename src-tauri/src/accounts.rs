@@ -0,0 +1,169 @@
+// ============== PROVIDER ACCOUNTS ==============
+//
+// Several named accounts per provider type (personal and work Claude plans,
+// OpenAI organizations, ...). The built-in entry of a type keeps its plain ID
+// ("anthropic"); added accounts get "<type>:<slug>" IDs ("anthropic:work").
+// Since API keys, internal tokens, thresholds and history are all keyed by
+// provider ID, every account has its own.
+
+use serde::Serialize;
+use std::sync::Mutex;
+
+use crate::i18n::tr;
+use crate::providers::{self, ProviderRegistry};
+use crate::token_store::{self, TokenAccount};
+use crate::{delete_api_key, save_state, AppError, AppState, ProviderType};
+
+/// Account of a provider, as listed in the settings
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderAccount {
+    pub id: String,
+    pub provider_type: ProviderType,
+    pub name: String,
+    pub enabled: bool,
+    /// Built-in entry of the type, which can't be removed
+    pub is_default: bool,
+    pub has_api_key: bool,
+    pub credentials_path: Option<String>,
+}
+
+/// Whether `provider_id` is the built-in entry of its type
+pub fn is_builtin(provider_id: &str) -> bool {
+    !provider_id.contains(':')
+}
+
+/// "Work Org #2" -> "work-org-2"
+fn slugify(name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "account".to_string()
+    } else {
+        slug
+    }
+}
+
+pub(crate) fn check_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::ConfigError(tr!("error.empty_account_name")));
+    }
+    Ok(name.to_string())
+}
+
+/// Every account, grouped by type with the built-in one first
+pub fn list(state: &AppState) -> Vec<ProviderAccount> {
+    let mut accounts: Vec<ProviderAccount> = state
+        .providers
+        .iter()
+        .map(|(id, p)| ProviderAccount {
+            id: id.clone(),
+            provider_type: p.config.provider_type.clone(),
+            name: p.config.name.clone(),
+            enabled: p.config.enabled,
+            is_default: is_builtin(id),
+            has_api_key: p.config.has_api_key,
+            credentials_path: p.config.credentials_path.clone(),
+        })
+        .collect();
+    accounts.sort_by_key(|a| (a.provider_type.as_str(), !a.is_default, a.name.clone()));
+    accounts
+}
+
+/// Add an account of `provider_type` with the default configuration and
+/// return its ID
+pub fn add(
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+    provider_type: &ProviderType,
+    name: &str,
+) -> Result<String, AppError> {
+    let name = check_name(name)?;
+    let backend = registry.get(provider_type).ok_or_else(|| {
+        AppError::ConfigError(tr!("error.no_backend", provider = format!("{:?}", provider_type)))
+    })?;
+
+    let mut state = state.lock().unwrap();
+    let base = format!("{}:{}", provider_type.as_str(), slugify(&name));
+    let mut id = base.clone();
+    let mut n = 2;
+    while state.providers.contains_key(&id) {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+
+    let mut entry = providers::default_entry(backend.as_ref());
+    entry.config.name = name.clone();
+    entry.usage.provider_name = name;
+    state.providers.insert(id.clone(), entry);
+    save_state(&state);
+    Ok(id)
+}
+
+pub fn rename(state: &Mutex<AppState>, provider_id: &str, name: &str) -> Result<(), AppError> {
+    let name = check_name(name)?;
+    let mut state = state.lock().unwrap();
+    let provider = state
+        .providers
+        .get_mut(provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    provider.config.name = name.clone();
+    provider.usage.provider_name = name;
+    save_state(&state);
+    Ok(())
+}
+
+/// Remove an added account with its API key and internal token. The
+/// built-in accounts can't be removed.
+pub fn remove(state: &Mutex<AppState>, provider_id: &str) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    let provider = state
+        .providers
+        .get(provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    if is_builtin(provider_id) {
+        return Err(AppError::ConfigError(tr!(
+            "error.builtin_account_locked",
+            provider = provider.config.name
+        )));
+    }
+
+    delete_api_key(provider_id)?;
+    if provider.config.provider_type == ProviderType::Anthropic {
+        token_store::delete_internal_token(&TokenAccount::new(&state, provider_id))?;
+    }
+    state.providers.remove(provider_id);
+    if state.active_provider == provider_id {
+        state.active_provider = "manual".to_string();
+    }
+    save_state(&state);
+    Ok(())
+}
+
+/// Set the Claude Code credentials file of a Claude account (None: the
+/// built-in account goes back to the settings' custom path and auto-detection)
+pub fn set_credentials_path(
+    state: &Mutex<AppState>,
+    provider_id: &str,
+    path: Option<String>,
+) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    let provider = state
+        .providers
+        .get_mut(provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    if provider.config.provider_type != ProviderType::Anthropic {
+        return Err(AppError::ConfigError(tr!("error.no_credentials_file")));
+    }
+    provider.config.credentials_path = path.filter(|p| !p.trim().is_empty());
+    save_state(&state);
+    Ok(())
+}
@@ -0,0 +1,75 @@
+// ============== LOCAL HTTP API SERVER ==============
+//
+// Runs the local HTTP API (`meter_ai::local_api`) for the app: requests read
+// the managed app state, and the app events are forwarded to the `/events`
+// subscribers.
+
+use hyper::{Body, Response};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use meter_ai::local_api::{self, ApiBackend};
+use meter_ai::{ApiServerConfig, AppError, AppState};
+
+/// Events kept for a slow `/events` client before it skips ahead
+const EVENT_BUFFER: usize = 64;
+
+struct Running {
+    task: JoinHandle<()>,
+    /// Dropped on stop, which ends the open event streams
+    events: broadcast::Sender<String>,
+}
+
+static RUNNING: Mutex<Option<Running>> = Mutex::new(None);
+
+struct Backend(AppHandle);
+
+impl ApiBackend for Backend {
+    fn with_state(&self, respond: &dyn Fn(&AppState) -> Response<Body>) -> Response<Body> {
+        let state = self.0.state::<Mutex<AppState>>();
+        let state = state.lock().unwrap();
+        respond(&state)
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<String>> {
+        RUNNING.lock().unwrap().as_ref().map(|r| r.events.subscribe())
+    }
+}
+
+/// Forward an app event to the `/events` subscribers
+pub fn broadcast<T: Serialize>(event: &str, payload: &T) {
+    let running = RUNNING.lock().unwrap();
+    let events = match running.as_ref() {
+        Some(running) if running.events.receiver_count() > 0 => &running.events,
+        _ => return,
+    };
+    if let Ok(data) = serde_json::to_string(payload) {
+        events.send(format!("event: {}\ndata: {}\n\n", event, data)).ok();
+    }
+}
+
+/// Stop the running server, if any, and wait until its port is released
+pub async fn stop() {
+    let running = RUNNING.lock().unwrap().take();
+    if let Some(running) = running {
+        running.task.abort();
+        running.task.await.ok();
+    }
+}
+
+/// (Re)start the server with `config`, or stop it when disabled
+pub async fn apply(app: AppHandle, config: ApiServerConfig) -> Result<(), AppError> {
+    stop().await;
+
+    let token = match config.token {
+        Some(token) if config.enabled && !token.is_empty() => token,
+        _ => return Ok(()),
+    };
+    let (_, task) = local_api::start(config.port, token, Arc::new(Backend(app)))?;
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    *RUNNING.lock().unwrap() = Some(Running { task, events });
+    Ok(())
+}
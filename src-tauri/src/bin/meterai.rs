@@ -0,0 +1,467 @@
+// ============== COMMAND-LINE INTERFACE ==============
+//
+// `meterai`: usage from scripts and shell prompts without the GUI. Reads the
+// same state as the app. While the app runs it owns that state: nothing is
+// saved from here, and `add` and `reset` are queued for the app, which
+// applies them within seconds (see `counters`). `--json` prints
+// machine-readable output on every subcommand.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use meter_ai::accounts;
+use meter_ai::budgets::{self, BudgetStatus};
+use meter_ai::counters::{self, QueuedChange};
+use meter_ai::forecast::{self, Forecast};
+use meter_ai::i18n::{self, tr, Locale};
+use meter_ai::pricing::PricingCatalog;
+use meter_ai::providers::{self, ProviderRegistry};
+use meter_ai::summary::{self, format_amount, status_line};
+use meter_ai::token_store::{self, TokenAccount, TokenStatus};
+use meter_ai::transcripts::{self, TokenGroup};
+use meter_ai::webhooks;
+use meter_ai::{http, load_state, mock_server, AppState, UsageData};
+
+const USAGE: &str = "\
+Usage: meterai [--json] [--account <id>] [--kind <kind>] <command>
+
+Commands:
+  status                  Usage of every enabled provider (last fetch)
+  accounts                Every provider account with its ID
+  fetch <provider>        Fetch usage now (anthropic, openai, ...)
+  counters                Every manual counter with its ID
+  add <n> [counter]       Add n (fractional, negative to correct) to a counter
+                          (the active provider by default)
+  reset [counter]         Reset a counter (the active provider by default)
+  budget                  Spend of the month against the budgets, with projection
+  forecast [provider]     Burn rate and projected exhaustion of every window
+  tokens [day|project|model]
+                          Claude Code tokens and API-equivalent cost of the
+                          last 30 days, from the local session transcripts
+  pricing                 Model prices in USD per million tokens
+  token status            Internal Claude Code token status
+  token copy              Copy the Claude Code token to internal storage
+  token refresh           Renew the internal token with its refresh token
+  token export            Print the internal token as credentials JSON
+  token import [file|-]   Import credentials JSON (stdin by default)
+  webhook test <id>       Send a sample alert to a webhook target
+
+Options:
+  --json                  Print JSON instead of text
+  --account <id>          Claude account of the token commands (anthropic:work, ...)
+  --kind <kind>           Count `add` with the counter's weight for <kind> (opus, ...)
+  -h, --help              Show this help
+  -V, --version           Show the version";
+
+/// Command-line failure: (message, exit code)
+struct CliError(String, u8);
+
+impl<E: fmt::Display> From<E> for CliError {
+    fn from(error: E) -> Self {
+        CliError(error.to_string(), 1)
+    }
+}
+
+/// Longest wait for webhook deliveries raised by `add` before exiting
+const WEBHOOK_FLUSH_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn usage_error(message: &str) -> CliError {
+    CliError(format!("{}\n\n{}", message, USAGE), 2)
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut account: Option<String> = None;
+    let mut kind: Option<String> = None;
+    let mut args: Vec<String> = Vec::new();
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--account" => match argv.next() {
+                Some(id) => account = Some(id),
+                None => {
+                    eprintln!("meterai: --account needs a provider ID\n\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "--kind" => match argv.next() {
+                Some(name) => kind = Some(name),
+                None => {
+                    eprintln!("meterai: --kind needs a name\n\n{}", USAGE);
+                    return ExitCode::from(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            "-V" | "--version" => {
+                println!("meterai {}", env!("CARGO_PKG_VERSION"));
+                return ExitCode::SUCCESS;
+            }
+            _ => args.push(arg),
+        }
+    }
+
+    // Held until exit when the app isn't running, so it can't start and load
+    // data.json before this process is done with it
+    let app_lock = meter_ai::try_lock_app();
+    let app_running = matches!(app_lock, Ok(None));
+    if app_running {
+        meter_ai::disable_save();
+    }
+
+    let state = load_state();
+    i18n::set_locale(state.settings.locale.unwrap_or_else(Locale::system));
+
+    let account = TokenAccount::new(&state, account.as_deref().unwrap_or(TokenAccount::DEFAULT_ID));
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = run(state, &args, &account, kind.as_deref(), app_running, json);
+    webhooks::flush(WEBHOOK_FLUSH_TIMEOUT);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError(message, code)) => {
+            eprintln!("meterai: {}", message);
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run(
+    mut state: AppState,
+    args: &[&str],
+    account: &TokenAccount,
+    kind: Option<&str>,
+    app_running: bool,
+    json: bool,
+) -> Result<(), CliError> {
+    match args {
+        ["status"] => status(&state, json),
+        ["accounts"] => list_accounts(&state, json),
+        ["fetch", provider_id] => fetch(state, provider_id, json),
+        ["counters"] => list_counters(&state, json),
+        ["add", count] | ["add", count, _] => {
+            let count: f64 = count
+                .parse()
+                .ok()
+                .filter(|count: &f64| count.is_finite())
+                .ok_or_else(|| usage_error(&format!("invalid count '{}'", count)))?;
+            let provider_id = args.get(2).copied().unwrap_or(&state.active_provider).to_string();
+            let config = state
+                .providers
+                .get(&provider_id)
+                .map(|p| p.config.clone())
+                .ok_or_else(|| CliError(tr!("error.provider_not_found"), 1))?;
+            let amount = counters::weighted_amount(&config, count, kind)?;
+            if app_running {
+                return queue_change(QueuedChange::Add { provider_id, amount }, json);
+            }
+            let (usage, alerts) = meter_ai::add_counter_usage(&mut state, &provider_id, amount)
+                .ok_or_else(|| CliError(tr!("error.provider_not_found"), 1))?;
+            alerts.into_iter().for_each(meter_ai::raise_alert);
+            print_usage(&state, &provider_id, &usage, json)
+        }
+        ["reset"] | ["reset", _] => {
+            let provider_id = args.get(1).copied().unwrap_or(&state.active_provider).to_string();
+            if !state.providers.contains_key(&provider_id) {
+                return Err(CliError(tr!("error.provider_not_found"), 1));
+            }
+            if app_running {
+                return queue_change(QueuedChange::Reset { provider_id }, json);
+            }
+            let usage = meter_ai::reset_counter(&mut state, &provider_id)
+                .ok_or_else(|| CliError(tr!("error.provider_not_found"), 1))?;
+            print_usage(&state, &provider_id, &usage, json)
+        }
+        ["budget"] => print_budgets(&budgets::status_all(&state, chrono::Utc::now().timestamp()), json),
+        ["forecast"] => print_forecasts(&forecast::forecast_all(&state, chrono::Utc::now().timestamp()), json),
+        ["forecast", provider_id] => {
+            if !state.providers.contains_key(*provider_id) {
+                return Err(CliError(tr!("error.provider_not_found"), 1));
+            }
+            print_forecasts(&forecast::forecast(&state, provider_id, chrono::Utc::now().timestamp()), json)
+        }
+        ["tokens"] => print_tokens(None, json),
+        ["tokens", group] => {
+            let group = match *group {
+                "day" => TokenGroup::Day,
+                "project" => TokenGroup::Project,
+                "model" => TokenGroup::Model,
+                _ => return Err(usage_error(&format!("unknown grouping '{}'", group))),
+            };
+            print_tokens(Some(group), json)
+        }
+        ["pricing"] => print_pricing(json),
+        ["token", "status"] => print_token_status(&token_store::token_status(account), json),
+        ["token", "copy"] => {
+            let status = token_store::copy_token_to_internal(account)?;
+            print_token_status(&status, json)
+        }
+        ["token", "refresh"] => {
+            tokio::runtime::Runtime::new()?
+                .block_on(token_store::refresh_internal_token(account, &state.settings))?;
+            print_token_status(&token_store::token_status(account), json)
+        }
+        // Always JSON: the output is the credentials file itself
+        ["token", "export"] => {
+            println!("{}", token_store::export_token_data(account)?);
+            Ok(())
+        }
+        ["token", "import"] | ["token", "import", "-"] => {
+            let mut data = String::new();
+            io::stdin().read_to_string(&mut data)?;
+            print_token_status(&token_store::import_token_data(account, &data)?, json)
+        }
+        ["token", "import", path] => {
+            let data = fs::read_to_string(path)?;
+            print_token_status(&token_store::import_token_data(account, &data)?, json)
+        }
+        ["webhook", "test", id] => {
+            let target = state
+                .settings
+                .webhooks
+                .iter()
+                .find(|t| t.id == *id)
+                .ok_or_else(|| CliError(tr!("error.webhook_not_found", id = id), 1))?;
+            tokio::runtime::Runtime::new()?.block_on(webhooks::send_test(&state.settings, target))?;
+            if json {
+                println!("{}", serde_json::json!({ "sent": true }));
+            } else {
+                println!("Sent a test alert to '{}'", target.name);
+            }
+            Ok(())
+        }
+        [] => Err(usage_error("missing command")),
+        _ => Err(usage_error(&format!("unknown command '{}'", args.join(" ")))),
+    }
+}
+
+fn status(state: &AppState, json: bool) -> Result<(), CliError> {
+    let snapshot = summary::snapshot(state);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&snapshot)?);
+    } else {
+        for provider in snapshot.providers {
+            let marker = if provider.active { "*" } else { " " };
+            println!("{} {}", marker, provider.summary);
+        }
+    }
+    Ok(())
+}
+
+fn list_accounts(state: &AppState, json: bool) -> Result<(), CliError> {
+    let accounts = accounts::list(state);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&accounts)?);
+    } else {
+        for account in accounts {
+            let enabled = if account.enabled { "" } else { " (disabled)" };
+            println!("{:<24} {}{}", account.id, account.name, enabled);
+        }
+    }
+    Ok(())
+}
+
+fn list_counters(state: &AppState, json: bool) -> Result<(), CliError> {
+    let counters = counters::list(state);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&counters)?);
+    } else {
+        for counter in counters {
+            println!(
+                "{:<24} {}: {}/{} {}",
+                counter.id,
+                counter.settings.name,
+                format_amount(counter.usage.used),
+                format_amount(counter.usage.limit),
+                counter.settings.unit.unwrap_or_else(|| tr!("unit.requests"))
+            );
+        }
+    }
+    Ok(())
+}
+
+fn fetch(state: AppState, provider_id: &str, json: bool) -> Result<(), CliError> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let state = Mutex::new(state);
+    let registry = ProviderRegistry::builtin();
+
+    let report = runtime.block_on(async {
+        let settings = state.lock().unwrap().settings.clone();
+        if mock_server::is_enabled(&settings) {
+            let base_url = mock_server::start(([127, 0, 0, 1], 0).into()).await?;
+            http::enable_mock_mode(base_url);
+        }
+        providers::fetch_provider(&state, &registry, provider_id).await
+    })?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        let state = state.lock().unwrap();
+        if let Some(provider) = state.providers.get(provider_id) {
+            println!("{}", status_line(provider));
+        }
+    }
+    Ok(())
+}
+
+/// Hand a counter change to the running app
+fn queue_change(change: QueuedChange, json: bool) -> Result<(), CliError> {
+    counters::queue(&change)?;
+    if json {
+        println!("{}", serde_json::json!({ "queued": change }));
+    } else {
+        println!("Queued for the running app");
+    }
+    Ok(())
+}
+
+fn print_usage(state: &AppState, provider_id: &str, usage: &UsageData, json: bool) -> Result<(), CliError> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(usage)?);
+    } else if let Some(provider) = state.providers.get(provider_id) {
+        println!("{}", status_line(provider));
+    }
+    Ok(())
+}
+
+fn print_budgets(statuses: &[BudgetStatus], json: bool) -> Result<(), CliError> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(statuses)?);
+        return Ok(());
+    }
+
+    for status in statuses {
+        let mut line = format!(
+            "{:<24} ${} spent, ${} projected",
+            status.name,
+            format_amount(status.spent_usd),
+            format_amount(status.projected_usd)
+        );
+        if let (Some(budget), Some(percent)) = (status.budget_usd, status.percent) {
+            line.push_str(&format!(" / ${} budget ({:.0}%)", format_amount(budget), percent));
+        }
+        if status.projected_overrun {
+            match &status.exhausted_on {
+                Some(date) => line.push_str(&format!(" — over budget by {}", date)),
+                None => line.push_str(" — over budget"),
+            }
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn print_forecasts(forecasts: &[Forecast], json: bool) -> Result<(), CliError> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(forecasts)?);
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let in_hours = |at: i64| format!("{:.1}h", (at - now).max(0) as f64 / 3600.0);
+    for forecast in forecasts {
+        let mut line = format!("{:<24} {:<16} {:>5.1}%", forecast.provider_id, forecast.window, forecast.percent);
+        match forecast.rate_per_hour {
+            Some(rate) => line.push_str(&format!("  {:.1}%/h", rate)),
+            None => line.push_str("  (not enough samples)"),
+        }
+        if let Some(projected) = forecast.projected_percent_at_reset {
+            line.push_str(&format!("  {:.0}% at reset", projected));
+        }
+        if let Some(at) = forecast.exhausts_at.filter(|_| forecast.exhausts_before_reset) {
+            line.push_str(&format!("  runs out in {}", in_hours(at)));
+        } else if let Some(reset) = forecast.resets_at {
+            line.push_str(&format!("  resets in {}", in_hours(reset)));
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn print_tokens(group: Option<TokenGroup>, json: bool) -> Result<(), CliError> {
+    transcripts::index(false)?;
+    let to = chrono::Utc::now().timestamp();
+    let report = transcripts::report(to - 30 * 86_400, to, group);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let cost = |cost: Option<f64>| cost.map_or("?".to_string(), |c| format!("${:.2}", c));
+    for row in &report.rows {
+        let label: Vec<&str> = [row.date.as_deref(), row.project.as_deref(), row.model.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        println!("{:<48} {:>14} {:>10}", label.join("  "), row.tokens.total(), cost(row.cost_usd));
+    }
+    println!(
+        "{:<48} {:>14} {:>10}",
+        "Total",
+        report.tokens.total(),
+        cost(Some(report.cost_usd))
+    );
+    if !report.unpriced_models.is_empty() {
+        println!("No price for {}", report.unpriced_models.join(", "));
+    }
+    Ok(())
+}
+
+fn print_pricing(json: bool) -> Result<(), CliError> {
+    let catalog = PricingCatalog::load();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&catalog)?);
+        return Ok(());
+    }
+
+    println!("Catalog {}", catalog.version);
+    let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| r.to_string());
+    for price in &catalog.models {
+        let from = price.effective_from.map(|d| format!("from {}", d)).unwrap_or_default();
+        println!(
+            "{:<10} {:<20} in {:<6} out {:<6} cache w {:<6} r {:<6} batch {}/{} {}",
+            price.provider.as_str(),
+            price.model,
+            price.input,
+            price.output,
+            rate(price.cache_write),
+            rate(price.cache_read),
+            rate(price.batch_input),
+            rate(price.batch_output),
+            from
+        );
+    }
+    Ok(())
+}
+
+fn print_token_status(status: &TokenStatus, json: bool) -> Result<(), CliError> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(status)?);
+        return Ok(());
+    }
+
+    match (&status.token_preview, &status.token_hash) {
+        (Some(preview), Some(hash)) => println!("Internal token: {} ({})", preview, hash),
+        _ if status.has_internal_token => println!("Internal token: stored"),
+        _ => println!("Internal token: none"),
+    }
+    if let Some(copied_at) = &status.copied_at {
+        println!("Copied at:      {}", copied_at);
+    }
+    if let Some(expires_at) = &status.expires_at {
+        println!("Expires at:     {}", expires_at);
+    }
+    println!("Source:         {}", status.source);
+    if status.source_differs {
+        println!("The source token differs from the internal copy (run `meterai token copy`)");
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+// ============== MONTHLY BUDGETS ==============
+//
+// User-defined monthly budgets in USD, per provider account and across all
+// of them, checked against the spend the API providers report for the
+// current calendar month (UTC). The end-of-month projection adds, for every
+// remaining day, the average daily cost of the last complete days (up to a
+// week), or of the month so far when there are none.
+//
+// Alerts are raised once per month and budget: when the projection exceeds
+// the budget, and when the spend does.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::i18n::tr;
+use crate::providers::UsageReport;
+use crate::summary::format_amount;
+use crate::webhooks::{Alert, AlertEvent};
+use crate::{AppError, AppState};
+
+/// Complete days averaged for the projection
+const RATE_DAYS: i64 = 7;
+
+/// Key of the global budget in `AppState.budget_alerts`
+const GLOBAL: &str = "global";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Budgets {
+    /// Budget across every provider
+    #[serde(rename = "globalUsd", default)]
+    pub global_usd: Option<f64>,
+    /// Budget per provider ID
+    #[serde(default)]
+    pub providers: HashMap<String, f64>,
+}
+
+/// Alerts already raised for a budget this month
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetAlertState {
+    /// "2026-10"
+    pub month: String,
+    pub projected: bool,
+    pub exceeded: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    /// None for the global budget
+    pub provider_id: Option<String>,
+    pub name: String,
+    /// "2026-10"
+    pub month: String,
+    pub budget_usd: Option<f64>,
+    pub spent_usd: f64,
+    /// Daily cost used for the projection
+    pub daily_rate_usd: f64,
+    /// Spend at the end of the month at that rate
+    pub projected_usd: f64,
+    pub percent: Option<f64>,
+    pub projected_percent: Option<f64>,
+    pub projected_overrun: bool,
+    /// Day the budget runs out at that rate ("YYYY-MM-DD", None if it doesn't)
+    pub exhausted_on: Option<String>,
+}
+
+impl Budgets {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let valid = |usd: &f64| usd.is_finite() && *usd > 0.0;
+        if self.global_usd.as_ref().is_none_or(valid) && self.providers.values().all(valid) {
+            Ok(())
+        } else {
+            Err(AppError::ConfigError(tr!("error.invalid_budget")))
+        }
+    }
+}
+
+/// First day of the month of `now` and of the next month
+fn month_bounds(now: DateTime<Utc>) -> (NaiveDate, NaiveDate) {
+    let start = NaiveDate::from_ymd_opt(now.year(), now.month(), 1).unwrap_or_default();
+    let next = if now.month() == 12 {
+        NaiveDate::from_ymd_opt(now.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(now.year(), now.month() + 1, 1)
+    };
+    (start, next.unwrap_or_default())
+}
+
+/// Daily costs of a report (`daily_costs` of OpenAI, or of the Admin API part
+/// of a Claude report)
+fn daily_costs(report: &UsageReport) -> Vec<(NaiveDate, f64)> {
+    let details = &report.details;
+    let days = details
+        .get("daily_costs")
+        .or_else(|| details.get("admin_api").and_then(|admin| admin.get("daily_costs")))
+        .and_then(|days| days.as_array());
+    days.into_iter()
+        .flatten()
+        .filter_map(|day| {
+            let date = NaiveDate::parse_from_str(day.get("date")?.as_str()?, "%Y-%m-%d").ok()?;
+            Some((date, day.get("cost_usd")?.as_f64()?))
+        })
+        .collect()
+}
+
+/// Spend of the month by day. A report without daily costs counts its total
+/// on the day it was fetched.
+fn month_spend(report: &UsageReport, start: NaiveDate, end: NaiveDate) -> BTreeMap<NaiveDate, f64> {
+    let mut days: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    let costs = daily_costs(report);
+    if costs.is_empty() {
+        let fetched = DateTime::<Utc>::from_timestamp(report.fetched_at, 0).map(|at| at.date_naive());
+        if let (Some(fetched), Some(cost)) = (fetched, report.cost_usd) {
+            days.insert(fetched, cost);
+        }
+    } else {
+        for (date, cost) in costs {
+            *days.entry(date).or_default() += cost;
+        }
+    }
+    days.retain(|date, _| *date >= start && *date < end);
+    days
+}
+
+fn status(
+    provider_id: Option<&str>,
+    name: String,
+    budget_usd: Option<f64>,
+    days: &BTreeMap<NaiveDate, f64>,
+    now: DateTime<Utc>,
+) -> BudgetStatus {
+    let (start, end) = month_bounds(now);
+    let today = now.date_naive();
+    let spent_usd: f64 = days.values().sum();
+
+    let complete_from = (today - Duration::days(RATE_DAYS)).max(start);
+    let complete_days = (today - complete_from).num_days();
+    let daily_rate_usd = if complete_days > 0 {
+        days.range(complete_from..today).map(|(_, cost)| cost).sum::<f64>() / complete_days as f64
+    } else {
+        // First day of the month: extrapolate today's spend
+        let elapsed = (now - Utc.from_utc_datetime(&start.and_hms_opt(0, 0, 0).unwrap_or_default())).num_seconds();
+        spent_usd / (elapsed.max(3600) as f64 / 86_400.0)
+    };
+    let month_end = Utc.from_utc_datetime(&end.and_hms_opt(0, 0, 0).unwrap_or_default());
+    let remaining_days = (month_end - now).num_seconds().max(0) as f64 / 86_400.0;
+    let projected_usd = spent_usd + daily_rate_usd * remaining_days;
+
+    let percent = budget_usd.map(|budget| spent_usd / budget * 100.0);
+    let projected_percent = budget_usd.map(|budget| projected_usd / budget * 100.0);
+    let exhausted_on = budget_usd.and_then(|budget| {
+        if spent_usd >= budget {
+            return Some(today);
+        }
+        // An empty sum is -0.0: check before dividing
+        if daily_rate_usd <= 0.0 {
+            return None;
+        }
+        let days_left = (budget - spent_usd) / daily_rate_usd;
+        let at = now + Duration::seconds((days_left * 86_400.0).min(86_400.0 * 366.0) as i64);
+        (at < month_end).then(|| at.date_naive())
+    });
+
+    BudgetStatus {
+        provider_id: provider_id.map(str::to_string),
+        name,
+        month: now.format("%Y-%m").to_string(),
+        budget_usd,
+        spent_usd,
+        daily_rate_usd,
+        projected_usd,
+        percent,
+        projected_percent,
+        projected_overrun: budget_usd.is_some_and(|budget| projected_usd > budget),
+        exhausted_on: exhausted_on.map(|date| date.format("%Y-%m-%d").to_string()),
+    }
+}
+
+/// Global status first, then every provider reporting a spend or with a
+/// budget, by name
+pub fn status_all(state: &AppState, now: i64) -> Vec<BudgetStatus> {
+    let now = DateTime::<Utc>::from_timestamp(now, 0).unwrap_or_else(Utc::now);
+    let (start, end) = month_bounds(now);
+    let budgets = &state.settings.budgets;
+
+    let mut global: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    let mut providers = Vec::new();
+    for (id, provider) in state.providers.iter().filter(|(_, p)| p.config.enabled) {
+        let days = match provider.last_report.as_ref() {
+            Some(report) if report.cost_usd.is_some() => month_spend(report, start, end),
+            _ if budgets.providers.contains_key(id) => BTreeMap::new(),
+            _ => continue,
+        };
+        for (date, cost) in &days {
+            *global.entry(*date).or_default() += cost;
+        }
+        let budget = budgets.providers.get(id).copied();
+        providers.push(status(Some(id), provider.config.name.clone(), budget, &days, now));
+    }
+    providers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut statuses = vec![status(None, tr!("budget.global"), budgets.global_usd, &global, now)];
+    statuses.extend(providers);
+    statuses
+}
+
+/// Alerts to raise, once per month, on each budget projected to be
+/// exceeded, then exceeded
+pub(crate) fn check_alerts(state: &mut AppState, now: i64) -> Vec<Alert> {
+    let budgets = &state.settings.budgets;
+    if budgets.global_usd.is_none() && budgets.providers.is_empty() {
+        return vec![];
+    }
+
+    let mut raised = Vec::new();
+    for status in status_all(state, now) {
+        let Some(budget) = status.budget_usd else {
+            continue;
+        };
+        let key = status.provider_id.clone().unwrap_or_else(|| GLOBAL.to_string());
+        let alerts = state.budget_alerts.entry(key).or_default();
+        if alerts.month != status.month {
+            *alerts = BudgetAlertState {
+                month: status.month.clone(),
+                ..Default::default()
+            };
+        }
+
+        let (spent, budget_text) = (format_amount(status.spent_usd), format_amount(budget));
+        let (title, message) = if status.spent_usd >= budget && !alerts.exceeded {
+            alerts.exceeded = true;
+            // The projection alert would only repeat this one
+            alerts.projected = true;
+            (
+                tr!("notify.budget_exceeded.title", name = status.name),
+                tr!("notify.budget_exceeded.body", spent = spent, budget = budget_text),
+            )
+        } else if status.projected_overrun && !alerts.projected {
+            alerts.projected = true;
+            (
+                tr!("notify.budget_projected.title", name = status.name),
+                tr!(
+                    "notify.budget_projected.body",
+                    projected = format_amount(status.projected_usd),
+                    budget = budget_text,
+                    spent = spent
+                ),
+            )
+        } else {
+            continue;
+        };
+
+        raised.push(Alert {
+            event: AlertEvent::Budget,
+            provider_id: status.provider_id.clone().unwrap_or_else(|| GLOBAL.to_string()),
+            provider_name: status.name.clone(),
+            window: None,
+            threshold: None,
+            percent: status.percent,
+            resets_at: None,
+            title,
+            message,
+            timestamp: now,
+        });
+    }
+    raised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    fn date(ymd: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(ymd, "%Y-%m-%d").unwrap()
+    }
+
+    fn days(costs: &[(&str, f64)]) -> BTreeMap<NaiveDate, f64> {
+        costs.iter().map(|(day, cost)| (date(day), *cost)).collect()
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn month_bounds_roll_over_in_december() {
+        assert_eq!(month_bounds(at("2026-10-15T12:00:00Z")), (date("2026-10-01"), date("2026-11-01")));
+        assert_eq!(month_bounds(at("2026-12-31T23:59:59Z")), (date("2026-12-01"), date("2027-01-01")));
+        assert_eq!(month_bounds(at("2027-01-01T00:00:00Z")), (date("2027-01-01"), date("2027-02-01")));
+    }
+
+    #[test]
+    fn first_day_extrapolates_todays_spend() {
+        // $3 in the first 6 hours: $12 a day for the 30.75 days left
+        let status = super::status(None, "All".to_string(), Some(100.0), &days(&[("2026-10-01", 3.0)]), at("2026-10-01T06:00:00Z"));
+        assert_close(status.daily_rate_usd, 12.0);
+        assert_close(status.projected_usd, 3.0 + 12.0 * 30.75);
+        assert!(status.projected_overrun);
+        // $97 left at $12 a day: a bit over 8 days
+        assert_eq!(status.exhausted_on.as_deref(), Some("2026-10-09"));
+
+        // The first hour counts as a full hour
+        let status = super::status(None, "All".to_string(), None, &days(&[("2026-10-01", 1.0)]), at("2026-10-01T00:10:00Z"));
+        assert_close(status.daily_rate_usd, 24.0);
+        assert_eq!(status.exhausted_on, None);
+    }
+
+    #[test]
+    fn projection_uses_the_last_complete_days() {
+        let mut costs: Vec<(String, f64)> = (1..=14).map(|day| (format!("2026-10-{:02}", day), 2.0)).collect();
+        costs[0].1 = 50.0;
+        costs.push(("2026-10-15".to_string(), 5.0));
+        let days: BTreeMap<NaiveDate, f64> = costs.iter().map(|(day, cost)| (date(day), *cost)).collect();
+
+        // The $50 of the 1st is older than a week: $2 a day
+        let status = super::status(Some("openai"), "OpenAI".to_string(), Some(120.0), &days, at("2026-10-15T12:00:00Z"));
+        assert_eq!(status.month, "2026-10");
+        assert_close(status.spent_usd, 81.0);
+        assert_close(status.daily_rate_usd, 2.0);
+        assert_close(status.projected_usd, 81.0 + 2.0 * 16.5);
+        assert_close(status.percent.unwrap(), 67.5);
+        assert!(!status.projected_overrun);
+        assert_eq!(status.exhausted_on, None);
+
+        let status = super::status(Some("openai"), "OpenAI".to_string(), Some(100.0), &days, at("2026-10-15T12:00:00Z"));
+        assert!(status.projected_overrun);
+        // $19 left at $2 a day: 9.5 days
+        assert_eq!(status.exhausted_on.as_deref(), Some("2026-10-25"));
+    }
+
+    #[test]
+    fn december_projects_to_the_new_year() {
+        let status = super::status(None, "All".to_string(), Some(1000.0), &days(&[("2026-12-30", 7.0)]), at("2026-12-31T12:00:00Z"));
+        assert_eq!(status.month, "2026-12");
+        assert_close(status.daily_rate_usd, 1.0);
+        assert_close(status.projected_usd, 7.5);
+        assert_eq!(status.exhausted_on, None);
+    }
+
+    #[test]
+    fn zero_rate_never_runs_out() {
+        let status = super::status(None, "All".to_string(), Some(100.0), &BTreeMap::new(), at("2026-10-15T12:00:00Z"));
+        assert_close(status.daily_rate_usd, 0.0);
+        assert_close(status.projected_usd, 0.0);
+        assert_eq!(status.exhausted_on, None);
+        assert!(!status.projected_overrun);
+
+        // Already over budget: exhausted today
+        let status = super::status(None, "All".to_string(), Some(100.0), &days(&[("2026-10-02", 150.0)]), at("2026-10-15T12:00:00Z"));
+        assert_close(status.daily_rate_usd, 0.0);
+        assert_eq!(status.exhausted_on.as_deref(), Some("2026-10-15"));
+    }
+
+    #[test]
+    fn budgets_must_be_positive() {
+        assert!(Budgets::default().validate().is_ok());
+        let budgets = |global_usd: Option<f64>, provider: f64| Budgets {
+            global_usd,
+            providers: HashMap::from([("openai".to_string(), provider)]),
+        };
+        assert!(budgets(Some(100.0), 20.0).validate().is_ok());
+        assert!(budgets(Some(0.0), 20.0).validate().is_err());
+        assert!(budgets(None, f64::NAN).validate().is_err());
+    }
+}
@@ -0,0 +1,323 @@
+// ============== MANUAL COUNTERS ==============
+//
+// User-defined counters for quotas MeterAI can't fetch ("Midjourney fast
+// hours", "Perplexity Pro searches"). Each one is a manual account
+// ("manual:<slug>", see `accounts`) with its own limit, unit label, reset
+// schedule (see `reset_policy`) and alert thresholds; the built-in "manual"
+// counter stays.
+//
+// Amounts are fractional, in any unit (requests, tokens, USD, minutes,
+// credits), and a use can be weighted by its kind ("opus" counts 5).
+//
+// While the app runs it owns data.json, so `meterai add` and `meterai reset`
+// append their change to a queue file instead, which the app applies on its
+// next scheduler tick.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::accounts;
+use crate::i18n::tr;
+use crate::providers::ProviderRegistry;
+use crate::reset_policy::{self, ResetPolicy};
+use crate::webhooks::Alert;
+use crate::{
+    add_counter_usage, get_data_dir, reset_counter, save_state, AppError, AppState, ProviderConfig, ProviderType,
+    UsageData,
+};
+
+/// Settings of a counter, as edited in the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterSettings {
+    pub name: String,
+    /// Unit label (None: "requests")
+    #[serde(default)]
+    pub unit: Option<String>,
+    pub limit: f64,
+    #[serde(rename = "resetIntervalHours")]
+    pub reset_interval_hours: u32,
+    #[serde(rename = "alertThresholds")]
+    pub alert_thresholds: Vec<u32>,
+    #[serde(rename = "resetPolicy", default)]
+    pub reset_policy: ResetPolicy,
+    /// Amount counted per use of a kind
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+    /// Keep counting past the limit
+    #[serde(rename = "allowOverage", default)]
+    pub allow_overage: bool,
+}
+
+/// A counter with its usage
+#[derive(Debug, Clone, Serialize)]
+pub struct Counter {
+    pub id: String,
+    pub settings: CounterSettings,
+    pub enabled: bool,
+    pub usage: UsageData,
+}
+
+fn check_settings(settings: &CounterSettings) -> Result<(), AppError> {
+    if settings.limit <= 0.0 || settings.reset_interval_hours == 0 {
+        return Err(AppError::ConfigError(tr!("error.invalid_counter")));
+    }
+    if settings.weights.values().any(|weight| *weight <= 0.0) {
+        return Err(AppError::ConfigError(tr!("error.invalid_weight")));
+    }
+    Ok(())
+}
+
+/// `amount` uses of `kind` in the counter's unit (`amount` itself without a kind)
+pub fn weighted_amount(config: &ProviderConfig, amount: f64, kind: Option<&str>) -> Result<f64, AppError> {
+    match kind {
+        None => Ok(amount),
+        Some(kind) => config
+            .weights
+            .get(kind)
+            .map(|weight| amount * weight)
+            .ok_or_else(|| AppError::ConfigError(tr!("error.unknown_weight", kind = kind))),
+    }
+}
+
+/// Every manual counter, the built-in one first
+pub fn list(state: &AppState) -> Vec<Counter> {
+    accounts::list(state)
+        .into_iter()
+        .filter(|account| account.provider_type == ProviderType::Manual)
+        .filter_map(|account| {
+            let provider = state.providers.get(&account.id)?;
+            let config = &provider.config;
+            Some(Counter {
+                id: account.id,
+                settings: CounterSettings {
+                    name: config.name.clone(),
+                    unit: config.unit.clone(),
+                    limit: config.limit,
+                    reset_interval_hours: config.reset_interval_hours,
+                    alert_thresholds: config.alert_thresholds.clone(),
+                    reset_policy: config.reset_policy.clone(),
+                    weights: config.weights.clone(),
+                    allow_overage: config.allow_overage,
+                },
+                enabled: config.enabled,
+                usage: provider.usage.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Create a counter, starting a fresh period now, and return its ID
+pub fn create(
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+    settings: CounterSettings,
+) -> Result<String, AppError> {
+    check_settings(&settings)?;
+    settings.reset_policy.validate(&state.lock().unwrap())?;
+    let id = accounts::add(state, registry, &ProviderType::Manual, &settings.name)?;
+    let policy = settings.reset_policy.clone();
+    update(state, &id, settings)?;
+
+    let mut state = state.lock().unwrap();
+    restart_period(&mut state, &id, &policy);
+    save_state(&state);
+    Ok(id)
+}
+
+/// Start the period of a counter over under `policy`: a rolling window that
+/// is already in use runs from now, other schedules move to their next reset
+fn restart_period(state: &mut AppState, provider_id: &str, policy: &ResetPolicy) {
+    let Some(provider) = state.providers.get(provider_id) else {
+        return;
+    };
+    let now = Utc::now().timestamp();
+    let next_reset = match policy.rolling_hours() {
+        Some(hours) if provider.usage.used > 0.0 => now + hours as i64 * 3600,
+        _ => policy.next_reset(provider.config.reset_interval_hours, state, now),
+    };
+    if let Some(provider) = state.providers.get_mut(provider_id) {
+        provider.usage.reset_time = next_reset;
+    }
+}
+
+/// Apply new settings to a counter. The current period follows a new reset
+/// schedule right away, and ends earlier when the reset interval is shortened.
+pub fn update(state: &Mutex<AppState>, provider_id: &str, settings: CounterSettings) -> Result<UsageData, AppError> {
+    check_settings(&settings)?;
+    let name = accounts::check_name(&settings.name)?;
+
+    let mut state = state.lock().unwrap();
+    settings.reset_policy.validate(&state)?;
+    let provider = state
+        .providers
+        .get_mut(provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    if provider.config.provider_type != ProviderType::Manual {
+        return Err(AppError::ConfigError(tr!(
+            "error.not_a_counter",
+            provider = provider.config.name
+        )));
+    }
+
+    let config = &mut provider.config;
+    config.name = name.clone();
+    config.unit = settings.unit.filter(|unit| !unit.trim().is_empty());
+    config.limit = settings.limit;
+    config.reset_interval_hours = settings.reset_interval_hours;
+    config.alert_thresholds = settings.alert_thresholds;
+    config.weights = settings.weights;
+    config.allow_overage = settings.allow_overage;
+    let policy_changed = config.reset_policy != settings.reset_policy;
+    config.reset_policy = settings.reset_policy.clone();
+
+    let usage = &mut provider.usage;
+    usage.provider_name = name;
+    usage.limit = settings.limit;
+    if !settings.allow_overage {
+        usage.used = usage.used.min(usage.limit);
+    }
+    usage.refresh_percent();
+    if settings.reset_policy == ResetPolicy::Interval && usage.reset_time != reset_policy::IDLE {
+        let next_reset = Utc::now().timestamp() + settings.reset_interval_hours as i64 * 3600;
+        usage.reset_time = usage.reset_time.min(next_reset);
+    }
+    // Thresholds are checked again against the new limit
+    let percent = usage.percent;
+    provider.notified_thresholds.retain(|t| *t <= percent);
+
+    if policy_changed {
+        restart_period(&mut state, provider_id, &settings.reset_policy);
+    }
+    let usage = state.providers[provider_id].usage.clone();
+    save_state(&state);
+    Ok(usage)
+}
+
+/// Set the reset schedule of any manual counter, the built-in one included
+pub fn set_reset_policy(state: &Mutex<AppState>, provider_id: &str, policy: ResetPolicy) -> Result<UsageData, AppError> {
+    let mut state = state.lock().unwrap();
+    policy.validate(&state)?;
+    let provider = state
+        .providers
+        .get_mut(provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    if provider.config.provider_type != ProviderType::Manual {
+        return Err(AppError::ConfigError(tr!(
+            "error.not_a_counter",
+            provider = provider.config.name
+        )));
+    }
+    provider.config.reset_policy = policy.clone();
+    restart_period(&mut state, provider_id, &policy);
+
+    let usage = state.providers[provider_id].usage.clone();
+    save_state(&state);
+    Ok(usage)
+}
+
+/// Delete an added counter (the built-in one can't be removed)
+pub fn delete(state: &Mutex<AppState>, provider_id: &str) -> Result<(), AppError> {
+    let is_counter = state
+        .lock()
+        .unwrap()
+        .providers
+        .get(provider_id)
+        .map(|p| p.config.provider_type == ProviderType::Manual);
+    if is_counter == Some(false) {
+        return Err(AppError::ConfigError(tr!(
+            "error.not_a_counter",
+            provider = provider_id
+        )));
+    }
+    accounts::remove(state, provider_id)
+}
+
+// ============== CHANGES QUEUED BY THE CLI ==============
+
+/// Counter change made by `meterai` while the app runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum QueuedChange {
+    /// Amount already weighted by its kind
+    Add { provider_id: String, amount: f64 },
+    Reset { provider_id: String },
+}
+
+fn queue_path() -> PathBuf {
+    get_data_dir().join("counter-queue.jsonl")
+}
+
+/// Queue a change for the running app
+pub fn queue(change: &QueuedChange) -> io::Result<()> {
+    append_to(&queue_path(), change)
+}
+
+fn append_to(path: &Path, change: &QueuedChange) -> io::Result<()> {
+    let mut line = serde_json::to_string(change)?;
+    line.push('\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Take the queued changes, oldest first
+pub fn take_queued() -> Vec<QueuedChange> {
+    take_from(&queue_path())
+}
+
+/// The queue is moved aside before it is read, so a change queued meanwhile
+/// starts a new file and waits for the next call
+fn take_from(path: &Path) -> Vec<QueuedChange> {
+    let taken = path.with_extension("taken");
+    if fs::rename(path, &taken).is_err() {
+        return vec![];
+    }
+    let content = fs::read_to_string(&taken).unwrap_or_default();
+    fs::remove_file(&taken).ok();
+    content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+/// Apply a queued change. Returns the counter's new usage and the alerts to
+/// raise once the state lock is released.
+pub fn apply_queued(state: &mut AppState, change: &QueuedChange) -> Option<(String, UsageData, Vec<Alert>)> {
+    match change {
+        QueuedChange::Add { provider_id, amount } => add_counter_usage(state, provider_id, *amount)
+            .map(|(usage, alerts)| (provider_id.clone(), usage, alerts)),
+        QueuedChange::Reset { provider_id } => {
+            reset_counter(state, provider_id).map(|usage| (provider_id.clone(), usage, vec![]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_keeps_order_and_empties_on_take() {
+        let path = std::env::temp_dir().join(format!("meterai-queue-{}.jsonl", std::process::id()));
+        let add = QueuedChange::Add {
+            provider_id: "manual:midjourney".to_string(),
+            amount: 2.5,
+        };
+        let reset = QueuedChange::Reset {
+            provider_id: "manual".to_string(),
+        };
+        append_to(&path, &add).unwrap();
+        append_to(&path, &reset).unwrap();
+
+        assert_eq!(take_from(&path), vec![add.clone(), reset]);
+        assert!(take_from(&path).is_empty());
+
+        append_to(&path, &add).unwrap();
+        assert_eq!(take_from(&path), vec![add]);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,203 @@
+// ============== CREDENTIALS WATCHER ==============
+//
+// Watches the Claude Code credentials files of every Claude account (for the
+// built-in one, the auto-detected paths and the custom one) so a token
+// rewritten by Claude Code is noticed right away: the
+// change is logged in the token history, copied to internal storage when
+// `auto_sync_token` is on, and announced to the UI as `credentials-changed`.
+//
+// The parent directories are watched rather than the files, which are often
+// replaced by a rename and may not exist yet. When a parent directory is
+// missing too (Claude Code not run yet), its nearest existing ancestor is
+// watched instead, and the watch is rebuilt once the directory shows up.
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::mpsc;
+
+use meter_ai::i18n::tr;
+use meter_ai::token_store::{self, TokenAccount, TokenChangeEntry};
+use meter_ai::{notify_error, AppState, ProviderType};
+
+/// Quiet time that ends a burst of events (truncate + write, rename, ...)
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Payload of `credentials-changed`
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialsChange {
+    pub provider_id: String,
+    pub entry: TokenChangeEntry,
+    /// Whether the new token was copied to internal storage
+    pub synced: bool,
+    pub sync_error: Option<String>,
+}
+
+/// Dropping the watcher ends the task of the previous `restart`
+static WATCHER: Mutex<Option<RecommendedWatcher>> = Mutex::new(None);
+
+/// Sent by the watcher callback
+enum Signal {
+    /// A credentials file of this provider changed
+    Changed(String),
+    /// A missing directory on the way to a credentials file was created
+    Rewatch,
+}
+
+/// Token accounts of the Claude providers
+fn claude_accounts(app: &AppHandle) -> Vec<TokenAccount> {
+    let state = app.state::<Mutex<AppState>>();
+    let state = state.lock().unwrap();
+    state
+        .providers
+        .iter()
+        .filter(|(_, p)| p.config.provider_type == ProviderType::Anthropic)
+        .map(|(id, _)| TokenAccount::new(&state, id))
+        .collect()
+}
+
+/// (Re)watch the credential paths of the current settings and accounts
+pub fn restart(app: &AppHandle) {
+    let last_hashes = claude_accounts(app)
+        .iter()
+        .map(|account| (account.provider_id.clone(), token_store::source_token_hash(account)))
+        .collect();
+    watch(app, last_hashes);
+}
+
+/// The parent directory of `file`, or its nearest existing ancestor
+fn watched_dir(file: &Path) -> Option<PathBuf> {
+    file.ancestors().skip(1).find(|dir| dir.is_dir()).map(PathBuf::from)
+}
+
+/// Watch the credential paths, comparing the next tokens with `last_hashes`
+fn watch(app: &AppHandle, last_hashes: HashMap<String, Option<String>>) {
+    // (file, provider ID)
+    let files: Vec<(PathBuf, String)> = claude_accounts(app)
+        .into_iter()
+        .flat_map(|account| {
+            account
+                .source_paths()
+                .into_iter()
+                .map(move |path| (path, account.provider_id.clone()))
+        })
+        .collect();
+
+    let dirs: BTreeSet<PathBuf> = files.iter().filter_map(|(file, _)| watched_dir(file)).collect();
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let watched = files.clone();
+    let watched_dirs = dirs.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            for path in &event.paths {
+                for (file, provider_id) in &watched {
+                    if path == file {
+                        sender.send(Signal::Changed(provider_id.clone())).ok();
+                    } else if file.starts_with(path) && !watched_dirs.contains(path) {
+                        sender.send(Signal::Rewatch).ok();
+                    }
+                }
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            notify_error(&tr!("error.watcher_start", detail = e));
+            *WATCHER.lock().unwrap() = None;
+            return;
+        }
+    };
+
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive).ok();
+    }
+
+    *WATCHER.lock().unwrap() = Some(watcher);
+    tauri::async_runtime::spawn(run(app.clone(), receiver, last_hashes));
+}
+
+fn record(signal: Signal, changed: &mut BTreeSet<String>, rewatch: &mut bool) {
+    match signal {
+        Signal::Changed(provider_id) => {
+            changed.insert(provider_id);
+        }
+        Signal::Rewatch => *rewatch = true,
+    }
+}
+
+async fn run(
+    app: AppHandle,
+    mut receiver: mpsc::UnboundedReceiver<Signal>,
+    mut last_hashes: HashMap<String, Option<String>>,
+) {
+    while let Some(signal) = receiver.recv().await {
+        let mut changed = BTreeSet::new();
+        let mut rewatch = false;
+        record(signal, &mut changed, &mut rewatch);
+        // Wait for the write to finish
+        loop {
+            tokio::time::sleep(DEBOUNCE).await;
+            let mut more = false;
+            while let Ok(signal) = receiver.try_recv() {
+                record(signal, &mut changed, &mut rewatch);
+                more = true;
+            }
+            if !more {
+                break;
+            }
+        }
+        if rewatch {
+            // The files may have been written before their directory was watched
+            changed.extend(last_hashes.keys().cloned());
+        }
+
+        for provider_id in changed {
+            let (account, auto_sync) = {
+                let state = app.state::<Mutex<AppState>>();
+                let state = state.lock().unwrap();
+                (TokenAccount::new(&state, &provider_id), state.settings.auto_sync_token)
+            };
+
+            // Claude Code also rewrites the file without changing the token
+            let hash = token_store::source_token_hash(&account);
+            let last_hash = last_hashes.entry(provider_id.clone()).or_default();
+            if hash.is_none() || hash == *last_hash {
+                continue;
+            }
+            *last_hash = hash;
+
+            let entry = token_store::check_token_change(&account);
+            let (synced, sync_error) = if auto_sync && entry.changed {
+                match token_store::copy_token_to_internal(&account) {
+                    Ok(_) => (true, None),
+                    Err(e) => (false, Some(e)),
+                }
+            } else {
+                (false, None)
+            };
+
+            app.emit_all(
+                "credentials-changed",
+                CredentialsChange {
+                    provider_id,
+                    entry,
+                    synced,
+                    sync_error,
+                },
+            )
+            .ok();
+        }
+
+        if rewatch {
+            // Ends this task along with the current watcher
+            watch(&app, last_hashes);
+            return;
+        }
+    }
+}
@@ -0,0 +1,372 @@
+// ============== BURN-RATE FORECASTS ==============
+//
+// Whether a window will run out before it resets. The samples of the current
+// period (usage history since the last drop in percentage, plus the current
+// value) give a consumption rate in percent per hour, smoothed with an
+// exponentially weighted moving average whose time constant is a fifth of
+// the window (one hour for windows of unknown length). At that rate the
+// forecast projects when the window hits 100% and where it ends at reset.
+//
+// With `AppSettings.forecast_alerts`, an alert is raised once per period when
+// the projected exhaustion comes before the reset.
+
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+
+use crate::history::{self, UsageSample};
+use crate::i18n::{self, tr};
+use crate::providers::WindowUsage;
+use crate::reset_policy;
+use crate::webhooks::{Alert, AlertEvent};
+use crate::{is_same_reset, AppState, ProviderType, ProviderUsage};
+
+/// History read for a window of unknown length
+const DEFAULT_LOOKBACK_HOURS: f64 = 7.0 * 24.0;
+/// Longest history read (monthly counters)
+const MAX_LOOKBACK_HOURS: f64 = 31.0 * 24.0;
+/// Smoothing time constant of a window of unknown length
+const DEFAULT_SMOOTHING_HOURS: f64 = 1.0;
+/// Shortest span of samples giving a rate
+const MIN_SPAN_SECS: i64 = 15 * 60;
+/// Drop in percentage read as a reset rather than rounding noise
+const RESET_DROP_PERCENT: f64 = 1.0;
+
+/// Window ID of manual counters in the history
+const COUNTER_WINDOW: &str = "quota";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Forecast {
+    pub provider_id: String,
+    pub window: String,
+    pub percent: f64,
+    /// Smoothed consumption in percent per hour (None with too few samples)
+    pub rate_per_hour: Option<f64>,
+    pub resets_at: Option<i64>,
+    /// When the window reaches 100% at this rate (now when it already has)
+    pub exhausts_at: Option<i64>,
+    pub projected_percent_at_reset: Option<f64>,
+    pub exhausts_before_reset: bool,
+    /// Samples of the current period used for the rate
+    pub samples: usize,
+}
+
+/// A window to forecast: (ID, current percent, reset, length in hours)
+struct WindowState {
+    id: String,
+    percent: f64,
+    resets_at: Option<i64>,
+    hours: Option<f64>,
+}
+
+fn window_hours(window_id: &str) -> Option<f64> {
+    match window_id {
+        "five_hour" => Some(5.0),
+        id if id.starts_with("seven_day") => Some(7.0 * 24.0),
+        _ => None,
+    }
+}
+
+fn parse_reset(window: &WindowUsage) -> Option<i64> {
+    DateTime::parse_from_rfc3339(window.resets_at.as_deref()?)
+        .ok()
+        .map(|at| at.timestamp())
+}
+
+/// Windows carrying a percentage: fetched windows, or the counter itself
+fn windows(provider: &ProviderUsage) -> Vec<WindowState> {
+    if provider.config.provider_type == ProviderType::Manual {
+        let config = &provider.config;
+        let hours = config
+            .reset_policy
+            .rolling_hours()
+            .unwrap_or(config.reset_interval_hours);
+        return vec![WindowState {
+            id: COUNTER_WINDOW.to_string(),
+            percent: provider.usage.percent as f64,
+            resets_at: Some(provider.usage.reset_time).filter(|at| *at != reset_policy::IDLE),
+            hours: Some(hours as f64),
+        }];
+    }
+    provider
+        .last_report
+        .iter()
+        .flat_map(|report| report.windows.iter())
+        .filter_map(|w| {
+            Some(WindowState {
+                id: w.id.clone(),
+                percent: w.percent?,
+                resets_at: parse_reset(w),
+                hours: window_hours(&w.id),
+            })
+        })
+        .collect()
+}
+
+/// Exponentially weighted rate (percent per hour) of successive points
+fn smoothed_rate(points: &[(i64, f64)], smoothing_hours: f64) -> Option<f64> {
+    let mut rate: Option<f64> = None;
+    for pair in points.windows(2) {
+        let ((t0, p0), (t1, p1)) = (pair[0], pair[1]);
+        let hours = (t1 - t0) as f64 / 3600.0;
+        if hours <= 0.0 {
+            continue;
+        }
+        let current = ((p1 - p0) / hours).max(0.0);
+        let alpha = 1.0 - (-hours / smoothing_hours).exp();
+        rate = Some(match rate {
+            Some(previous) => previous + alpha * (current - previous),
+            None => current,
+        });
+    }
+    rate
+}
+
+fn forecast_window(provider_id: &str, window: &WindowState, samples: &[UsageSample], now: i64) -> Forecast {
+    let lookback = window.hours.unwrap_or(DEFAULT_LOOKBACK_HOURS).min(MAX_LOOKBACK_HOURS);
+    let since = now - (lookback * 3600.0) as i64;
+    let mut points: Vec<(i64, f64)> = samples
+        .iter()
+        .filter(|s| s.provider == provider_id && s.window == window.id && s.timestamp >= since)
+        .filter_map(|s| s.percent.map(|p| (s.timestamp, p)))
+        .collect();
+    points.sort_by_key(|(t, _)| *t);
+    if points.last().is_none_or(|(t, _)| *t < now) {
+        points.push((now, window.percent));
+    }
+    // Keep the current period: the points after the last reset
+    if let Some(start) = (1..points.len())
+        .rev()
+        .find(|i| points[*i].1 < points[i - 1].1 - RESET_DROP_PERCENT)
+    {
+        points.drain(..start);
+    }
+
+    let span = points.last().map_or(0, |(t, _)| *t) - points.first().map_or(0, |(t, _)| *t);
+    let smoothing = window
+        .hours
+        .map_or(DEFAULT_SMOOTHING_HOURS, |hours| (hours / 5.0).clamp(0.25, 24.0));
+    let rate = (span >= MIN_SPAN_SECS)
+        .then(|| smoothed_rate(&points, smoothing))
+        .flatten();
+
+    let exhausts_at = match rate {
+        _ if window.percent >= 100.0 => Some(now),
+        Some(rate) if rate > 0.0 => Some(now + ((100.0 - window.percent) / rate * 3600.0) as i64),
+        _ => None,
+    };
+    let projected_percent_at_reset = rate
+        .zip(window.resets_at)
+        .map(|(rate, reset)| window.percent + rate * (reset - now).max(0) as f64 / 3600.0);
+
+    Forecast {
+        provider_id: provider_id.to_string(),
+        window: window.id.clone(),
+        percent: window.percent,
+        rate_per_hour: rate,
+        resets_at: window.resets_at,
+        exhausts_at,
+        projected_percent_at_reset,
+        exhausts_before_reset: exhausts_at.zip(window.resets_at).is_some_and(|(at, reset)| at < reset),
+        samples: points.len(),
+    }
+}
+
+fn lookback_start(now: i64) -> i64 {
+    now - (MAX_LOOKBACK_HOURS * 3600.0) as i64
+}
+
+/// Forecasts of every window of `provider_id`
+pub fn forecast(state: &AppState, provider_id: &str, now: i64) -> Vec<Forecast> {
+    let Some(provider) = state.providers.get(provider_id) else {
+        return vec![];
+    };
+    let samples = history::query(Some(provider_id), None, lookback_start(now), now);
+    windows(provider)
+        .iter()
+        .map(|window| forecast_window(provider_id, window, &samples, now))
+        .collect()
+}
+
+/// Forecasts of every enabled provider, by name
+pub fn forecast_all(state: &AppState, now: i64) -> Vec<Forecast> {
+    let samples = history::query(None, None, lookback_start(now), now);
+    let mut providers: Vec<(&String, &ProviderUsage)> =
+        state.providers.iter().filter(|(_, p)| p.config.enabled).collect();
+    providers.sort_by(|a, b| a.1.config.name.cmp(&b.1.config.name));
+    providers
+        .into_iter()
+        .flat_map(|(id, provider)| {
+            windows(provider)
+                .into_iter()
+                .map(|window| forecast_window(id, &window, &samples, now))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn format_time(timestamp: i64) -> String {
+    let local = DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local);
+    if local.date_naive() == Local::now().date_naive() {
+        local.format("%H:%M").to_string()
+    } else {
+        local.format("%d/%m %H:%M").to_string()
+    }
+}
+
+/// Alerts to raise, once per period, on the windows of `provider_id`
+/// projected to run out before their reset (when `forecast_alerts` is on)
+pub(crate) fn check_alerts(state: &mut AppState, provider_id: &str, now: i64) -> Vec<Alert> {
+    if !state.settings.forecast_alerts {
+        return vec![];
+    }
+    let forecasts = forecast(state, provider_id, now);
+    let Some(provider) = state.providers.get_mut(provider_id) else {
+        return vec![];
+    };
+
+    let mut alerts = Vec::new();
+
+    for forecast in forecasts {
+        let resets_at = forecast
+            .resets_at
+            .and_then(|at| DateTime::<Utc>::from_timestamp(at, 0))
+            .map(|at| at.to_rfc3339());
+        let alert = provider.notified_windows.entry(forecast.window.clone()).or_default();
+        if !is_same_reset(alert.resets_at.as_deref(), resets_at.as_deref()) {
+            alert.resets_at = resets_at.clone();
+            alert.notified.clear();
+            alert.forecast_warned = false;
+        }
+        // Already exhausted windows are covered by the threshold alerts
+        if !forecast.exhausts_before_reset || forecast.percent >= 100.0 || alert.forecast_warned {
+            continue;
+        }
+        let (Some(exhausts_at), Some(reset)) = (forecast.exhausts_at, forecast.resets_at) else {
+            continue;
+        };
+        alert.forecast_warned = true;
+
+        let label = if forecast.window == COUNTER_WINDOW {
+            provider.config.name.clone()
+        } else {
+            i18n::window_label(&forecast.window)
+        };
+        alerts.push(Alert {
+            event: AlertEvent::Forecast,
+            provider_id: provider_id.to_string(),
+            provider_name: provider.config.name.clone(),
+            window: (forecast.window != COUNTER_WINDOW).then(|| forecast.window.clone()),
+            threshold: None,
+            percent: Some(forecast.percent),
+            resets_at,
+            title: tr!("notify.forecast.title", provider = provider.config.name, window = label),
+            message: tr!(
+                "notify.forecast.body",
+                window = label,
+                at = format_time(exhausts_at),
+                reset = format_time(reset)
+            ),
+            timestamp: now,
+        });
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_780_000_000;
+    const HOUR: i64 = 3600;
+
+    fn window(percent: f64, resets_in: i64) -> WindowState {
+        WindowState {
+            id: "five_hour".to_string(),
+            percent,
+            resets_at: Some(NOW + resets_in),
+            hours: Some(5.0),
+        }
+    }
+
+    fn samples(points: &[(i64, f64)]) -> Vec<UsageSample> {
+        points
+            .iter()
+            .map(|(ago, percent)| UsageSample {
+                timestamp: NOW - ago,
+                provider: "anthropic".to_string(),
+                window: "five_hour".to_string(),
+                percent: Some(*percent),
+                used: None,
+                limit: None,
+                cost_usd: None,
+            })
+            .collect()
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.unwrap();
+        assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn smoothed_rate_of_steady_use() {
+        assert_eq!(smoothed_rate(&[], 1.0), None);
+        assert_eq!(smoothed_rate(&[(0, 10.0)], 1.0), None);
+        assert_close(smoothed_rate(&[(0, 10.0), (HOUR, 20.0), (2 * HOUR, 30.0)], 1.0), 10.0);
+        // Points at the same time are skipped, a decrease counts as no use
+        assert_close(smoothed_rate(&[(0, 10.0), (0, 12.0), (HOUR, 22.0)], 1.0), 10.0);
+        assert_close(smoothed_rate(&[(0, 30.0), (HOUR, 20.0)], 1.0), 0.0);
+    }
+
+    #[test]
+    fn smoothed_rate_leans_on_recent_use() {
+        let rate = smoothed_rate(&[(0, 0.0), (HOUR, 10.0), (2 * HOUR, 40.0)], 1.0).unwrap();
+        assert!(rate > 20.0 && rate < 30.0);
+    }
+
+    #[test]
+    fn samples_before_a_reset_are_dropped() {
+        // 60% before the reset an hour ago, then 20% per hour
+        let samples = samples(&[(3 * HOUR, 40.0), (2 * HOUR, 60.0), (HOUR, 2.0), (HOUR / 2, 12.0)]);
+        let forecast = forecast_window("anthropic", &window(22.0, 3 * HOUR), &samples, NOW);
+
+        assert_eq!(forecast.samples, 3);
+        assert_close(forecast.rate_per_hour, 20.0);
+        assert_close(forecast.projected_percent_at_reset, 82.0);
+        assert!(!forecast.exhausts_before_reset);
+    }
+
+    #[test]
+    fn rate_needs_min_span() {
+        let short = samples(&[(MIN_SPAN_SECS - 60, 10.0)]);
+        let forecast = forecast_window("anthropic", &window(20.0, HOUR), &short, NOW);
+        assert_eq!(forecast.rate_per_hour, None);
+        assert_eq!(forecast.exhausts_at, None);
+        assert!(!forecast.exhausts_before_reset);
+
+        let long = samples(&[(MIN_SPAN_SECS, 10.0)]);
+        let forecast = forecast_window("anthropic", &window(20.0, HOUR), &long, NOW);
+        assert_close(forecast.rate_per_hour, 40.0);
+    }
+
+    #[test]
+    fn exhaustion_before_reset() {
+        // 20% per hour from 50%: full in 2.5 hours
+        let samples = samples(&[(2 * HOUR, 10.0), (HOUR, 30.0)]);
+        let forecast = forecast_window("anthropic", &window(50.0, 3 * HOUR), &samples, NOW);
+        assert_eq!(forecast.exhausts_at, Some(NOW + 5 * HOUR / 2));
+        assert_close(forecast.projected_percent_at_reset, 110.0);
+        assert!(forecast.exhausts_before_reset);
+
+        let forecast = forecast_window("anthropic", &window(50.0, 2 * HOUR), &samples, NOW);
+        assert!(!forecast.exhausts_before_reset);
+
+        // Other providers' samples are ignored; a full window is exhausted now
+        let forecast = forecast_window("openai", &window(100.0, HOUR), &samples, NOW);
+        assert_eq!(forecast.rate_per_hour, None);
+        assert_eq!(forecast.exhausts_at, Some(NOW));
+        assert!(forecast.exhausts_before_reset);
+    }
+}
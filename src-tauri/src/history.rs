@@ -0,0 +1,473 @@
+// ============== USAGE HISTORY ==============
+//
+// Append-only JSONL log of every polled sample (`usage_history.jsonl` in the
+// data dir). One line per provider window; old lines are dropped by
+// `apply_retention` according to `AppSettings.history_retention_days`, at
+// startup and once a day from the scheduler.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::providers::claude_code::ClaudeCodeUsageResult;
+use crate::providers::openai::OpenAIUsageResult;
+use crate::providers::UsageReport;
+use crate::{get_data_dir, AppError, UsageData};
+
+/// Default retention when the setting is absent
+pub const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+/// Serializes writers (polling tasks, commands) on the log file
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// One polled measurement of a provider window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageSample {
+    /// Unix timestamp (UTC, seconds)
+    pub timestamp: i64,
+    /// Provider ID in `AppState.providers`
+    pub provider: String,
+    /// Window ID ("five_hour", "billing_period", "quota", ...)
+    pub window: String,
+    pub percent: Option<f64>,
+    pub used: Option<f64>,
+    pub limit: Option<f64>,
+    pub cost_usd: Option<f64>,
+}
+
+impl UsageSample {
+    fn new(provider: &str, window: &str) -> Self {
+        Self {
+            timestamp: Utc::now().timestamp(),
+            provider: provider.to_string(),
+            window: window.to_string(),
+            percent: None,
+            used: None,
+            limit: None,
+            cost_usd: None,
+        }
+    }
+
+    /// One sample per window of a fetch report
+    pub fn from_report(report: &UsageReport) -> Vec<Self> {
+        report
+            .windows
+            .iter()
+            .map(|w| Self {
+                timestamp: report.fetched_at,
+                provider: report.provider_id.clone(),
+                window: w.id.clone(),
+                percent: w.percent,
+                used: w.used,
+                limit: w.limit,
+                // Spend is attached to the billing window only
+                cost_usd: if w.id == "billing_period" {
+                    report.cost_usd
+                } else {
+                    None
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBucket {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+impl HistoryBucket {
+    /// Start of the bucket containing `timestamp` (UTC, weeks start on Monday)
+    pub fn start_of(&self, timestamp: i64) -> i64 {
+        match self {
+            HistoryBucket::Hourly => timestamp - timestamp.rem_euclid(3600),
+            HistoryBucket::Daily => timestamp - timestamp.rem_euclid(86_400),
+            HistoryBucket::Weekly => {
+                // 1970-01-01 was a Thursday: shift by 3 days to start on Monday
+                let days = timestamp.div_euclid(86_400);
+                (days - (days + 3).rem_euclid(7)) * 86_400
+            }
+        }
+    }
+}
+
+/// Aggregated samples of one provider window over one bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageAggregate {
+    /// Bucket start (unix, UTC)
+    pub start: i64,
+    /// Bucket start (RFC 3339) for display
+    pub start_iso: String,
+    pub provider: String,
+    pub window: String,
+    pub samples: u32,
+    pub avg_percent: Option<f64>,
+    pub min_percent: Option<f64>,
+    pub max_percent: Option<f64>,
+    pub last_percent: Option<f64>,
+    pub max_used: Option<f64>,
+    /// Latest spend figure in the bucket (provider spend is cumulative per period)
+    pub last_cost_usd: Option<f64>,
+}
+
+fn get_history_path() -> PathBuf {
+    get_data_dir().join("usage_history.jsonl")
+}
+
+/// Append samples to the log
+pub fn record(samples: &[UsageSample]) -> Result<(), AppError> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    append(&get_history_path(), samples)
+}
+
+fn append(path: &Path, samples: &[UsageSample]) -> Result<(), AppError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+    let mut lines = String::new();
+    for sample in samples {
+        let line = serde_json::to_string(sample).map_err(|e| AppError::ConfigError(e.to_string()))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())
+        .map_err(|e| AppError::ConfigError(e.to_string()))
+}
+
+/// Append the windows of a fetch report to the log
+pub fn record_report(report: &UsageReport) {
+    record(&UsageSample::from_report(report)).ok();
+}
+
+/// Record the counter of a manual provider
+pub fn record_manual(provider_id: &str, usage: &UsageData) {
+    let sample = UsageSample {
+        percent: Some(usage.percent as f64),
+        used: Some(usage.used),
+        limit: Some(usage.limit),
+        ..UsageSample::new(provider_id, "quota")
+    };
+    record(&[sample]).ok();
+}
+
+/// Record a legacy Claude Code fetch (subscription windows of "anthropic")
+pub fn record_claude_code(result: &ClaudeCodeUsageResult) {
+    if !result.success {
+        return;
+    }
+    let samples = [
+        ("five_hour", result.five_hour_percent),
+        ("seven_day", result.seven_day_percent),
+    ]
+    .into_iter()
+    .filter(|(_, percent)| percent.is_some())
+    .map(|(window, percent)| UsageSample {
+        percent,
+        ..UsageSample::new("anthropic", window)
+    })
+    .collect::<Vec<_>>();
+    record(&samples).ok();
+}
+
+/// Record a legacy OpenAI fetch (billing period of "openai")
+pub fn record_openai(result: &OpenAIUsageResult) {
+    if !result.success || !result.usage_available {
+        return;
+    }
+    let sample = UsageSample {
+        percent: result.percent,
+        used: result.usage_usd,
+        limit: result.limit_usd,
+        cost_usd: result.usage_usd,
+        ..UsageSample::new("openai", "billing_period")
+    };
+    record(&[sample]).ok();
+}
+
+fn read_all(path: &Path) -> Vec<UsageSample> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return vec![],
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        // Skip lines truncated by a crash instead of failing the whole query
+        .filter_map(|line| serde_json::from_str::<UsageSample>(&line).ok())
+        .collect()
+}
+
+/// Samples in `[from, to]`, optionally filtered by provider and window, oldest first
+pub fn query(
+    provider: Option<&str>,
+    window: Option<&str>,
+    from: i64,
+    to: i64,
+) -> Vec<UsageSample> {
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    select(&get_history_path(), provider, window, from, to)
+}
+
+fn select(path: &Path, provider: Option<&str>, window: Option<&str>, from: i64, to: i64) -> Vec<UsageSample> {
+    let mut samples: Vec<UsageSample> = read_all(path)
+        .into_iter()
+        .filter(|s| s.timestamp >= from && s.timestamp <= to)
+        .filter(|s| provider.is_none_or(|p| s.provider == p))
+        .filter(|s| window.is_none_or(|w| s.window == w))
+        .collect();
+    samples.sort_by_key(|s| s.timestamp);
+    samples
+}
+
+/// Per-bucket aggregates of the samples matching the filters
+pub fn aggregate(
+    provider: Option<&str>,
+    window: Option<&str>,
+    bucket: HistoryBucket,
+    from: i64,
+    to: i64,
+) -> Vec<UsageAggregate> {
+    group(query(provider, window, from, to), bucket)
+}
+
+fn group(samples: Vec<UsageSample>, bucket: HistoryBucket) -> Vec<UsageAggregate> {
+    let mut groups: BTreeMap<(String, String, i64), Vec<UsageSample>> = BTreeMap::new();
+    for sample in samples {
+        let key = (
+            sample.provider.clone(),
+            sample.window.clone(),
+            bucket.start_of(sample.timestamp),
+        );
+        groups.entry(key).or_default().push(sample);
+    }
+
+    let mut aggregates: Vec<UsageAggregate> = groups
+        .into_iter()
+        .map(|((provider, window, start), samples)| {
+            let percents: Vec<f64> = samples.iter().filter_map(|s| s.percent).collect();
+            let avg_percent = if percents.is_empty() {
+                None
+            } else {
+                Some(percents.iter().sum::<f64>() / percents.len() as f64)
+            };
+            UsageAggregate {
+                start,
+                start_iso: DateTime::<Utc>::from_timestamp(start, 0)
+                    .map(|dt| dt.to_rfc3339())
+                    .unwrap_or_default(),
+                provider,
+                window,
+                samples: samples.len() as u32,
+                avg_percent,
+                min_percent: percents.iter().copied().reduce(f64::min),
+                max_percent: percents.iter().copied().reduce(f64::max),
+                last_percent: percents.last().copied(),
+                max_used: samples.iter().filter_map(|s| s.used).reduce(f64::max),
+                last_cost_usd: samples.iter().rev().find_map(|s| s.cost_usd),
+            }
+        })
+        .collect();
+    aggregates.sort_by_key(|a| a.start);
+    aggregates
+}
+
+/// Drop samples older than `retention_days` (0 keeps everything).
+/// Returns the number of samples removed.
+pub fn apply_retention(retention_days: u32) -> Result<usize, AppError> {
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let cutoff = Utc::now().timestamp() - retention_days as i64 * 86_400;
+    prune(&get_history_path(), cutoff)
+}
+
+/// Drop the samples of `path` older than `cutoff`
+fn prune(path: &Path, cutoff: i64) -> Result<usize, AppError> {
+    let samples = read_all(path);
+    let total = samples.len();
+    let kept: Vec<&UsageSample> = samples.iter().filter(|s| s.timestamp >= cutoff).collect();
+    if kept.len() == total {
+        return Ok(0);
+    }
+
+    // Rewrite through a temp file so a crash never leaves a half-written log
+    let mut content = String::new();
+    for sample in &kept {
+        let line = serde_json::to_string(sample).map_err(|e| AppError::ConfigError(e.to_string()))?;
+        content.push_str(&line);
+        content.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    fs::write(&tmp_path, content).map_err(|e| AppError::ConfigError(e.to_string()))?;
+    fs::rename(&tmp_path, path).map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+    Ok(total - kept.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2025-06-04 (a Wednesday) 13:45:10 UTC
+    const WEDNESDAY: i64 = 1_749_044_710;
+
+    fn sample(timestamp: i64, provider: &str, window: &str, percent: Option<f64>) -> UsageSample {
+        UsageSample {
+            timestamp,
+            percent,
+            ..UsageSample::new(provider, window)
+        }
+    }
+
+    /// History file in the temp dir, removed on drop
+    struct Log(PathBuf);
+
+    impl Log {
+        fn new(name: &str, samples: &[UsageSample]) -> Self {
+            let log = Self(std::env::temp_dir().join(format!("meterai-{}-{}.jsonl", name, std::process::id())));
+            fs::remove_file(&log.0).ok();
+            append(&log.0, samples).unwrap();
+            log
+        }
+    }
+
+    impl Drop for Log {
+        fn drop(&mut self) {
+            fs::remove_file(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn buckets_start_on_the_hour_day_and_monday() {
+        assert_eq!(HistoryBucket::Hourly.start_of(WEDNESDAY), WEDNESDAY - 45 * 60 - 10);
+        assert_eq!(HistoryBucket::Daily.start_of(WEDNESDAY), 1_748_995_200);
+        // Monday 2025-06-02
+        let monday = 1_748_822_400;
+        assert_eq!(HistoryBucket::Weekly.start_of(WEDNESDAY), monday);
+        assert_eq!(HistoryBucket::Weekly.start_of(monday), monday);
+        assert_eq!(HistoryBucket::Weekly.start_of(monday - 1), monday - 7 * 86_400);
+        // Before the epoch too
+        assert_eq!(HistoryBucket::Hourly.start_of(-1), -3600);
+        assert_eq!(HistoryBucket::Weekly.start_of(0), -3 * 86_400);
+    }
+
+    #[test]
+    fn query_filters_and_sorts() {
+        let log = Log::new(
+            "query",
+            &[
+                sample(WEDNESDAY + 60, "anthropic", "five_hour", Some(20.0)),
+                sample(WEDNESDAY, "anthropic", "five_hour", Some(10.0)),
+                sample(WEDNESDAY, "anthropic", "seven_day", Some(5.0)),
+                sample(WEDNESDAY, "openai", "billing_period", None),
+                sample(WEDNESDAY + 7200, "anthropic", "five_hour", Some(30.0)),
+            ],
+        );
+        // A line truncated by a crash is skipped
+        OpenOptions::new().append(true).open(&log.0).unwrap().write_all(b"{\"timestamp\":17").unwrap();
+
+        let times = |samples: Vec<UsageSample>| samples.iter().map(|s| s.timestamp).collect::<Vec<_>>();
+        assert_eq!(select(&log.0, None, None, 0, i64::MAX).len(), 5);
+        assert_eq!(
+            times(select(&log.0, Some("anthropic"), Some("five_hour"), 0, i64::MAX)),
+            [WEDNESDAY, WEDNESDAY + 60, WEDNESDAY + 7200]
+        );
+        // Both bounds are inclusive
+        assert_eq!(
+            times(select(&log.0, Some("anthropic"), Some("five_hour"), WEDNESDAY, WEDNESDAY + 60)),
+            [WEDNESDAY, WEDNESDAY + 60]
+        );
+        assert_eq!(select(&log.0, Some("openai"), None, 0, i64::MAX).len(), 1);
+        assert!(select(&log.0, Some("gemini"), None, 0, i64::MAX).is_empty());
+    }
+
+    #[test]
+    fn aggregates_per_bucket_and_window() {
+        let hour = HistoryBucket::Hourly.start_of(WEDNESDAY);
+        let samples = vec![
+            UsageSample {
+                used: Some(4.0),
+                cost_usd: Some(1.5),
+                ..sample(hour + 60, "openai", "billing_period", Some(10.0))
+            },
+            UsageSample {
+                used: Some(9.0),
+                cost_usd: Some(2.5),
+                ..sample(hour + 120, "openai", "billing_period", Some(40.0))
+            },
+            UsageSample {
+                used: Some(7.0),
+                ..sample(hour + 180, "openai", "billing_period", Some(25.0))
+            },
+            sample(hour + 3600, "openai", "billing_period", None),
+            sample(hour + 90_000, "openai", "billing_period", Some(50.0)),
+            sample(hour + 60, "anthropic", "five_hour", Some(5.0)),
+        ];
+
+        let hourly = group(samples.clone(), HistoryBucket::Hourly);
+        assert_eq!(hourly.len(), 4);
+        let first = hourly.iter().find(|a| a.provider == "openai" && a.start == hour).unwrap();
+        assert_eq!(first.samples, 3);
+        assert_eq!(first.avg_percent, Some(25.0));
+        assert_eq!(first.min_percent, Some(10.0));
+        assert_eq!(first.max_percent, Some(40.0));
+        assert_eq!(first.last_percent, Some(25.0));
+        assert_eq!(first.max_used, Some(9.0));
+        // Latest spend in the bucket, even when the last sample has none
+        assert_eq!(first.last_cost_usd, Some(2.5));
+        assert_eq!(first.start_iso, "2025-06-04T13:00:00+00:00");
+        // A bucket without percentages
+        let empty = hourly.iter().find(|a| a.start == hour + 3600).unwrap();
+        assert_eq!((empty.samples, empty.avg_percent, empty.max_percent), (1, None, None));
+
+        let daily = group(samples.clone(), HistoryBucket::Daily);
+        let starts: Vec<(i64, &str, u32)> = daily.iter().map(|a| (a.start, a.provider.as_str(), a.samples)).collect();
+        let day = HistoryBucket::Daily.start_of(WEDNESDAY);
+        assert!(starts.contains(&(day, "openai", 4)));
+        assert!(starts.contains(&(day, "anthropic", 1)));
+        assert!(starts.contains(&(day + 86_400, "openai", 1)));
+        assert!(daily.windows(2).all(|pair| pair[0].start <= pair[1].start));
+
+        let weekly = group(samples, HistoryBucket::Weekly);
+        let openai = weekly.iter().find(|a| a.provider == "openai").unwrap();
+        assert_eq!(openai.samples, 5);
+        assert_eq!(openai.max_percent, Some(50.0));
+        assert_eq!(openai.last_percent, Some(50.0));
+    }
+
+    #[test]
+    fn retention_drops_old_samples_only() {
+        let log = Log::new(
+            "retention",
+            &[
+                sample(WEDNESDAY - 86_400, "anthropic", "five_hour", Some(10.0)),
+                sample(WEDNESDAY, "anthropic", "five_hour", Some(20.0)),
+                sample(WEDNESDAY + 60, "anthropic", "five_hour", Some(30.0)),
+            ],
+        );
+
+        assert_eq!(prune(&log.0, WEDNESDAY).unwrap(), 1);
+        let kept = read_all(&log.0);
+        assert_eq!(kept.iter().map(|s| s.percent).collect::<Vec<_>>(), [Some(20.0), Some(30.0)]);
+        assert!(!log.0.with_extension("jsonl.tmp").exists());
+
+        // Nothing to drop: the file is left alone
+        assert_eq!(prune(&log.0, WEDNESDAY).unwrap(), 0);
+        assert_eq!(read_all(&log.0).len(), 2);
+        assert_eq!(apply_retention(0).unwrap(), 0);
+    }
+}
@@ -0,0 +1,275 @@
+// ============== HTTP ENDPOINTS ==============
+//
+// Every outgoing request goes through an `Endpoint`, resolved from
+// `AppSettings.endpoints` (base URL override, extra headers, proxy). In mock
+// mode all endpoints point at the built-in mock server instead.
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::i18n::tr;
+use crate::{AppError, AppSettings};
+
+/// Endpoint keys used in `AppSettings.endpoints`
+pub const ANTHROPIC: &str = "anthropic";
+pub const OPENAI: &str = "openai";
+/// OAuth token endpoint of Claude accounts (token refresh)
+pub const ANTHROPIC_OAUTH: &str = "anthropic_oauth";
+/// Outgoing webhooks: only the headers and proxy apply, each target has its own URL
+pub const WEBHOOKS: &str = "webhooks";
+
+pub const ANTHROPIC_DEFAULT_URL: &str = "https://api.anthropic.com";
+pub const OPENAI_DEFAULT_URL: &str = "https://api.openai.com";
+pub const ANTHROPIC_OAUTH_DEFAULT_URL: &str = "https://console.anthropic.com";
+
+/// Per-endpoint overrides (corporate gateways, proxies, ...)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct EndpointConfig {
+    /// Replaces the scheme/host part of the default URL
+    #[serde(rename = "baseUrl", default)]
+    pub base_url: Option<String>,
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Proxy URL (http:// or https://)
+    #[serde(default)]
+    pub proxy: Option<String>,
+}
+
+/// Wait used when a 429 carries no usable Retry-After header
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+/// Base URL of the mock server, set once at startup in mock mode
+static MOCK_BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Route every endpoint to the mock server
+pub fn enable_mock_mode(base_url: String) {
+    MOCK_BASE_URL.set(base_url).ok();
+}
+
+pub fn mock_base_url() -> Option<&'static str> {
+    MOCK_BASE_URL.get().map(|s| s.as_str())
+}
+
+/// A configured HTTP client bound to a base URL
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub base_url: String,
+    client: reqwest::Client,
+}
+
+impl Endpoint {
+    /// Resolve `key` from the settings, falling back to `default_url`
+    pub fn resolve(settings: &AppSettings, key: &str, default_url: &str) -> Result<Self, AppError> {
+        let config = settings.endpoints.get(key).cloned().unwrap_or_default();
+        let client = build_client(&config)?;
+
+        let base_url = match mock_base_url() {
+            Some(mock) => mock.to_string(),
+            None => config
+                .base_url
+                .filter(|url| !url.trim().is_empty())
+                .unwrap_or_else(|| default_url.to_string()),
+        };
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+
+    pub fn anthropic(settings: &AppSettings) -> Result<Self, AppError> {
+        Self::resolve(settings, ANTHROPIC, ANTHROPIC_DEFAULT_URL)
+    }
+
+    pub fn openai(settings: &AppSettings) -> Result<Self, AppError> {
+        Self::resolve(settings, OPENAI, OPENAI_DEFAULT_URL)
+    }
+
+    pub fn anthropic_oauth(settings: &AppSettings) -> Result<Self, AppError> {
+        Self::resolve(settings, ANTHROPIC_OAUTH, ANTHROPIC_OAUTH_DEFAULT_URL)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(self.url(path))
+    }
+
+    pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.post(self.url(path))
+    }
+
+    pub fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client.request(method, self.url(path))
+    }
+}
+
+/// A client with the headers and proxy of endpoint `key`, for requests to
+/// full URLs (webhooks)
+pub fn client(settings: &AppSettings, key: &str) -> Result<reqwest::Client, AppError> {
+    build_client(&settings.endpoints.get(key).cloned().unwrap_or_default())
+}
+
+fn build_client(config: &EndpointConfig) -> Result<reqwest::Client, AppError> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| AppError::ConfigError(tr!("error.invalid_header_name", name = name, detail = e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| AppError::ConfigError(tr!("error.invalid_header_value", name = name, detail = e)))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(proxy) = config.proxy.as_ref().filter(|p| !p.trim().is_empty()) {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| AppError::ConfigError(tr!("error.invalid_proxy", proxy = proxy, detail = e)))?;
+        builder = builder.proxy(proxy);
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::ConfigError(e.to_string()))
+}
+
+/// Turn a 429 response into `AppError::RateLimited`, honouring Retry-After
+/// (either delay-seconds or an HTTP date)
+pub fn check_rate_limit(response: &reqwest::Response) -> Result<(), AppError> {
+    if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value.trim().parse::<u64>().ok().or_else(|| {
+                DateTime::parse_from_rfc2822(value.trim())
+                    .ok()
+                    .map(|at| (at.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64)
+            })
+        })
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+    Err(AppError::RateLimited(retry_after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server;
+
+    fn retry_after(status: u16, value: Option<&str>) -> Option<u64> {
+        let mut response = hyper::Response::builder().status(status);
+        if let Some(value) = value {
+            response = response.header(RETRY_AFTER, value);
+        }
+        let response: reqwest::Response = response.body(String::new()).unwrap().into();
+        match check_rate_limit(&response) {
+            Ok(()) => None,
+            Err(AppError::RateLimited(secs)) => Some(secs),
+            Err(error) => panic!("{:?}", error),
+        }
+    }
+
+    fn http_date(offset_secs: i64) -> String {
+        (Utc::now() + chrono::Duration::seconds(offset_secs))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string()
+    }
+
+    fn settings(key: &str, config: EndpointConfig) -> AppSettings {
+        let mut settings = AppSettings::default();
+        settings.endpoints.insert(key.to_string(), config);
+        settings
+    }
+
+    #[test]
+    fn rate_limits_honour_retry_after() {
+        assert_eq!(retry_after(200, Some("120")), None);
+        assert_eq!(retry_after(503, Some("120")), None);
+
+        assert_eq!(retry_after(429, Some(" 120 ")), Some(120));
+        let wait = retry_after(429, Some(&http_date(90))).unwrap();
+        assert!((88..=90).contains(&wait), "{}", wait);
+        assert_eq!(retry_after(429, Some(&http_date(-90))), Some(0));
+
+        assert_eq!(retry_after(429, None), Some(DEFAULT_RETRY_AFTER_SECS));
+        assert_eq!(retry_after(429, Some("soon")), Some(DEFAULT_RETRY_AFTER_SECS));
+    }
+
+    #[test]
+    fn base_urls_fall_back_to_the_default() {
+        let endpoint = Endpoint::anthropic(&AppSettings::default()).unwrap();
+        assert_eq!(endpoint.url("/v1/messages"), "https://api.anthropic.com/v1/messages");
+
+        let gateway = EndpointConfig {
+            base_url: Some("https://gateway.example.com/openai/".to_string()),
+            ..Default::default()
+        };
+        let endpoint = Endpoint::openai(&settings(OPENAI, gateway)).unwrap();
+        assert_eq!(endpoint.url("/v1/models"), "https://gateway.example.com/openai/v1/models");
+        // Other endpoints keep their default
+        let endpoint = Endpoint::anthropic_oauth(&settings(OPENAI, EndpointConfig::default())).unwrap();
+        assert_eq!(endpoint.base_url, ANTHROPIC_OAUTH_DEFAULT_URL);
+
+        let blank = EndpointConfig {
+            base_url: Some("  ".to_string()),
+            ..Default::default()
+        };
+        let endpoint = Endpoint::openai(&settings(OPENAI, blank)).unwrap();
+        assert_eq!(endpoint.base_url, OPENAI_DEFAULT_URL);
+    }
+
+    #[test]
+    fn invalid_headers_and_proxies_are_config_errors() {
+        let invalid = [
+            EndpointConfig {
+                headers: [("bad name".to_string(), "value".to_string())].into(),
+                ..Default::default()
+            },
+            EndpointConfig {
+                headers: [("x-team".to_string(), "a\nb".to_string())].into(),
+                ..Default::default()
+            },
+            EndpointConfig {
+                proxy: Some("socks5://127.0.0.1:1080".to_string()),
+                ..Default::default()
+            },
+        ];
+        for config in invalid {
+            let error = Endpoint::anthropic(&settings(ANTHROPIC, config.clone()));
+            assert!(matches!(error, Err(AppError::ConfigError(_))), "{:?}", config);
+        }
+
+        let proxy = EndpointConfig {
+            proxy: Some("http://127.0.0.1:3128".to_string()),
+            ..Default::default()
+        };
+        assert!(Endpoint::anthropic(&settings(ANTHROPIC, proxy)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn extra_headers_go_with_every_request() {
+        let base_url = mock_server::start(([127, 0, 0, 1], 0).into()).await.unwrap();
+        // The mock server rejects credentials ending in -401
+        let config = |headers: &[(&str, &str)]| EndpointConfig {
+            base_url: Some(base_url.clone()),
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            proxy: None,
+        };
+        let status = |endpoint: Endpoint| async move { endpoint.get("/v1/models").send().await.unwrap().status() };
+
+        let plain = Endpoint::openai(&settings(OPENAI, config(&[]))).unwrap();
+        assert_eq!(status(plain).await, reqwest::StatusCode::OK);
+        let gateway = Endpoint::openai(&settings(OPENAI, config(&[("x-api-key", "gateway-401")]))).unwrap();
+        assert_eq!(status(gateway).await, reqwest::StatusCode::UNAUTHORIZED);
+
+        let webhooks = client(&settings(WEBHOOKS, config(&[("x-api-key", "gateway-401")])), WEBHOOKS).unwrap();
+        let response = webhooks.get(format!("{}/v1/models", base_url)).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+}
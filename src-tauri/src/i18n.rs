@@ -0,0 +1,413 @@
+// ============== LOCALIZATION ==============
+//
+// Message catalog for notifications and user-facing errors. The locale comes
+// from `AppSettings.locale`, falling back to the OS locale. Templates use
+// `{name}` placeholders filled by the `tr!` macro:
+//
+//     tr!("notify.reset.title", provider = name)
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Fr,
+}
+
+impl Locale {
+    /// Match a BCP 47 tag ("fr-FR", "en_US", ...) on its language
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_', '.']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "fr" => Some(Locale::Fr),
+            _ => None,
+        }
+    }
+
+    /// OS locale, English when unsupported
+    pub fn system() -> Self {
+        sys_locale::get_locale()
+            .and_then(|tag| Self::from_tag(&tag))
+            .unwrap_or_default()
+    }
+}
+
+static CURRENT_LOCALE: RwLock<Locale> = RwLock::new(Locale::En);
+
+pub fn set_locale(locale: Locale) {
+    *CURRENT_LOCALE.write().unwrap() = locale;
+}
+
+pub fn current_locale() -> Locale {
+    *CURRENT_LOCALE.read().unwrap()
+}
+
+/// (key, English, French)
+const MESSAGES: &[(&str, &str, &str)] = &[
+    // ---------- Error kinds ----------
+    ("error.api", "API error: {detail}", "Erreur API : {detail}"),
+    ("error.network", "Network error: {detail}", "Erreur réseau : {detail}"),
+    ("error.config", "Configuration error: {detail}", "Erreur de configuration : {detail}"),
+    ("error.keyring", "Keyring error: {detail}", "Erreur du trousseau : {detail}"),
+    (
+        "error.rate_limited",
+        "Rate limited, retry in {seconds}s",
+        "Trop de requêtes, nouvel essai dans {seconds} s",
+    ),
+    ("error.unauthorized", "Unauthorized: {detail}", "Non autorisé : {detail}"),
+    // ---------- Errors ----------
+    ("error.provider_not_found", "Provider not found", "Fournisseur introuvable"),
+    ("error.webhook_not_found", "Webhook not found: {id}", "Webhook introuvable : {id}"),
+    ("error.empty_account_name", "Account name cannot be empty", "Le nom du compte ne peut pas être vide"),
+    (
+        "error.builtin_account_locked",
+        "The built-in {provider} account cannot be removed",
+        "Le compte intégré {provider} ne peut pas être supprimé",
+    ),
+    (
+        "error.not_a_counter",
+        "{provider} is not a manual counter",
+        "{provider} n'est pas un compteur manuel",
+    ),
+    (
+        "error.invalid_counter",
+        "The limit and the reset interval must be greater than 0",
+        "La limite et l'intervalle de réinitialisation doivent être supérieurs à 0",
+    ),
+    (
+        "error.invalid_weight",
+        "Weights must be greater than 0",
+        "Les poids doivent être supérieurs à 0",
+    ),
+    (
+        "error.unknown_weight",
+        "No weight is defined for '{kind}'",
+        "Aucun poids n'est défini pour '{kind}'",
+    ),
+    (
+        "error.invalid_reset_policy",
+        "Invalid reset schedule: {detail}",
+        "Calendrier de réinitialisation invalide : {detail}",
+    ),
+    (
+        "error.no_credentials_file",
+        "Only Claude accounts have a credentials file",
+        "Seuls les comptes Claude ont un fichier d'identifiants",
+    ),
+    (
+        "error.invalid_proxy_route",
+        "Proxy route '{prefix}' needs a unique path prefix, a Claude or OpenAI account and, if set, a manual counter",
+        "La route de proxy '{prefix}' demande un préfixe de chemin unique, un compte Claude ou OpenAI et, si indiqué, un compteur manuel",
+    ),
+    (
+        "error.invalid_budget",
+        "Budgets must be greater than 0",
+        "Les budgets doivent être supérieurs à 0",
+    ),
+    (
+        "error.no_model_price",
+        "No price is known for {model}",
+        "Aucun tarif n'est connu pour {model}",
+    ),
+    (
+        "error.no_refresh_token",
+        "The internal token expired and has no refresh token. Copy the token from Claude Code again.",
+        "Le token interne a expiré et n'a pas de refresh token. Copiez à nouveau le token depuis Claude Code.",
+    ),
+    (
+        "error.token_refresh_failed",
+        "Token refresh failed ({status}): {body}",
+        "Échec du renouvellement du token ({status}) : {body}",
+    ),
+    (
+        "error.token_refresh_rejected",
+        "The refresh token was rejected. Log in to Claude Code again, then copy the token.",
+        "Le refresh token a été refusé. Reconnectez-vous à Claude Code, puis copiez le token.",
+    ),
+    (
+        "error.no_backend",
+        "No backend registered for {provider}",
+        "Aucun service enregistré pour {provider}",
+    ),
+    (
+        "error.no_api_key",
+        "No API key configured for {provider}",
+        "Aucune clé API configurée pour {provider}",
+    ),
+    ("error.api_status", "{service} returned {status}: {body}", "{service} a renvoyé {status} : {body}"),
+    ("error.parse_response", "Failed to parse response: {detail}", "Réponse illisible : {detail}"),
+    ("error.timeout", "{provider} request timed out", "Délai dépassé pour {provider}"),
+    (
+        "error.invalid_header_name",
+        "Invalid header name '{name}': {detail}",
+        "Nom d'en-tête invalide '{name}' : {detail}",
+    ),
+    (
+        "error.invalid_header_value",
+        "Invalid header value for '{name}': {detail}",
+        "Valeur d'en-tête invalide pour '{name}' : {detail}",
+    ),
+    ("error.invalid_proxy", "Invalid proxy '{proxy}': {detail}", "Proxy invalide '{proxy}' : {detail}"),
+    (
+        "error.server_start",
+        "{server} failed to start on {addr}: {detail}",
+        "{server} n'a pas pu démarrer sur {addr} : {detail}",
+    ),
+    (
+        "error.watcher_start",
+        "Credentials watcher failed to start: {detail}",
+        "La surveillance des identifiants n'a pas pu démarrer : {detail}",
+    ),
+    ("error.no_proxy_route", "No proxy route for '/{prefix}'", "Aucune route de proxy pour '/{prefix}'"),
+    ("error.http_method_not_allowed", "Only GET is supported", "Seul GET est pris en charge"),
+    ("error.http_unauthorized", "Missing or invalid token", "Token manquant ou invalide"),
+    ("error.http_stopping", "Server is stopping", "Le serveur s'arrête"),
+    ("error.http_unknown_path", "Unknown path", "Chemin inconnu"),
+    (
+        "error.http_invalid_bucket",
+        "bucket must be hourly, daily or weekly",
+        "bucket doit valoir hourly, daily ou weekly",
+    ),
+    (
+        "error.claude_token_not_found",
+        "Claude Code OAuth token not found. Check that Claude Code is logged in.",
+        "Token OAuth Claude Code non trouvé. Vérifiez que Claude Code est connecté.",
+    ),
+    (
+        "error.no_token_available",
+        "No token available. Please copy token to internal storage or ensure Claude Code is connected.",
+        "Aucun token disponible. Copiez le token dans le stockage interne ou vérifiez que Claude Code est connecté.",
+    ),
+    (
+        "error.no_claude_credentials",
+        "No Claude credentials configured",
+        "Aucun identifiant Claude configuré",
+    ),
+    (
+        "error.invalid_admin_key_format",
+        "Invalid admin key format. Anthropic admin keys start with 'sk-ant-admin'",
+        "Format de clé admin invalide. Les clés admin Anthropic commencent par 'sk-ant-admin'",
+    ),
+    (
+        "error.no_openai_key",
+        "No OpenAI API key configured. Please add your API key in settings.",
+        "Aucune clé API OpenAI configurée. Ajoutez-la dans les paramètres.",
+    ),
+    ("error.empty_api_key", "API key cannot be empty", "La clé API ne peut pas être vide"),
+    (
+        "error.invalid_openai_key_format",
+        "Invalid API key format. OpenAI API keys start with 'sk-'",
+        "Format de clé API invalide. Les clés API OpenAI commencent par 'sk-'",
+    ),
+    (
+        "error.invalid_openai_key",
+        "Invalid API key or API error (status {status})",
+        "Clé API invalide ou erreur API (statut {status})",
+    ),
+    (
+        "error.openai_usage_unavailable",
+        "Usage data unavailable for this key. Use an admin key (sk-admin-...) to read organization usage.",
+        "Données d'utilisation indisponibles pour cette clé. Utilisez une clé admin (sk-admin-...) pour lire l'utilisation de l'organisation.",
+    ),
+    (
+        "error.credentials_not_found",
+        "No Claude Code credentials found. Please ensure Claude Code is installed and logged in.",
+        "Aucun identifiant Claude Code trouvé. Vérifiez que Claude Code est installé et connecté.",
+    ),
+    (
+        "error.token_not_in_credentials",
+        "Token not found in credentials file",
+        "Token introuvable dans le fichier d'identifiants",
+    ),
+    ("error.no_internal_token", "No internal token stored", "Aucun token interne enregistré"),
+    (
+        "error.token_not_in_keyring",
+        "Token not found in secure storage",
+        "Token introuvable dans le stockage sécurisé",
+    ),
+    ("error.invalid_json", "Invalid JSON format: {detail}", "Format JSON invalide : {detail}"),
+    (
+        "error.no_token_in_import",
+        "No access token found in imported data",
+        "Aucun token d'accès dans les données importées",
+    ),
+    (
+        "error.invalid_credentials_file",
+        "Invalid file: incorrect JSON format or missing fields",
+        "Fichier invalide : format JSON incorrect ou champs manquants",
+    ),
+    (
+        "error.localappdata_missing",
+        "Could not find LOCALAPPDATA environment variable",
+        "Variable d'environnement LOCALAPPDATA introuvable",
+    ),
+    (
+        "error.not_installed",
+        "MeterAI is not installed. Please install the application first.\nExpected path: {path}",
+        "MeterAI n'est pas installé. Installez d'abord l'application.\nChemin attendu : {path}",
+    ),
+    // ---------- Notifications ----------
+    ("notify.error.title", "⚠️ MeterAI - Error", "⚠️ MeterAI - Erreur"),
+    ("notify.limit_reached.title", "⚠️ {provider} - Limit reached!", "⚠️ {provider} - Limite atteinte!"),
+    (
+        "notify.limit_reached.body",
+        "You have used 100% of your quota.",
+        "Vous avez utilisé 100% de votre quota.",
+    ),
+    ("notify.threshold.title", "⚡ {provider} - {threshold}%", "⚡ {provider} - {threshold}%"),
+    (
+        "notify.threshold.body",
+        "You have used {used} of {limit} {unit}.",
+        "Vous avez utilisé {used} {unit} sur {limit}.",
+    ),
+    ("notify.reset.title", "🔄 {provider} - Quota reset!", "🔄 {provider} - Quota réinitialisé!"),
+    (
+        "notify.reset.body",
+        "Your quota of {limit} {unit} is available again.",
+        "Votre quota de {limit} {unit} est à nouveau disponible.",
+    ),
+    ("unit.requests", "requests", "requêtes"),
+    (
+        "notify.window_limit.title",
+        "⚠️ {provider} - {window} limit reached!",
+        "⚠️ {provider} - Limite {window} atteinte!",
+    ),
+    ("notify.window_limit.body", "The {window} window is exhausted.", "Fenêtre {window} épuisée."),
+    (
+        "notify.window_limit.body_reset",
+        "The {window} window is exhausted. Resets at {reset}.",
+        "Fenêtre {window} épuisée. Réinitialisation à {reset}.",
+    ),
+    (
+        "notify.window_threshold.title",
+        "⚡ {provider} - {window}: {percent}%",
+        "⚡ {provider} - {window} : {percent}%",
+    ),
+    (
+        "notify.window_threshold.body",
+        "You have used {percent}% of the {window} window.",
+        "Vous avez utilisé {percent}% de la fenêtre {window}.",
+    ),
+    (
+        "notify.window_threshold.body_reset",
+        "You have used {percent}% of the {window} window. Resets at {reset}.",
+        "Vous avez utilisé {percent}% de la fenêtre {window}. Réinitialisation à {reset}.",
+    ),
+    (
+        "notify.forecast.title",
+        "📈 {provider} - {window} running out",
+        "📈 {provider} - {window} bientôt épuisé",
+    ),
+    (
+        "notify.forecast.body",
+        "At the current pace, {window} reaches 100% around {at}, before its reset at {reset}.",
+        "Au rythme actuel, {window} atteint 100% vers {at}, avant sa réinitialisation à {reset}.",
+    ),
+    (
+        "notify.budget_projected.title",
+        "💸 {name} - Budget on track to be exceeded",
+        "💸 {name} - Budget en voie d'être dépassé",
+    ),
+    (
+        "notify.budget_projected.body",
+        "Projected spend this month: ${projected} of ${budget} (${spent} so far).",
+        "Dépense prévue ce mois-ci : {projected} $ sur {budget} $ ({spent} $ à ce jour).",
+    ),
+    (
+        "notify.budget_exceeded.title",
+        "💸 {name} - Budget exceeded",
+        "💸 {name} - Budget dépassé",
+    ),
+    (
+        "notify.budget_exceeded.body",
+        "${spent} spent of the ${budget} monthly budget.",
+        "{spent} $ dépensés sur un budget mensuel de {budget} $.",
+    ),
+    ("budget.global", "All providers", "Tous les fournisseurs"),
+    ("webhook.test.title", "🔔 MeterAI - Test alert", "🔔 MeterAI - Alerte de test"),
+    (
+        "webhook.test.body",
+        "The webhook \"{name}\" is working.",
+        "Le webhook « {name} » fonctionne.",
+    ),
+    // ---------- Windows ----------
+    ("window.five_hour", "5 hours", "5 heures"),
+    ("window.seven_day", "7 days", "7 jours"),
+    ("window.billing_period", "billing period", "période de facturation"),
+    ("window.quota", "quota", "quota"),
+    ("window_short.five_hour", "5h", "5h"),
+    ("window_short.seven_day", "7d", "7j"),
+    ("window_short.billing_period", "month", "mois"),
+    ("window_short.quota", "", ""),
+    // ---------- Tray ----------
+    ("tray.show", "Show", "Afficher"),
+    ("tray.quit", "Quit", "Quitter"),
+    ("tray.active_provider", "Active provider", "Fournisseur actif"),
+    ("tray.refresh", "Refresh now", "Actualiser maintenant"),
+    ("tray.add_request", "+1 request", "+1 requête"),
+    ("tray.reset", "Reset", "Réinitialiser"),
+    ("tray.pause", "Pause notifications for 1h", "Suspendre les notifications 1 h"),
+    (
+        "tray.resume",
+        "Resume notifications (paused until {time})",
+        "Reprendre les notifications (suspendues jusqu'à {time})",
+    ),
+    ("tray.open_settings", "Open settings", "Ouvrir les paramètres"),
+    ("tray.resets_in", "resets in {duration}", "réinit. dans {duration}"),
+    ("tray.no_data", "no data", "aucune donnée"),
+    // ---------- Durations ----------
+    ("duration.days_hours", "{days}d {hours}h", "{days}j {hours}h"),
+];
+
+fn lookup(key: &str) -> Option<&'static str> {
+    let locale = current_locale();
+    MESSAGES
+        .iter()
+        .find(|(k, _, _)| *k == key)
+        .map(|(_, en, fr)| match locale {
+            Locale::En => *en,
+            Locale::Fr => *fr,
+        })
+}
+
+/// Translate `key` in the current locale and fill its placeholders.
+/// Unknown keys are returned as-is so a missing entry stays visible.
+pub fn t(key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut text = lookup(key).unwrap_or(key).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+/// Localized label of a usage window, the raw ID when unknown
+pub fn window_label(window_id: &str) -> String {
+    lookup(&format!("window.{}", window_id))
+        .map(str::to_string)
+        .unwrap_or_else(|| window_id.to_string())
+}
+
+/// Compact window label for the tray, the raw ID when unknown
+pub fn window_short_label(window_id: &str) -> String {
+    lookup(&format!("window_short.{}", window_id))
+        .map(str::to_string)
+        .unwrap_or_else(|| window_id.to_string())
+}
+
+/// `tr!(key, name = value, ...)`: `t` with named placeholder arguments
+#[macro_export]
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::i18n::t($key, &[])
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::t($key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+pub use crate::tr;
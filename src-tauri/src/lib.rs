@@ -0,0 +1,1111 @@
+// ============== CORE ==============
+//
+// State, persistence, credential discovery and provider fetchers, shared by the
+// desktop app (`main.rs`) and the `meterai` command-line tool (`bin/meterai.rs`).
+
+use chrono::{DateTime, Local, Utc};
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Mutex;
+
+pub mod accounts;
+pub mod budgets;
+pub mod counters;
+pub mod forecast;
+pub mod history;
+pub mod http;
+pub mod i18n;
+pub mod local_api;
+pub mod metering_proxy;
+pub mod metrics;
+pub mod mock_server;
+pub mod pricing;
+pub mod providers;
+pub mod reset_policy;
+pub mod summary;
+pub mod token_store;
+pub mod transcripts;
+pub mod webhooks;
+
+use http::EndpointConfig;
+use budgets::{BudgetAlertState, Budgets};
+use i18n::Locale;
+use metering_proxy::{MeteredTotals, MeteringProxyConfig};
+use providers::claude_code::ClaudeCodeUsageResult;
+use providers::{ProviderRegistry, UsageReport, WindowUsage};
+use reset_policy::ResetPolicy;
+use webhooks::{Alert, AlertEvent, WebhookTarget};
+
+// ============== ERROR HANDLING ==============
+
+#[derive(Debug)]
+pub enum AppError {
+    ApiError(String),
+    NetworkError(String),
+    ConfigError(String),
+    KeyringError(String),
+    /// HTTP 429, with the number of seconds to wait before retrying
+    RateLimited(u64),
+    /// HTTP 401: missing, expired or revoked credentials
+    Unauthorized(String),
+}
+
+impl AppError {
+    /// Every value of `kind`
+    pub const KINDS: &'static [&'static str] = &["api", "network", "config", "keyring", "rate_limited", "unauthorized"];
+
+    /// Stable, untranslated variant name (metrics labels)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::ApiError(_) => "api",
+            AppError::NetworkError(_) => "network",
+            AppError::ConfigError(_) => "config",
+            AppError::KeyringError(_) => "keyring",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::Unauthorized(_) => "unauthorized",
+        }
+    }
+}
+
+/// Localized in the current locale, as errors are shown to the user as-is
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            AppError::ApiError(detail) => tr!("error.api", detail = detail),
+            AppError::NetworkError(detail) => tr!("error.network", detail = detail),
+            AppError::ConfigError(detail) => tr!("error.config", detail = detail),
+            AppError::KeyringError(detail) => tr!("error.keyring", detail = detail),
+            AppError::RateLimited(seconds) => tr!("error.rate_limited", seconds = seconds),
+            AppError::Unauthorized(detail) => tr!("error.unauthorized", detail = detail),
+        };
+        f.write_str(&text)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// ============== PROVIDER TYPES ==============
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderType {
+    #[default]
+    Manual,
+    Anthropic,
+    OpenAI,
+}
+
+impl ProviderType {
+    /// Lowercase name, as serialized and used in account IDs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderType::Manual => "manual",
+            ProviderType::Anthropic => "anthropic",
+            ProviderType::OpenAI => "openai",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub provider_type: ProviderType,
+    pub name: String,
+    pub enabled: bool,
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    pub has_api_key: bool,
+    pub limit: f64,
+    #[serde(rename = "alertThresholds")]
+    pub alert_thresholds: Vec<u32>,
+    #[serde(rename = "resetIntervalHours")]
+    pub reset_interval_hours: u32,
+    /// Background refresh interval in seconds (0 disables polling)
+    #[serde(rename = "pollIntervalSecs", default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u32,
+    /// Claude Code credentials file of an added Claude account
+    #[serde(rename = "credentialsPath", default)]
+    pub credentials_path: Option<String>,
+    /// Unit label of a manual counter ("fast hours", "searches", ...)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// When a manual counter starts a new period
+    #[serde(rename = "resetPolicy", default)]
+    pub reset_policy: ResetPolicy,
+    /// Amount counted per use of a kind ("opus": 5.0), for weighted counters
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+    /// Keep counting past the limit instead of stopping at 100%
+    #[serde(rename = "allowOverage", default)]
+    pub allow_overage: bool,
+    /// A Claude account was enabled on its first Claude Code login (see
+    /// `scheduler`); disabling it afterwards sticks
+    #[serde(rename = "loginSeen", default)]
+    pub login_seen: bool,
+}
+
+fn default_poll_interval_secs() -> u32 {
+    300
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            provider_type: ProviderType::Manual,
+            name: "Manual".to_string(),
+            enabled: true,
+            api_key: None,
+            has_api_key: false,
+            limit: 100.0,
+            alert_thresholds: vec![70, 90, 100],
+            reset_interval_hours: 4,
+            poll_interval_secs: default_poll_interval_secs(),
+            credentials_path: None,
+            unit: None,
+            reset_policy: ResetPolicy::default(),
+            weights: HashMap::new(),
+            allow_overage: false,
+            login_seen: false,
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// Counter unit, "requests" when none is set
+    pub fn unit_label(&self) -> String {
+        self.unit.clone().unwrap_or_else(|| tr!("unit.requests"))
+    }
+}
+
+// ============== DATA STRUCTURES ==============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageData {
+    /// Amount used in the counter's unit (requests, tokens, USD, ...)
+    pub used: f64,
+    pub limit: f64,
+    /// May exceed 100 when the counter allows overage
+    pub percent: u32,
+    #[serde(rename = "resetTime")]
+    pub reset_time: i64,
+    pub history: Vec<HistoryEntry>,
+    #[serde(rename = "providerType")]
+    pub provider_type: ProviderType,
+    #[serde(rename = "providerName")]
+    pub provider_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub time: String,
+    pub used: f64,
+    pub limit: f64,
+}
+
+impl UsageData {
+    /// Recompute `percent` from `used` and `limit`
+    pub fn refresh_percent(&mut self) {
+        self.percent = if self.limit > 0.0 {
+            ((self.used / self.limit) * 100.0) as u32
+        } else {
+            0
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderUsage {
+    pub usage: UsageData,
+    pub config: ProviderConfig,
+    #[serde(skip)]
+    pub notified_thresholds: Vec<u32>,
+    /// Alert state per fetched window ("five_hour", "seven_day", ...)
+    #[serde(default, rename = "notifiedWindows")]
+    pub notified_windows: HashMap<String, WindowAlertState>,
+    /// Last successful fetch from the provider backend
+    #[serde(default, rename = "lastReport")]
+    pub last_report: Option<UsageReport>,
+    /// Calls charged through the metering proxy
+    #[serde(default)]
+    pub metered: Option<MeteredTotals>,
+}
+
+/// Thresholds already announced for a window, until its reset time rolls over
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WindowAlertState {
+    #[serde(rename = "resetsAt")]
+    pub resets_at: Option<String>,
+    pub notified: Vec<u32>,
+    /// A forecast alert was raised for this period
+    #[serde(rename = "forecastWarned", default)]
+    pub forecast_warned: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppSettings {
+    #[serde(rename = "customCredentialsPath")]
+    pub custom_credentials_path: Option<String>,
+    /// Copy the Claude Code token to internal storage whenever its credentials file changes
+    #[serde(rename = "autoSyncToken", default)]
+    pub auto_sync_token: bool,
+    /// Base URL / header / proxy overrides keyed by endpoint ("anthropic", "openai")
+    #[serde(default)]
+    pub endpoints: HashMap<String, EndpointConfig>,
+    /// Serve recorded responses from the built-in mock server (applies on restart)
+    #[serde(rename = "mockMode", default)]
+    pub mock_mode: bool,
+    /// Days of usage history to keep (0 keeps everything)
+    #[serde(rename = "historyRetentionDays", default = "default_history_retention_days")]
+    pub history_retention_days: u32,
+    /// Language of notifications and messages (None follows the OS locale)
+    #[serde(default)]
+    pub locale: Option<Locale>,
+    /// Unix timestamp until which notifications are muted
+    #[serde(rename = "notificationsPausedUntil", default)]
+    pub notifications_paused_until: Option<i64>,
+    /// Local HTTP API for other tools (off by default)
+    #[serde(rename = "apiServer", default)]
+    pub api_server: ApiServerConfig,
+    /// Targets of threshold and reset alerts, besides the desktop notification
+    #[serde(default)]
+    pub webhooks: Vec<WebhookTarget>,
+    /// Monthly spend budgets in USD
+    #[serde(default)]
+    pub budgets: Budgets,
+    /// Alert when a window is projected to run out before its reset
+    #[serde(rename = "forecastAlerts", default)]
+    pub forecast_alerts: bool,
+    /// Local reverse proxy metering API calls (off by default)
+    #[serde(rename = "meteringProxy", default)]
+    pub metering_proxy: MeteringProxyConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiServerConfig {
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+    /// Bearer token required on every request, generated on first enable
+    pub token: Option<String>,
+}
+
+impl Default for ApiServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47600,
+            token: None,
+        }
+    }
+}
+
+fn default_history_retention_days() -> u32 {
+    history::DEFAULT_RETENTION_DAYS
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            custom_credentials_path: None,
+            auto_sync_token: false,
+            endpoints: HashMap::new(),
+            mock_mode: false,
+            history_retention_days: default_history_retention_days(),
+            locale: None,
+            notifications_paused_until: None,
+            api_server: ApiServerConfig::default(),
+            webhooks: vec![],
+            budgets: Budgets::default(),
+            forecast_alerts: false,
+            metering_proxy: MeteringProxyConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppState {
+    pub providers: HashMap<String, ProviderUsage>,
+    #[serde(rename = "activeProvider")]
+    pub active_provider: String,
+    #[serde(default)]
+    pub settings: AppSettings,
+    /// Budget alerts raised this month, by provider ID ("global" for all)
+    #[serde(rename = "budgetAlerts", default)]
+    pub budget_alerts: HashMap<String, BudgetAlertState>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        let mut state = Self {
+            providers: HashMap::new(),
+            active_provider: "manual".to_string(),
+            settings: AppSettings::default(),
+            budget_alerts: HashMap::new(),
+        };
+        // One entry per registered backend (manual enabled, others disabled)
+        ProviderRegistry::builtin().ensure_defaults(&mut state);
+        state
+    }
+}
+
+// ============== PERSISTENCE ==============
+
+pub fn get_data_dir() -> PathBuf {
+    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("meter-ai");
+    fs::create_dir_all(&path).ok();
+    path
+}
+
+fn get_data_path() -> PathBuf {
+    get_data_dir().join("data.json")
+}
+
+/// Set in `meterai` while the app runs: the app owns data.json then
+static SAVE_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Take the app lock without waiting, or `None` when another process holds
+/// it. The lock lasts as long as the returned file, and ends with the
+/// process if it crashes. The app holds it for its whole run; `meterai`
+/// while it changes data.json.
+pub fn try_lock_app() -> std::io::Result<Option<fs::File>> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(get_data_dir().join("app.lock"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(fs::TryLockError::WouldBlock) => Ok(None),
+        Err(fs::TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// Make `save_state` a no-op, so a process that doesn't own data.json can't
+/// overwrite what the app saved
+pub fn disable_save() {
+    SAVE_DISABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn load_state() -> AppState {
+    let path = get_data_path();
+    if path.exists() {
+        if let Ok(content) = fs::read_to_string(&path) {
+            if let Ok(mut state) = serde_json::from_str::<AppState>(&content) {
+                // Providers added since the state was saved
+                ProviderRegistry::builtin().ensure_defaults(&mut state);
+
+                // A pause survives restarts
+                NOTIFICATIONS_PAUSED_UNTIL.store(
+                    state.settings.notifications_paused_until.unwrap_or(0),
+                    Ordering::Relaxed,
+                );
+                webhooks::configure(&state.settings);
+
+                // Load API keys from secure storage
+                for (provider_id, provider) in state.providers.iter_mut() {
+                    if let Ok(entry) = keyring::Entry::new("meter-ai", provider_id) {
+                        if let Ok(key) = entry.get_password() {
+                            provider.config.api_key = Some(key);
+                            provider.config.has_api_key = true;
+                        }
+                    }
+                }
+                return state;
+            }
+        }
+    }
+    AppState::default()
+}
+
+pub fn save_state(state: &AppState) {
+    if SAVE_DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let path = get_data_path();
+    if let Ok(json) = serde_json::to_string_pretty(state) {
+        fs::write(path, json).ok();
+    }
+}
+
+// ============== SECURE API KEY STORAGE ==============
+
+pub fn save_api_key(provider_id: &str, api_key: &str) -> Result<(), AppError> {
+    let entry = keyring::Entry::new("meter-ai", provider_id)
+        .map_err(|e| AppError::KeyringError(e.to_string()))?;
+    entry
+        .set_password(api_key)
+        .map_err(|e| AppError::KeyringError(e.to_string()))?;
+    Ok(())
+}
+
+pub fn delete_api_key(provider_id: &str) -> Result<(), AppError> {
+    if let Ok(entry) = keyring::Entry::new("meter-ai", provider_id) {
+        entry.delete_password().ok();
+    }
+    Ok(())
+}
+
+/// Validate and store an API key for any provider that uses one
+pub fn store_provider_api_key(
+    provider_id: &str,
+    api_key: String,
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    let provider_type = state
+        .providers
+        .get(provider_id)
+        .map(|p| p.config.provider_type.clone())
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    let backend = registry
+        .get(&provider_type)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    backend.check_api_key_format(&api_key)?;
+
+    // Save to keyring
+    save_api_key(provider_id, &api_key)?;
+
+    // Update state
+    if let Some(provider) = state.providers.get_mut(provider_id) {
+        provider.config.api_key = Some(api_key);
+        provider.config.has_api_key = true;
+    }
+    save_state(&state);
+
+    Ok(())
+}
+
+// ============== CLAUDE CODE OAUTH INTEGRATION ==============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeOAuthData {
+    #[serde(rename = "accessToken")]
+    pub access_token: Option<String>,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "subscriptionType")]
+    pub subscription_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeCodeCredentials {
+    // New nested format: { "claudeAiOauth": { "accessToken": "..." } }
+    #[serde(rename = "claudeAiOauth")]
+    pub claude_ai_oauth: Option<ClaudeOAuthData>,
+    // Legacy flat format: { "accessToken": "..." }
+    #[serde(rename = "accessToken")]
+    pub access_token: Option<String>,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+}
+
+/// Extract token from ClaudeCodeCredentials (handles both nested and flat format)
+pub(crate) fn extract_token_from_creds(creds: &ClaudeCodeCredentials) -> Option<String> {
+    // Try nested format first: { "claudeAiOauth": { "accessToken": "..." } }
+    if let Some(ref oauth) = creds.claude_ai_oauth {
+        if let Some(ref token) = oauth.access_token {
+            if !token.is_empty() {
+                return Some(token.clone());
+            }
+        }
+    }
+    // Fall back to flat format: { "accessToken": "..." }
+    if let Some(ref token) = creds.access_token {
+        if !token.is_empty() {
+            return Some(token.clone());
+        }
+    }
+    None
+}
+
+/// Credentials info with token and subscription type
+#[derive(Debug, Clone)]
+pub struct CredentialsInfo {
+    pub token: String,
+    pub subscription_type: Option<String>,
+}
+
+/// Try to read credentials from a specific path
+fn try_read_credentials(path: &PathBuf) -> Option<String> {
+    if !path.exists() {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    let creds: ClaudeCodeCredentials = serde_json::from_str(&content).ok()?;
+    extract_token_from_creds(&creds)
+}
+
+/// Try to read full credentials info (token + subscription type) from a path
+pub(crate) fn try_read_credentials_info(path: &PathBuf) -> Option<CredentialsInfo> {
+    if !path.exists() {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    let creds: ClaudeCodeCredentials = serde_json::from_str(&content).ok()?;
+    let token = extract_token_from_creds(&creds)?;
+
+    // Extract subscription type from nested format
+    let subscription_type = creds
+        .claude_ai_oauth
+        .as_ref()
+        .and_then(|oauth| oauth.subscription_type.clone());
+
+    Some(CredentialsInfo {
+        token,
+        subscription_type,
+    })
+}
+
+/// Get all possible credential paths for the current OS
+pub(crate) fn get_credential_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+
+    if let Some(home) = dirs::home_dir() {
+        // Primary: ~/.claude/.credentials.json
+        paths.push(home.join(".claude").join(".credentials.json"));
+        // Legacy: ~/.claude/credentials.json
+        paths.push(home.join(".claude").join("credentials.json"));
+        // Alternative: ~/.config/claude-code/auth.json
+        paths.push(home.join(".config").join("claude-code").join("auth.json"));
+    }
+
+    // Windows-specific paths
+    #[cfg(target_os = "windows")]
+    {
+        if let Ok(appdata) = env::var("APPDATA") {
+            // VS Code extension storage
+            paths.push(
+                PathBuf::from(&appdata)
+                    .join("Code")
+                    .join("User")
+                    .join("globalStorage")
+                    .join("anthropic.claude-code")
+                    .join("credentials.json"),
+            );
+        }
+        if let Ok(localappdata) = env::var("LOCALAPPDATA") {
+            paths.push(
+                PathBuf::from(&localappdata)
+                    .join("claude-code")
+                    .join("credentials.json"),
+            );
+        }
+    }
+
+    // Linux XDG paths
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
+            paths.insert(
+                2,
+                PathBuf::from(&xdg_config)
+                    .join("claude-code")
+                    .join("auth.json"),
+            );
+        }
+    }
+
+    // macOS specific
+    #[cfg(target_os = "macos")]
+    {
+        if let Some(home) = dirs::home_dir() {
+            paths.push(
+                home.join("Library")
+                    .join("Application Support")
+                    .join("claude-code")
+                    .join("credentials.json"),
+            );
+        }
+    }
+
+    paths
+}
+
+/// Get Claude Code OAuth token from various sources
+pub fn get_claude_code_oauth_token_with_custom(custom_path: Option<&str>) -> Option<String> {
+    // 1. Custom path (priority)
+    if let Some(path) = custom_path {
+        if let Some(token) = try_read_credentials(&PathBuf::from(path)) {
+            return Some(token);
+        }
+    }
+
+    // 2. Environment variable
+    if let Ok(token) = env::var("CLAUDE_CODE_OAUTH_TOKEN") {
+        if !token.is_empty() {
+            return Some(token);
+        }
+    }
+
+    // 3. Auto-detect paths
+    for path in get_credential_paths() {
+        if let Some(token) = try_read_credentials(&path) {
+            return Some(token);
+        }
+    }
+
+    None
+}
+
+/// Get Claude Code OAuth token (legacy function for backward compatibility)
+pub fn get_claude_code_oauth_token() -> Option<String> {
+    get_claude_code_oauth_token_with_custom(None)
+}
+
+/// Get full credentials info (token + subscription type)
+pub fn get_claude_code_credentials_info() -> Option<CredentialsInfo> {
+    // Try auto-detect paths
+    for path in get_credential_paths() {
+        if let Some(info) = try_read_credentials_info(&path) {
+            return Some(info);
+        }
+    }
+    None
+}
+
+/// Get detected config source for UI display
+pub fn get_detected_config_source(custom_path: Option<&str>) -> String {
+    // 1. Custom path
+    if let Some(path) = custom_path {
+        if try_read_credentials(&PathBuf::from(path)).is_some() {
+            return format!("custom:{}", path);
+        }
+    }
+
+    // 2. Environment variable
+    if let Ok(token) = env::var("CLAUDE_CODE_OAUTH_TOKEN") {
+        if !token.is_empty() {
+            return "env:CLAUDE_CODE_OAUTH_TOKEN".to_string();
+        }
+    }
+
+    // 3. Auto-detect paths
+    for path in get_credential_paths() {
+        if try_read_credentials(&path).is_some() {
+            return format!("auto:{}", path.display());
+        }
+    }
+
+    "none".to_string()
+}
+
+// ============== USAGE COUNTERS ==============
+
+/// Add `count` requests to the active provider and return its new usage,
+/// with the alerts to raise once the state lock is released
+pub fn add_requests(state: &mut AppState, count: u32) -> Option<(UsageData, Vec<Alert>)> {
+    let active = state.active_provider.clone();
+    add_counter_usage(state, &active, count as f64)
+}
+
+/// Add `amount` (negative to correct a mistake) to the counter of
+/// `provider_id` and return its new usage, with the alerts to raise once the
+/// state lock is released. The counter stops at its limit unless it allows
+/// overage.
+pub fn add_counter_usage(state: &mut AppState, provider_id: &str, amount: f64) -> Option<(UsageData, Vec<Alert>)> {
+    let counted = count_usage(state, provider_id, amount)?;
+    save_state(state);
+    Some(counted)
+}
+
+/// `add_counter_usage` without saving, for callers that batch their saves
+pub(crate) fn count_usage(state: &mut AppState, provider_id: &str, amount: f64) -> Option<(UsageData, Vec<Alert>)> {
+    let config = state.providers.get(provider_id)?.config.clone();
+    let now = Utc::now().timestamp();
+    let next_reset = config.reset_policy.next_reset(config.reset_interval_hours, state, now);
+
+    let mut alerts = Vec::new();
+    let usage_data = {
+        let provider = state.providers.get_mut(provider_id).unwrap();
+
+        // Check if reset needed
+        if reset_policy::is_due(provider.usage.reset_time, now) {
+            alerts.push(roll_over(provider_id, provider, next_reset, now));
+        }
+        // First use of a rolling window
+        if provider.usage.reset_time == reset_policy::IDLE {
+            provider.usage.reset_time = config.reset_policy.open_window(next_reset, now);
+        }
+
+        // Add usage
+        let mut used = (provider.usage.used + amount).max(0.0);
+        if !config.allow_overage {
+            used = used.min(provider.usage.limit);
+        }
+        provider.usage.used = used;
+        provider.usage.refresh_percent();
+
+        // Check notifications
+        alerts.extend(threshold_alerts(provider_id, provider));
+
+        provider.usage.clone()
+    };
+
+    // Outside the borrow scope
+    history::record_manual(provider_id, &usage_data);
+    alerts.extend(forecast::check_alerts(state, provider_id, now));
+    Some((usage_data, alerts))
+}
+
+/// Log the current period of a counter in the short history and clear it
+fn clear_period(provider: &mut ProviderUsage, next_reset: i64) {
+    // Save to history
+    let time_str = Local::now().format("%H:%M").to_string();
+    provider.usage.history.insert(
+        0,
+        HistoryEntry {
+            time: time_str,
+            used: provider.usage.used,
+            limit: provider.usage.limit,
+        },
+    );
+    if provider.usage.history.len() > 6 {
+        provider.usage.history.pop();
+    }
+
+    // Reset
+    provider.usage.used = 0.0;
+    provider.usage.percent = 0;
+    provider.usage.reset_time = next_reset;
+    provider.notified_thresholds.clear();
+}
+
+/// End the current period of a counter: clear it and return the reset alert
+fn roll_over(provider_id: &str, provider: &mut ProviderUsage, next_reset: i64, now: i64) -> Alert {
+    clear_period(provider, next_reset);
+
+    Alert {
+        event: AlertEvent::Reset,
+        provider_id: provider_id.to_string(),
+        provider_name: provider.config.name.clone(),
+        window: None,
+        threshold: None,
+        percent: None,
+        resets_at: None,
+        title: tr!("notify.reset.title", provider = provider.config.name),
+        message: tr!(
+            "notify.reset.body",
+            limit = summary::format_amount(provider.config.limit),
+            unit = provider.config.unit_label()
+        ),
+        timestamp: now,
+    }
+}
+
+/// Reset the manual counters whose period is over and move the ones synced
+/// to a provider to its latest `resets_at`. Returns the changed counters and
+/// the reset alerts to raise once the state lock is released.
+pub fn apply_due_resets(state: &mut AppState) -> (Vec<(String, UsageData)>, Vec<Alert>) {
+    let now = Utc::now().timestamp();
+    let ids: Vec<String> = state
+        .providers
+        .iter()
+        .filter(|(_, p)| p.config.provider_type == ProviderType::Manual)
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    let mut changed = Vec::new();
+    let mut alerts = Vec::new();
+    for id in ids {
+        let provider = &state.providers[&id];
+        let policy = &provider.config.reset_policy;
+        let next_reset = policy.next_reset(provider.config.reset_interval_hours, state, now);
+        let synced = match policy {
+            ResetPolicy::Provider { provider_id, window } => {
+                reset_policy::provider_reset(state, provider_id, window.as_deref()).filter(|at| *at > now)
+            }
+            _ => None,
+        };
+
+        let provider = state.providers.get_mut(&id).unwrap();
+        let reset_time = provider.usage.reset_time;
+        if reset_policy::is_due(reset_time, now) {
+            alerts.push(roll_over(&id, provider, next_reset, now));
+        } else if let Some(at) = synced.filter(|at| *at != reset_time) {
+            provider.usage.reset_time = at;
+        } else {
+            continue;
+        }
+        changed.push((id, provider.usage.clone()));
+    }
+
+    if !changed.is_empty() {
+        save_state(state);
+        for (id, usage) in &changed {
+            history::record_manual(id, usage);
+        }
+    }
+    (changed, alerts)
+}
+
+/// Reset the counter of the active provider and return its new usage
+pub fn reset_active_usage(state: &mut AppState) -> Option<UsageData> {
+    let active = state.active_provider.clone();
+    reset_counter(state, &active)
+}
+
+/// Reset the counter of `provider_id` and return its new usage
+pub fn reset_counter(state: &mut AppState, provider_id: &str) -> Option<UsageData> {
+    let config = state.providers.get(provider_id)?.config.clone();
+    let next_reset = config
+        .reset_policy
+        .next_reset(config.reset_interval_hours, state, Utc::now().timestamp());
+
+    let usage_data = {
+        let provider = state.providers.get_mut(provider_id).unwrap();
+        clear_period(provider, next_reset);
+        provider.usage.clone()
+    };
+
+    save_state(state);
+    history::record_manual(provider_id, &usage_data);
+    Some(usage_data)
+}
+
+// ============== NOTIFICATIONS ==============
+
+/// Unix timestamp until which notifications are muted (0 when not paused)
+static NOTIFICATIONS_PAUSED_UNTIL: AtomicI64 = AtomicI64::new(0);
+
+/// Mute notifications until `until` (None resumes them)
+pub fn pause_notifications(state: &mut AppState, until: Option<i64>) {
+    NOTIFICATIONS_PAUSED_UNTIL.store(until.unwrap_or(0), Ordering::Relaxed);
+    state.settings.notifications_paused_until = until;
+    save_state(state);
+}
+
+fn send_notification(title: &str, body: &str) {
+    if Utc::now().timestamp() < NOTIFICATIONS_PAUSED_UNTIL.load(Ordering::Relaxed) {
+        return;
+    }
+    Notification::new()
+        .summary(title)
+        .body(body)
+        .appname("MeterAI")
+        .timeout(5000)
+        .show()
+        .ok();
+}
+
+/// Desktop notification of a failure with nowhere else to show, such as a
+/// server failing to start (release builds have no console)
+pub fn notify_error(detail: &str) {
+    send_notification(&tr!("notify.error.title"), detail);
+}
+
+/// Desktop notification and webhooks. The notification can block: never
+/// call with the state lock held.
+pub fn raise_alert(alert: Alert) {
+    send_notification(&alert.title, &alert.message);
+    webhooks::dispatch(&alert);
+}
+
+/// Alerts of the thresholds a counter just crossed
+fn threshold_alerts(provider_id: &str, provider: &mut ProviderUsage) -> Vec<Alert> {
+    let percent = provider.usage.percent;
+    let mut alerts = Vec::new();
+
+    for threshold in &provider.config.alert_thresholds {
+        if percent >= *threshold && !provider.notified_thresholds.contains(threshold) {
+            provider.notified_thresholds.push(*threshold);
+
+            let provider_name = &provider.config.name;
+            let (title, body) = if *threshold == 100 {
+                (
+                    tr!("notify.limit_reached.title", provider = provider_name),
+                    tr!("notify.limit_reached.body"),
+                )
+            } else {
+                (
+                    tr!("notify.threshold.title", provider = provider_name, threshold = threshold),
+                    tr!(
+                        "notify.threshold.body",
+                        used = summary::format_amount(provider.usage.used),
+                        limit = summary::format_amount(provider.usage.limit),
+                        unit = provider.config.unit_label()
+                    ),
+                )
+            };
+
+            alerts.push(Alert {
+                event: AlertEvent::Threshold,
+                provider_id: provider_id.to_string(),
+                provider_name: provider_name.clone(),
+                window: None,
+                threshold: Some(*threshold),
+                percent: Some(percent as f64),
+                resets_at: None,
+                title,
+                message: body,
+                timestamp: Utc::now().timestamp(),
+            });
+        }
+    }
+    alerts
+}
+
+/// Whether two `resets_at` values denote the same window.
+/// The API reports the reset with sub-second drift between calls.
+pub(crate) fn is_same_reset(previous: Option<&str>, current: Option<&str>) -> bool {
+    match (previous, current) {
+        (Some(a), Some(b)) => match (DateTime::parse_from_rfc3339(a), DateTime::parse_from_rfc3339(b)) {
+            (Ok(a), Ok(b)) => (b - a).num_seconds().abs() < 60,
+            _ => a == b,
+        },
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Apply `alert_thresholds` to every fetched window independently and
+/// return the alerts to raise
+pub(crate) fn check_window_alerts(
+    provider_id: &str,
+    provider: &mut ProviderUsage,
+    windows: &[WindowUsage],
+) -> Vec<Alert> {
+    let thresholds = provider.config.alert_thresholds.clone();
+    let provider_name = provider.config.name.clone();
+    let mut alerts = Vec::new();
+
+    for window in windows {
+        let percent = match window.percent {
+            Some(percent) => percent,
+            None => continue,
+        };
+
+        let alert = provider.notified_windows.entry(window.id.clone()).or_default();
+        if !is_same_reset(alert.resets_at.as_deref(), window.resets_at.as_deref()) {
+            alert.resets_at = window.resets_at.clone();
+            alert.notified.clear();
+            alert.forecast_warned = false;
+        }
+
+        let crossed: Vec<u32> = thresholds
+            .iter()
+            .copied()
+            .filter(|t| percent >= *t as f64 && !alert.notified.contains(t))
+            .collect();
+        // Announce only the highest newly crossed threshold
+        let highest = match crossed.iter().max() {
+            Some(highest) => *highest,
+            None => continue,
+        };
+        alert.notified.extend(crossed);
+
+        let reset = window
+            .resets_at
+            .as_deref()
+            .and_then(|r| DateTime::parse_from_rfc3339(r).ok())
+            .map(|r| {
+                let local = r.with_timezone(&Local);
+                if local.date_naive() == Local::now().date_naive() {
+                    local.format("%H:%M").to_string()
+                } else {
+                    local.format("%d/%m %H:%M").to_string()
+                }
+            });
+        let label = i18n::window_label(&window.id);
+        let rounded = format!("{:.0}", percent);
+
+        let (title, body) = if highest >= 100 {
+            (
+                tr!("notify.window_limit.title", provider = provider_name, window = label),
+                match reset {
+                    Some(reset) => tr!("notify.window_limit.body_reset", window = label, reset = reset),
+                    None => tr!("notify.window_limit.body", window = label),
+                },
+            )
+        } else {
+            (
+                tr!(
+                    "notify.window_threshold.title",
+                    provider = provider_name,
+                    window = label,
+                    percent = rounded
+                ),
+                match reset {
+                    Some(reset) => tr!(
+                        "notify.window_threshold.body_reset",
+                        percent = rounded,
+                        window = label,
+                        reset = reset
+                    ),
+                    None => tr!("notify.window_threshold.body", percent = rounded, window = label),
+                },
+            )
+        };
+
+        alerts.push(Alert {
+            event: AlertEvent::Threshold,
+            provider_id: provider_id.to_string(),
+            provider_name: provider_name.clone(),
+            window: Some(window.id.clone()),
+            threshold: Some(highest),
+            percent: Some(percent),
+            resets_at: window.resets_at.clone(),
+            title,
+            message: body,
+            timestamp: Utc::now().timestamp(),
+        });
+    }
+    alerts
+}
+
+/// Store a Claude Code fetch made outside the provider registry (legacy
+/// commands): history and last report. Returns the window alerts to raise
+/// once the state lock is released.
+pub fn apply_claude_code_result(state: &mut AppState, result: &ClaudeCodeUsageResult) -> Vec<Alert> {
+    if !result.success {
+        return vec![];
+    }
+    history::record_claude_code(result);
+
+    let mut alerts = Vec::new();
+    if let Some(provider) = state.providers.get_mut("anthropic") {
+        let windows = result.windows();
+        alerts = check_window_alerts("anthropic", provider, &windows);
+        if let Some(percent) = result.five_hour_percent {
+            provider.usage.percent = percent.round() as u32;
+        }
+
+        // Keep the Admin API part of a previous registry fetch
+        let report = provider.last_report.get_or_insert_with(|| UsageReport {
+            provider_id: "anthropic".to_string(),
+            provider_type: ProviderType::Anthropic,
+            windows: vec![],
+            cost_usd: None,
+            subscription_type: None,
+            details: serde_json::Value::Null,
+            fetched_at: 0,
+        });
+        report.windows.retain(|w| !windows.iter().any(|n| n.id == w.id));
+        report.windows.splice(0..0, windows);
+        report.subscription_type = result.subscription_type.clone();
+        report.details["claude_code"] = serde_json::to_value(result).unwrap_or_default();
+        report.fetched_at = Utc::now().timestamp();
+    }
+    alerts.extend(forecast::check_alerts(state, "anthropic", Utc::now().timestamp()));
+    save_state(state);
+    alerts
+}
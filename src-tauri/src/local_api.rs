@@ -0,0 +1,325 @@
+// ============== LOCAL HTTP API ==============
+//
+// Opt-in read-only JSON API on 127.0.0.1 for editors, status bars and
+// dashboards, served from the app state:
+//
+//     GET /usage       usage of every enabled provider (as `meterai --json status`)
+//     GET /providers   provider configuration, without API keys
+//     GET /history     samples (`provider`, `window`, `from`, `to`), or
+//                      aggregates with `bucket=hourly|daily|weekly`
+//     GET /events      server-sent events: `usage-updated`, `provider-usage-updated`,
+//                      `counter-updated`, `proxy-call`
+//     GET /metrics     Prometheus text format (see `metrics.rs`)
+//
+// Every request needs the token from `AppSettings.api_server`, either as
+// `Authorization: Bearer <token>` or `?token=<token>` (EventSource can't set
+// headers). The app runs the server through `ApiBackend`, which gives it the
+// state and the event stream.
+
+use chrono::Utc;
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::history::{self, HistoryBucket};
+use crate::i18n::tr;
+use crate::metrics;
+use crate::summary;
+use crate::{AppError, AppState};
+
+/// Comment line sent on idle streams so proxies and clients keep them open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Access of the server to the app
+pub trait ApiBackend: Send + Sync + 'static {
+    /// Build a response from the app state, locked for the call
+    fn with_state(&self, respond: &dyn Fn(&AppState) -> Response<Body>) -> Response<Body>;
+
+    /// Receiver of the `/events` messages (`event: ...\ndata: ...\n\n`), None
+    /// while the server stops
+    fn subscribe(&self) -> Option<broadcast::Receiver<String>>;
+}
+
+/// Random 128-bit token, hex-encoded
+pub fn generate_token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 16]>())
+}
+
+/// Start the server on `port` (0 picks a free port), accepting `token`, and
+/// return its address with the server task
+pub fn start(
+    port: u16,
+    token: String,
+    backend: Arc<dyn ApiBackend>,
+) -> Result<(SocketAddr, JoinHandle<()>), AppError> {
+    let token = Arc::new(token);
+    let make_svc = make_service_fn(move |_conn| {
+        let backend = backend.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let response = respond(backend.as_ref(), &token, &req);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let server = Server::try_bind(&addr)
+        .map_err(|e| {
+            AppError::ConfigError(tr!("error.server_start", server = "API server", addr = addr, detail = e))
+        })?
+        .serve(make_svc);
+    let local_addr = server.local_addr();
+
+    let task = tokio::spawn(async move {
+        server.await.ok();
+    });
+    Ok((local_addr, task))
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let body = serde_json::to_string(body).unwrap_or_default();
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+/// `a=1&b=%3A` as `{a: "1", b: ":"}`
+fn query_params(query: Option<&str>) -> HashMap<String, String> {
+    url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// Compare in constant time so the token can't be guessed byte by byte
+fn token_matches(candidate: &str, token: &str) -> bool {
+    candidate.len() == token.len()
+        && candidate
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn is_authorized(req: &Request<Body>, params: &HashMap<String, String>, token: &str) -> bool {
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer
+        .or(params.get("token").map(String::as_str))
+        .is_some_and(|candidate| token_matches(candidate, token))
+}
+
+fn respond(backend: &dyn ApiBackend, token: &str, req: &Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, &tr!("error.http_method_not_allowed"));
+    }
+    let params = query_params(req.uri().query());
+    if !is_authorized(req, &params, token) {
+        return error_response(StatusCode::UNAUTHORIZED, &tr!("error.http_unauthorized"));
+    }
+
+    match req.uri().path() {
+        "/usage" => backend.with_state(&|state| json_response(StatusCode::OK, &summary::snapshot(state))),
+        "/providers" => backend.with_state(&|state| {
+            // `api_key` is never serialized
+            let providers: HashMap<_, _> = state
+                .providers
+                .iter()
+                .map(|(id, p)| (id.clone(), p.config.clone()))
+                .collect();
+            json_response(StatusCode::OK, &providers)
+        }),
+        "/history" => history_response(&params),
+        "/metrics" => {
+            let mut response = backend.with_state(&|state| Response::new(Body::from(metrics::render(state))));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+            );
+            response
+        }
+        "/events" => match backend.subscribe() {
+            Some(receiver) => event_stream(receiver),
+            None => error_response(StatusCode::SERVICE_UNAVAILABLE, &tr!("error.http_stopping")),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, &tr!("error.http_unknown_path")),
+    }
+}
+
+/// Same defaults as the history commands: 24 hours of samples, 30 days of aggregates
+fn history_response(params: &HashMap<String, String>) -> Response<Body> {
+    let timestamp = |name: &str| params.get(name).and_then(|v| v.parse::<i64>().ok());
+    let provider = params.get("provider").map(String::as_str);
+    let window = params.get("window").map(String::as_str);
+    let to = timestamp("to").unwrap_or_else(|| Utc::now().timestamp());
+
+    match params.get("bucket") {
+        Some(bucket) => {
+            let bucket: HistoryBucket = match serde_json::from_value(bucket.as_str().into()) {
+                Ok(bucket) => bucket,
+                Err(_) => {
+                    return error_response(StatusCode::BAD_REQUEST, &tr!("error.http_invalid_bucket"))
+                }
+            };
+            let from = timestamp("from").unwrap_or(to - 30 * 86_400);
+            json_response(
+                StatusCode::OK,
+                &history::aggregate(provider, window, bucket, from, to),
+            )
+        }
+        None => {
+            let from = timestamp("from").unwrap_or(to - 24 * 3600);
+            json_response(StatusCode::OK, &history::query(provider, window, from, to))
+        }
+    }
+}
+
+fn event_stream(mut receiver: broadcast::Receiver<String>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE);
+        loop {
+            let chunk = tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+            };
+            // The client disconnected
+            if sender.send_data(Bytes::from(chunk)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+    headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    struct TestBackend {
+        state: Mutex<AppState>,
+        events: broadcast::Sender<String>,
+    }
+
+    impl ApiBackend for TestBackend {
+        fn with_state(&self, respond: &dyn Fn(&AppState) -> Response<Body>) -> Response<Body> {
+            respond(&self.state.lock().unwrap())
+        }
+
+        fn subscribe(&self) -> Option<broadcast::Receiver<String>> {
+            Some(self.events.subscribe())
+        }
+    }
+
+    /// Server over the default state (only the manual counter enabled)
+    fn start_api() -> String {
+        let backend = TestBackend {
+            state: Mutex::new(AppState::default()),
+            events: broadcast::channel(1).0,
+        };
+        let (addr, _) = start(0, TOKEN.to_string(), Arc::new(backend)).unwrap();
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn query_values_are_percent_decoded() {
+        let params = query_params(Some("provider=anthropic%3Awork&window=five_hour&note=a+b%26c&flag&=x"));
+        assert_eq!(params["provider"], "anthropic:work");
+        assert_eq!(params["window"], "five_hour");
+        assert_eq!(params["note"], "a b&c");
+        assert_eq!(params["flag"], "");
+        assert_eq!(params.len(), 4);
+        assert!(query_params(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn requests_without_the_token_are_rejected() {
+        let api = start_api();
+        let client = reqwest::Client::new();
+        let usage = format!("{}/usage", api);
+
+        let rejected = [
+            client.get(&usage),
+            client.get(&usage).bearer_auth("wrong"),
+            client.get(&usage).bearer_auth(&TOKEN[1..]),
+            client.get(format!("{}?token=wrong", usage)),
+            // Not a Bearer scheme
+            client.get(&usage).header(AUTHORIZATION, TOKEN),
+        ];
+        for request in rejected {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            let body: Value = response.json().await.unwrap();
+            assert!(body["error"].is_string(), "{}", body);
+        }
+
+        let accepted = [
+            client.get(&usage).bearer_auth(TOKEN),
+            client.get(format!("{}?token={}", usage, TOKEN)),
+        ];
+        for request in accepted {
+            assert_eq!(request.send().await.unwrap().status(), StatusCode::OK);
+        }
+
+        let response = client.post(&usage).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = client.get(format!("{}/unknown", api)).bearer_auth(TOKEN).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn usage_lists_the_enabled_providers() {
+        let api = start_api();
+        let response = reqwest::Client::new()
+            .get(format!("{}/usage", api))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+        let usage: Value = response.json().await.unwrap();
+        assert_eq!(usage["active_provider"], "manual");
+        let providers = usage["providers"].as_array().unwrap();
+        let ids: Vec<&str> = providers.iter().filter_map(|p| p["id"].as_str()).collect();
+        assert_eq!(ids, ["manual"]);
+        assert_eq!(providers[0]["active"], true);
+        assert_eq!(usage["totals"][0]["accounts"], 1);
+    }
+}
@@ -3,587 +3,70 @@
     windows_subsystem = "windows"
 )]
 
-use chrono::{Local, Utc, DateTime};
-use notify_rust::Notification;
+use chrono::{NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use sha2::{Sha256, Digest};
 use std::collections::HashMap;
-use std::env;
 use std::fs;
-use std::path::PathBuf;
 use std::sync::Mutex;
-use tauri::{
-    CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem,
-    Window,
-};
-use thiserror::Error;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, SystemTray, Window};
 
 #[cfg(target_os = "windows")]
 use winreg::enums::*;
 #[cfg(target_os = "windows")]
 use winreg::RegKey;
 
-// ============== ERROR HANDLING ==============
-
-#[derive(Error, Debug)]
-pub enum AppError {
-    #[error("API error: {0}")]
-    ApiError(String),
-    #[error("Network error: {0}")]
-    NetworkError(String),
-    #[error("Configuration error: {0}")]
-    ConfigError(String),
-    #[error("Keyring error: {0}")]
-    KeyringError(String),
-}
-
-impl Serialize for AppError {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&self.to_string())
-    }
-}
-
-// ============== PROVIDER TYPES ==============
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
-#[serde(rename_all = "lowercase")]
-pub enum ProviderType {
-    #[default]
-    Manual,
-    Anthropic,
-    OpenAI,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProviderConfig {
-    pub provider_type: ProviderType,
-    pub name: String,
-    pub enabled: bool,
-    #[serde(skip_serializing)]
-    pub api_key: Option<String>,
-    pub has_api_key: bool,
-    pub limit: u32,
-    #[serde(rename = "alertThresholds")]
-    pub alert_thresholds: Vec<u32>,
-    #[serde(rename = "resetIntervalHours")]
-    pub reset_interval_hours: u32,
-}
-
-impl Default for ProviderConfig {
-    fn default() -> Self {
-        Self {
-            provider_type: ProviderType::Manual,
-            name: "Manual".to_string(),
-            enabled: true,
-            api_key: None,
-            has_api_key: false,
-            limit: 100,
-            alert_thresholds: vec![70, 90, 100],
-            reset_interval_hours: 4,
-        }
-    }
-}
-
-// ============== DATA STRUCTURES ==============
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UsageData {
-    pub used: u32,
-    pub limit: u32,
-    pub percent: u32,
-    #[serde(rename = "resetTime")]
-    pub reset_time: i64,
-    pub history: Vec<HistoryEntry>,
-    #[serde(rename = "providerType")]
-    pub provider_type: ProviderType,
-    #[serde(rename = "providerName")]
-    pub provider_name: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HistoryEntry {
-    pub time: String,
-    pub used: u32,
-    pub limit: u32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProviderUsage {
-    pub usage: UsageData,
-    pub config: ProviderConfig,
-    #[serde(skip)]
-    pub notified_thresholds: Vec<u32>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct AppSettings {
-    #[serde(rename = "customCredentialsPath")]
-    pub custom_credentials_path: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppState {
-    pub providers: HashMap<String, ProviderUsage>,
-    #[serde(rename = "activeProvider")]
-    pub active_provider: String,
-    #[serde(default)]
-    pub settings: AppSettings,
-}
-
-impl Default for AppState {
-    fn default() -> Self {
-        let mut providers = HashMap::new();
-        let reset_interval = 4 * 3600;
-
-        // Default manual provider
-        providers.insert(
-            "manual".to_string(),
-            ProviderUsage {
-                usage: UsageData {
-                    used: 0,
-                    limit: 100,
-                    percent: 0,
-                    reset_time: Utc::now().timestamp() + reset_interval,
-                    history: vec![],
-                    provider_type: ProviderType::Manual,
-                    provider_name: "Manual".to_string(),
-                },
-                config: ProviderConfig::default(),
-                notified_thresholds: vec![],
-            },
-        );
-
-        // Anthropic provider (disabled by default)
-        providers.insert(
-            "anthropic".to_string(),
-            ProviderUsage {
-                usage: UsageData {
-                    used: 0,
-                    limit: 100,
-                    percent: 0,
-                    reset_time: Utc::now().timestamp() + reset_interval,
-                    history: vec![],
-                    provider_type: ProviderType::Anthropic,
-                    provider_name: "Anthropic (Claude)".to_string(),
-                },
-                config: ProviderConfig {
-                    provider_type: ProviderType::Anthropic,
-                    name: "Anthropic (Claude)".to_string(),
-                    enabled: false,
-                    api_key: None,
-                    has_api_key: false,
-                    limit: 100,
-                    alert_thresholds: vec![70, 90, 100],
-                    reset_interval_hours: 4,
-                },
-                notified_thresholds: vec![],
-            },
-        );
-
-        // OpenAI provider (disabled by default)
-        providers.insert(
-            "openai".to_string(),
-            ProviderUsage {
-                usage: UsageData {
-                    used: 0,
-                    limit: 100,
-                    percent: 0,
-                    reset_time: Utc::now().timestamp() + reset_interval,
-                    history: vec![],
-                    provider_type: ProviderType::OpenAI,
-                    provider_name: "OpenAI (ChatGPT)".to_string(),
-                },
-                config: ProviderConfig {
-                    provider_type: ProviderType::OpenAI,
-                    name: "OpenAI (ChatGPT)".to_string(),
-                    enabled: false,
-                    api_key: None,
-                    has_api_key: false,
-                    limit: 100,
-                    alert_thresholds: vec![70, 90, 100],
-                    reset_interval_hours: 4,
-                },
-                notified_thresholds: vec![],
-            },
-        );
-
-        Self {
-            providers,
-            active_provider: "manual".to_string(),
-            settings: AppSettings::default(),
-        }
-    }
-}
-
-// ============== PERSISTENCE ==============
-
-fn get_data_path() -> PathBuf {
-    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push("meter-ai");
-    fs::create_dir_all(&path).ok();
-    path.push("data.json");
-    path
-}
-
-fn load_state() -> AppState {
-    let path = get_data_path();
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(&path) {
-            if let Ok(mut state) = serde_json::from_str::<AppState>(&content) {
-                // Load API keys from secure storage
-                for (provider_id, provider) in state.providers.iter_mut() {
-                    if let Ok(entry) = keyring::Entry::new("meter-ai", provider_id) {
-                        if let Ok(key) = entry.get_password() {
-                            provider.config.api_key = Some(key);
-                            provider.config.has_api_key = true;
-                        }
-                    }
-                }
-                return state;
-            }
-        }
-    }
-    AppState::default()
-}
-
-fn save_state(state: &AppState) {
-    let path = get_data_path();
-    if let Ok(json) = serde_json::to_string_pretty(state) {
-        fs::write(path, json).ok();
-    }
-}
-
-// ============== SECURE API KEY STORAGE ==============
-
-fn save_api_key(provider_id: &str, api_key: &str) -> Result<(), AppError> {
-    let entry = keyring::Entry::new("meter-ai", provider_id)
-        .map_err(|e| AppError::KeyringError(e.to_string()))?;
-    entry
-        .set_password(api_key)
-        .map_err(|e| AppError::KeyringError(e.to_string()))?;
-    Ok(())
-}
-
-fn delete_api_key(provider_id: &str) -> Result<(), AppError> {
-    if let Ok(entry) = keyring::Entry::new("meter-ai", provider_id) {
-        entry.delete_password().ok();
-    }
-    Ok(())
-}
-
-// ============== CLAUDE CODE OAUTH INTEGRATION ==============
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeOAuthData {
-    #[serde(rename = "accessToken")]
-    pub access_token: Option<String>,
-    #[serde(rename = "refreshToken")]
-    pub refresh_token: Option<String>,
-    #[serde(rename = "expiresAt")]
-    pub expires_at: Option<i64>,
-    #[serde(rename = "subscriptionType")]
-    pub subscription_type: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeCodeCredentials {
-    // New nested format: { "claudeAiOauth": { "accessToken": "..." } }
-    #[serde(rename = "claudeAiOauth")]
-    pub claude_ai_oauth: Option<ClaudeOAuthData>,
-    // Legacy flat format: { "accessToken": "..." }
-    #[serde(rename = "accessToken")]
-    pub access_token: Option<String>,
-    #[serde(rename = "refreshToken")]
-    pub refresh_token: Option<String>,
-    #[serde(rename = "expiresAt")]
-    pub expires_at: Option<i64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeUsageWindow {
-    pub utilization: f64,
-    pub resets_at: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeUsageResponse {
-    pub five_hour: Option<ClaudeUsageWindow>,
-    pub seven_day: Option<ClaudeUsageWindow>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeCodeUsageResult {
-    pub success: bool,
-    pub error: Option<String>,
-    pub five_hour_percent: Option<f64>,
-    pub five_hour_reset: Option<String>,
-    pub seven_day_percent: Option<f64>,
-    pub seven_day_reset: Option<String>,
-    pub subscription_type: Option<String>, // "pro", "max", etc.
-}
-
-/// Extract token from ClaudeCodeCredentials (handles both nested and flat format)
-fn extract_token_from_creds(creds: &ClaudeCodeCredentials) -> Option<String> {
-    // Try nested format first: { "claudeAiOauth": { "accessToken": "..." } }
-    if let Some(ref oauth) = creds.claude_ai_oauth {
-        if let Some(ref token) = oauth.access_token {
-            if !token.is_empty() {
-                return Some(token.clone());
-            }
-        }
-    }
-    // Fall back to flat format: { "accessToken": "..." }
-    if let Some(ref token) = creds.access_token {
-        if !token.is_empty() {
-            return Some(token.clone());
-        }
-    }
-    None
-}
-
-/// Credentials info with token and subscription type
-#[derive(Debug, Clone)]
-pub struct CredentialsInfo {
-    pub token: String,
-    pub subscription_type: Option<String>,
-}
-
-/// Try to read credentials from a specific path
-fn try_read_credentials(path: &PathBuf) -> Option<String> {
-    if !path.exists() {
-        return None;
-    }
-    let content = fs::read_to_string(path).ok()?;
-    let creds: ClaudeCodeCredentials = serde_json::from_str(&content).ok()?;
-    extract_token_from_creds(&creds)
-}
-
-/// Try to read full credentials info (token + subscription type) from a path
-fn try_read_credentials_info(path: &PathBuf) -> Option<CredentialsInfo> {
-    if !path.exists() {
-        return None;
-    }
-    let content = fs::read_to_string(path).ok()?;
-    let creds: ClaudeCodeCredentials = serde_json::from_str(&content).ok()?;
-    let token = extract_token_from_creds(&creds)?;
-
-    // Extract subscription type from nested format
-    let subscription_type = creds
-        .claude_ai_oauth
-        .as_ref()
-        .and_then(|oauth| oauth.subscription_type.clone());
-
-    Some(CredentialsInfo {
-        token,
-        subscription_type,
-    })
-}
-
-/// Get all possible credential paths for the current OS
-fn get_credential_paths() -> Vec<PathBuf> {
-    let mut paths: Vec<PathBuf> = Vec::new();
-
-    if let Some(home) = dirs::home_dir() {
-        // Primary: ~/.claude/.credentials.json
-        paths.push(home.join(".claude").join(".credentials.json"));
-        // Legacy: ~/.claude/credentials.json
-        paths.push(home.join(".claude").join("credentials.json"));
-        // Alternative: ~/.config/claude-code/auth.json
-        paths.push(home.join(".config").join("claude-code").join("auth.json"));
-    }
-
-    // Windows-specific paths
-    #[cfg(target_os = "windows")]
-    {
-        if let Ok(appdata) = env::var("APPDATA") {
-            // VS Code extension storage
-            paths.push(
-                PathBuf::from(&appdata)
-                    .join("Code")
-                    .join("User")
-                    .join("globalStorage")
-                    .join("anthropic.claude-code")
-                    .join("credentials.json"),
-            );
-        }
-        if let Ok(localappdata) = env::var("LOCALAPPDATA") {
-            paths.push(
-                PathBuf::from(&localappdata)
-                    .join("claude-code")
-                    .join("credentials.json"),
-            );
-        }
-    }
-
-    // Linux XDG paths
-    #[cfg(target_os = "linux")]
-    {
-        if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
-            paths.insert(
-                2,
-                PathBuf::from(&xdg_config)
-                    .join("claude-code")
-                    .join("auth.json"),
-            );
-        }
-    }
-
-    // macOS specific
-    #[cfg(target_os = "macos")]
-    {
-        if let Some(home) = dirs::home_dir() {
-            paths.push(
-                home.join("Library")
-                    .join("Application Support")
-                    .join("claude-code")
-                    .join("credentials.json"),
-            );
-        }
-    }
-
-    paths
-}
-
-/// Get Claude Code OAuth token from various sources
-fn get_claude_code_oauth_token_with_custom(custom_path: Option<&str>) -> Option<String> {
-    // 1. Custom path (priority)
-    if let Some(path) = custom_path {
-        if let Some(token) = try_read_credentials(&PathBuf::from(path)) {
-            return Some(token);
-        }
-    }
-
-    // 2. Environment variable
-    if let Ok(token) = env::var("CLAUDE_CODE_OAUTH_TOKEN") {
-        if !token.is_empty() {
-            return Some(token);
-        }
-    }
-
-    // 3. Auto-detect paths
-    for path in get_credential_paths() {
-        if let Some(token) = try_read_credentials(&path) {
-            return Some(token);
-        }
-    }
-
-    None
-}
-
-/// Get Claude Code OAuth token (legacy function for backward compatibility)
-fn get_claude_code_oauth_token() -> Option<String> {
-    get_claude_code_oauth_token_with_custom(None)
-}
-
-/// Get full credentials info (token + subscription type)
-fn get_claude_code_credentials_info() -> Option<CredentialsInfo> {
-    // Try auto-detect paths
-    for path in get_credential_paths() {
-        if let Some(info) = try_read_credentials_info(&path) {
-            return Some(info);
-        }
-    }
-    None
-}
-
-/// Get detected config source for UI display
-fn get_detected_config_source(custom_path: Option<&str>) -> String {
-    // 1. Custom path
-    if let Some(path) = custom_path {
-        if try_read_credentials(&PathBuf::from(path)).is_some() {
-            return format!("custom:{}", path);
-        }
-    }
-
-    // 2. Environment variable
-    if let Ok(token) = env::var("CLAUDE_CODE_OAUTH_TOKEN") {
-        if !token.is_empty() {
-            return "env:CLAUDE_CODE_OAUTH_TOKEN".to_string();
-        }
-    }
-
-    // 3. Auto-detect paths
-    for path in get_credential_paths() {
-        if try_read_credentials(&path).is_some() {
-            return format!("auto:{}", path.display());
-        }
-    }
-
-    "none".to_string()
-}
-
-/// Fetch usage from Claude Code OAuth API
-async fn fetch_claude_code_usage(token: &str) -> Result<ClaudeUsageResponse, AppError> {
-    let client = reqwest::Client::new();
-
-    let response = client
-        .get("https://api.anthropic.com/api/oauth/usage")
-        .header("Authorization", format!("Bearer {}", token))
-        .header("anthropic-beta", "oauth-2025-04-20")
-        .header("User-Agent", "claude-code/2.0.32")
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-        .map_err(|e| AppError::NetworkError(e.to_string()))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(AppError::ApiError(format!(
-            "API returned {}: {}",
-            status, body
-        )));
-    }
-
-    let usage: ClaudeUsageResponse = response
-        .json()
-        .await
-        .map_err(|e| AppError::ApiError(format!("Failed to parse response: {}", e)))?;
-
-    Ok(usage)
-}
+mod api_server;
+mod credential_watcher;
+mod proxy_server;
+mod scheduler;
+mod tray;
+
+use meter_ai::accounts::{self, ProviderAccount};
+use meter_ai::budgets::{self, BudgetStatus, Budgets};
+use meter_ai::counters::{self, Counter, CounterSettings};
+use meter_ai::forecast::{self, Forecast};
+use meter_ai::history::{self, HistoryBucket, UsageAggregate, UsageSample};
+use meter_ai::http::{self, Endpoint, EndpointConfig};
+use meter_ai::i18n::{self, tr, Locale};
+use meter_ai::local_api;
+use meter_ai::metering_proxy::{MeteredTotals, MeteringProxyConfig};
+use meter_ai::metrics;
+use meter_ai::pricing::{CostEstimate, PricingCatalog, TokenCounts};
+use meter_ai::mock_server;
+use meter_ai::providers::claude_code::{
+    fetch_claude_code_usage, fetch_claude_code_usage_internal, ClaudeCodeUsageResult,
+};
+use meter_ai::providers::openai::{fetch_openai_usage, OpenAIUsageResult};
+use meter_ai::providers::{self, ProviderDescriptor, ProviderRegistry, UsageReport};
+use meter_ai::reset_policy::ResetPolicy;
+use meter_ai::token_store::{self, TokenAccount, TokenChangeEntry, TokenHistory, TokenStatus};
+use meter_ai::transcripts::{self, IndexSummary, TokenGroup, TokenUsageReport};
+use meter_ai::webhooks::{self, WebhookTarget};
+use meter_ai::{
+    apply_claude_code_result, delete_api_key, get_claude_code_credentials_info,
+    get_claude_code_oauth_token, get_claude_code_oauth_token_with_custom,
+    get_detected_config_source, load_state, notify_error, raise_alert, save_api_key, save_state, store_provider_api_key,
+    ApiServerConfig, AppError, AppState, ClaudeCodeCredentials, CredentialsInfo, ProviderConfig,
+    ProviderType, UsageData,
+};
 
 // ============== NOTIFICATIONS ==============
 
-fn send_notification(title: &str, body: &str) {
-    Notification::new()
-        .summary(title)
-        .body(body)
-        .appname("MeterAI")
-        .timeout(5000)
-        .show()
-        .ok();
+/// Mute notifications until `until` (None resumes them)
+fn pause_notifications(app: &AppHandle, until: Option<i64>) {
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    meter_ai::pause_notifications(&mut state, until);
+    tray::update(app, &state);
 }
 
-fn check_and_notify(provider: &mut ProviderUsage) {
-    let percent = provider.usage.percent;
+// ============== EVENTS ==============
 
-    for threshold in &provider.config.alert_thresholds {
-        if percent >= *threshold && !provider.notified_thresholds.contains(threshold) {
-            provider.notified_thresholds.push(*threshold);
-
-            let provider_name = &provider.config.name;
-            let (title, body) = if *threshold >= 100 {
-                (
-                    format!("⚠️ {} - Limite atteinte!", provider_name),
-                    "Vous avez utilisé 100% de votre quota.".to_string(),
-                )
-            } else {
-                (
-                    format!("⚡ {} - {}%", provider_name, threshold),
-                    format!(
-                        "Vous avez utilisé {} requêtes sur {}.",
-                        provider.usage.used, provider.usage.limit
-                    ),
-                )
-            };
-
-            send_notification(&title, &body);
-        }
-    }
+/// `usage-updated` for the window and the local HTTP API
+fn emit_usage(app: &AppHandle, usage: &UsageData) {
+    app.emit_all("usage-updated", usage).ok();
+    api_server::broadcast("usage-updated", usage);
 }
 
 // ============== COMMANDS ==============
@@ -597,8 +80,8 @@ fn get_usage(state: tauri::State<Mutex<AppState>>) -> UsageData {
         .get(active)
         .map(|p| p.usage.clone())
         .unwrap_or_else(|| UsageData {
-            used: 0,
-            limit: 100,
+            used: 0.0,
+            limit: 100.0,
             percent: 0,
             reset_time: Utc::now().timestamp() + 4 * 3600,
             history: vec![],
@@ -607,13 +90,17 @@ fn get_usage(state: tauri::State<Mutex<AppState>>) -> UsageData {
         })
 }
 
+/// Configuration of the built-in entries (one per type, the frontend looks
+/// them up by `provider_type`). Added accounts are listed by
+/// `list_provider_accounts`.
 #[tauri::command]
 fn get_all_providers(state: tauri::State<Mutex<AppState>>) -> Vec<ProviderConfig> {
     let state = state.lock().unwrap();
     state
         .providers
-        .values()
-        .map(|p| {
+        .iter()
+        .filter(|(id, _)| accounts::is_builtin(id))
+        .map(|(_, p)| {
             let mut config = p.config.clone();
             config.api_key = None; // Never send API keys to frontend
             config
@@ -637,12 +124,13 @@ fn set_active_provider(
     if state.providers.contains_key(&provider_id) {
         state.active_provider = provider_id.clone();
         save_state(&state);
+        tray::update(&window.app_handle(), &state);
         if let Some(provider) = state.providers.get(&provider_id) {
-            window.emit("usage-updated", provider.usage.clone()).ok();
+            emit_usage(&window.app_handle(), &provider.usage);
         }
         Ok(())
     } else {
-        Err(AppError::ConfigError("Provider not found".to_string()))
+        Err(AppError::ConfigError(tr!("error.provider_not_found")))
     }
 }
 
@@ -651,22 +139,28 @@ fn set_active_provider(
 fn configure_provider(
     provider_id: String,
     api_key: Option<String>,
-    limit: u32,
+    limit: f64,
     alert_thresholds: Vec<u32>,
     reset_interval_hours: u32,
     enabled: bool,
     state: tauri::State<Mutex<AppState>>,
+    registry: tauri::State<ProviderRegistry>,
     window: Window,
 ) -> Result<(), AppError> {
     let mut state = state.lock().unwrap();
 
-    if !state.providers.contains_key(&provider_id) {
-        return Err(AppError::ConfigError("Provider not found".to_string()));
-    }
+    let provider_type = state
+        .providers
+        .get(&provider_id)
+        .map(|p| p.config.provider_type.clone())
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
 
     // Save API key securely if provided
     if let Some(key) = &api_key {
         if !key.is_empty() {
+            if let Some(backend) = registry.get(&provider_type) {
+                backend.check_api_key_format(key)?;
+            }
             save_api_key(&provider_id, key)?;
         }
     }
@@ -688,15 +182,15 @@ fn configure_provider(
         provider.config.reset_interval_hours = reset_interval_hours;
         provider.config.enabled = enabled;
         provider.usage.limit = limit;
-        provider.usage.percent =
-            ((provider.usage.used as f64 / provider.usage.limit as f64) * 100.0) as u32;
+        provider.usage.refresh_percent();
         usage_data = provider.usage.clone();
     }
 
     save_state(&state);
+    tray::update(&window.app_handle(), &state);
 
     if should_emit {
-        window.emit("usage-updated", usage_data).ok();
+        emit_usage(&window.app_handle(), &usage_data);
     }
 
     Ok(())
@@ -716,108 +210,40 @@ fn remove_api_key(
         save_state(&state);
         Ok(())
     } else {
-        Err(AppError::ConfigError("Provider not found".to_string()))
+        Err(AppError::ConfigError(tr!("error.provider_not_found")))
     }
 }
 
 #[tauri::command]
-fn add_request(count: u32, state: tauri::State<Mutex<AppState>>, window: Window) {
-    let mut state = state.lock().unwrap();
-    let active = state.active_provider.clone();
-
-    if !state.providers.contains_key(&active) {
-        return;
-    }
-
-    let usage_data = {
-        let provider = state.providers.get_mut(&active).unwrap();
-
-        // Check if reset needed
-        let now = Utc::now().timestamp();
-        if now >= provider.usage.reset_time {
-            // Save to history
-            let time_str = Local::now().format("%H:%M").to_string();
-            provider.usage.history.insert(
-                0,
-                HistoryEntry {
-                    time: time_str,
-                    used: provider.usage.used,
-                    limit: provider.usage.limit,
-                },
-            );
-            if provider.usage.history.len() > 6 {
-                provider.usage.history.pop();
-            }
-
-            // Reset
-            provider.usage.used = 0;
-            provider.usage.reset_time =
-                now + (provider.config.reset_interval_hours as i64 * 3600);
-            provider.notified_thresholds.clear();
-
-            send_notification(
-                &format!("🔄 {} - Quota réinitialisé!", provider.config.name),
-                &format!(
-                    "Votre quota de {} requêtes est à nouveau disponible.",
-                    provider.config.limit
-                ),
-            );
-        }
-
-        // Add requests
-        provider.usage.used = (provider.usage.used + count).min(provider.usage.limit);
-        provider.usage.percent =
-            ((provider.usage.used as f64 / provider.usage.limit as f64) * 100.0) as u32;
-
-        // Check notifications
-        check_and_notify(provider);
-
-        provider.usage.clone()
-    };
-
-    // Save and emit (outside the borrow scope)
-    save_state(&state);
-    window.emit("usage-updated", usage_data).ok();
+fn add_request(count: u32, window: Window) {
+    add_requests(&window.app_handle(), count);
 }
 
 #[tauri::command]
-fn reset_usage(state: tauri::State<Mutex<AppState>>, window: Window) {
-    let mut state = state.lock().unwrap();
-    let active = state.active_provider.clone();
+fn reset_usage(window: Window) {
+    reset_active_usage(&window.app_handle());
+}
 
-    if !state.providers.contains_key(&active) {
-        return;
+/// Add `count` requests to the active provider (command and tray menu)
+fn add_requests(app: &AppHandle, count: u32) {
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    if let Some((usage_data, alerts)) = meter_ai::add_requests(&mut state, count) {
+        tray::update(app, &state);
+        emit_usage(app, &usage_data);
+        drop(state);
+        alerts.into_iter().for_each(raise_alert);
     }
+}
 
-    let usage_data = {
-        let provider = state.providers.get_mut(&active).unwrap();
-
-        // Save to history
-        let time_str = Local::now().format("%H:%M").to_string();
-        provider.usage.history.insert(
-            0,
-            HistoryEntry {
-                time: time_str,
-                used: provider.usage.used,
-                limit: provider.usage.limit,
-            },
-        );
-        if provider.usage.history.len() > 6 {
-            provider.usage.history.pop();
-        }
-
-        // Reset
-        provider.usage.used = 0;
-        provider.usage.percent = 0;
-        provider.usage.reset_time =
-            Utc::now().timestamp() + (provider.config.reset_interval_hours as i64 * 3600);
-        provider.notified_thresholds.clear();
-
-        provider.usage.clone()
-    };
-
-    save_state(&state);
-    window.emit("usage-updated", usage_data).ok();
+/// Reset the counter of the active provider (command and tray menu)
+fn reset_active_usage(app: &AppHandle) {
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    if let Some(usage_data) = meter_ai::reset_active_usage(&mut state) {
+        tray::update(app, &state);
+        emit_usage(app, &usage_data);
+    }
 }
 
 // Legacy command for backward compatibility
@@ -838,7 +264,12 @@ fn get_settings(state: tauri::State<Mutex<AppState>>) -> ProviderConfig {
 
 /// Get Claude Code usage from OAuth API (for Pro/Max plans)
 #[tauri::command]
-async fn get_claude_code_usage() -> ClaudeCodeUsageResult {
+async fn get_claude_code_usage(
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<ClaudeCodeUsageResult, String> {
+    let settings = state.lock().unwrap().settings.clone();
+
     // Try to get credentials info (token + subscription type)
     let creds_info = match get_claude_code_credentials_info() {
         Some(info) => info,
@@ -850,44 +281,33 @@ async fn get_claude_code_usage() -> ClaudeCodeUsageResult {
                     subscription_type: None,
                 },
                 None => {
-                    return ClaudeCodeUsageResult {
-                        success: false,
-                        error: Some("Token OAuth Claude Code non trouvé. Vérifiez que Claude Code est connecté.".to_string()),
-                        five_hour_percent: None,
-                        five_hour_reset: None,
-                        seven_day_percent: None,
-                        seven_day_reset: None,
-                        subscription_type: None,
-                    };
+                    return Ok(ClaudeCodeUsageResult::failed(
+                        tr!("error.claude_token_not_found"),
+                    ));
                 }
             }
         }
     };
 
+    let endpoint = match Endpoint::anthropic(&settings) {
+        Ok(endpoint) => endpoint,
+        Err(e) => return Ok(ClaudeCodeUsageResult::failed(e.to_string())),
+    };
+
     // Fetch usage from API
-    match fetch_claude_code_usage(&creds_info.token).await {
+    match fetch_claude_code_usage(&endpoint, &creds_info.token).await {
         Ok(usage) => {
-            // API returns utilization already as percentage (0-100), no need to multiply
-            ClaudeCodeUsageResult {
-                success: true,
-                error: None,
-                five_hour_percent: usage.five_hour.as_ref().map(|w| w.utilization),
-                five_hour_reset: usage.five_hour.and_then(|w| w.resets_at),
-                seven_day_percent: usage.seven_day.as_ref().map(|w| w.utilization),
-                seven_day_reset: usage.seven_day.and_then(|w| w.resets_at),
-                subscription_type: creds_info.subscription_type,
-            }
+            let result = ClaudeCodeUsageResult::from_response(usage, creds_info.subscription_type);
+            let mut state = state.lock().unwrap();
+            let alerts = apply_claude_code_result(&mut state, &result);
+            tray::update(&app, &state);
+            drop(state);
+            alerts.into_iter().for_each(raise_alert);
+            Ok(result)
         }
         Err(e) => {
-            ClaudeCodeUsageResult {
-                success: false,
-                error: Some(e.to_string()),
-                five_hour_percent: None,
-                five_hour_reset: None,
-                seven_day_percent: None,
-                seven_day_reset: None,
-                subscription_type: None,
-            }
+            metrics::record_fetch_error("anthropic", &e);
+            Ok(ClaudeCodeUsageResult::failed(e.to_string()))
         }
     }
 }
@@ -900,7 +320,7 @@ fn has_claude_code_token() -> bool {
 
 #[tauri::command]
 fn save_settings(
-    limit: u32,
+    limit: f64,
     alert_thresholds: Vec<u32>,
     reset_interval_hours: u32,
     state: tauri::State<Mutex<AppState>>,
@@ -919,13 +339,13 @@ fn save_settings(
         provider.config.alert_thresholds = alert_thresholds;
         provider.config.reset_interval_hours = reset_interval_hours;
         provider.usage.limit = limit;
-        provider.usage.percent =
-            ((provider.usage.used as f64 / provider.usage.limit as f64) * 100.0) as u32;
+        provider.usage.refresh_percent();
         provider.usage.clone()
     };
 
     save_state(&state);
-    window.emit("usage-updated", usage_data).ok();
+    tray::update(&window.app_handle(), &state);
+    emit_usage(&window.app_handle(), &usage_data);
 }
 
 // ============== AUTOSTART (Windows) ==============
@@ -972,17 +392,14 @@ fn set_autostart_enabled(enabled: bool) -> Result<(), String> {
             if enabled {
                 // Use the standard install location: %LOCALAPPDATA%\MeterAI\MeterAI.exe
                 let local_app_data = std::env::var("LOCALAPPDATA")
-                    .map_err(|_| "Could not find LOCALAPPDATA environment variable")?;
+                    .map_err(|_| tr!("error.localappdata_missing"))?;
                 let installed_path = std::path::PathBuf::from(&local_app_data)
                     .join("MeterAI")
                     .join("MeterAI.exe");
 
                 // Check if the installed version exists
                 if !installed_path.exists() {
-                    return Err(format!(
-                        "MeterAI is not installed. Please install the application first.\nExpected path: {}",
-                        installed_path.display()
-                    ));
+                    return Err(tr!("error.not_installed", path = installed_path.display()));
                 }
 
                 run_key
@@ -1041,7 +458,7 @@ async fn browse_credentials_file() -> Result<Option<String>, String> {
         // Validate file
         let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
         let _: ClaudeCodeCredentials = serde_json::from_str(&content)
-            .map_err(|_| "Invalid file: incorrect JSON format or missing fields".to_string())?;
+            .map_err(|_| tr!("error.invalid_credentials_file"))?;
 
         Ok(Some(path.to_string_lossy().to_string()))
     } else {
@@ -1054,10 +471,14 @@ async fn browse_credentials_file() -> Result<Option<String>, String> {
 fn set_custom_credentials_path(
     path: Option<String>,
     state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
 ) -> Result<(), String> {
-    let mut state = state.lock().unwrap();
-    state.settings.custom_credentials_path = path;
-    save_state(&state);
+    {
+        let mut state = state.lock().unwrap();
+        state.settings.custom_credentials_path = path;
+        save_state(&state);
+    }
+    credential_watcher::restart(&app);
     Ok(())
 }
 
@@ -1067,717 +488,508 @@ fn get_custom_credentials_path(state: tauri::State<Mutex<AppState>>) -> Option<S
     state.lock().unwrap().settings.custom_credentials_path.clone()
 }
 
-// ============== INTERNAL TOKEN STORAGE ==============
+// ============== ENDPOINT SETTINGS ==============
 
-/// Stored token data (internal copy of Claude Code credentials)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StoredTokenData {
-    /// The actual token (stored encrypted via keyring)
-    #[serde(skip)]
-    pub token: Option<String>,
-    /// SHA256 hash of the token (first 16 chars for display)
-    pub token_hash: String,
-    /// When the token was copied to internal storage
-    pub copied_at: String,
-    /// Token expiration time (if available from source)
-    pub expires_at: Option<String>,
-    /// Source path where the token was copied from
-    pub source_path: Option<String>,
-    /// Refresh token (if available)
-    #[serde(skip)]
-    pub refresh_token: Option<String>,
-}
-
-/// Token change history entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenChangeEntry {
-    pub timestamp: String,
-    pub changed: bool,
-    pub old_hash: Option<String>,
-    pub new_hash: Option<String>,
-    pub source: String,
+/// Get base URL / header / proxy overrides for all endpoints
+#[tauri::command]
+fn get_endpoint_configs(state: tauri::State<Mutex<AppState>>) -> HashMap<String, EndpointConfig> {
+    state.lock().unwrap().settings.endpoints.clone()
 }
 
-/// Token status for UI display
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenStatus {
-    /// Whether internal token exists
-    pub has_internal_token: bool,
-    /// Masked token preview (e.g., "sk-ant-...xxxx")
-    pub token_preview: Option<String>,
-    /// Token hash (first 16 chars)
-    pub token_hash: Option<String>,
-    /// When copied
-    pub copied_at: Option<String>,
-    /// Expiration
-    pub expires_at: Option<String>,
-    /// Source used
-    pub source: String,
-    /// Whether source token differs from internal
-    pub source_differs: bool,
-    /// Source token hash (for comparison)
-    pub source_hash: Option<String>,
+/// Set (or clear with `None`) the overrides of one endpoint
+#[tauri::command]
+fn set_endpoint_config(
+    endpoint: String,
+    config: Option<EndpointConfig>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    match config {
+        Some(config) => {
+            // Reject unusable overrides before they break every fetch
+            let mut probe = state.settings.clone();
+            probe.endpoints.insert(endpoint.clone(), config.clone());
+            Endpoint::resolve(&probe, &endpoint, "http://localhost")?;
+            state.settings.endpoints.insert(endpoint, config);
+        }
+        None => {
+            state.settings.endpoints.remove(&endpoint);
+        }
+    }
+    webhooks::configure(&state.settings);
+    save_state(&state);
+    Ok(())
 }
 
-/// Token history data
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct TokenHistory {
-    pub entries: Vec<TokenChangeEntry>,
-    pub last_check: Option<String>,
+/// Enable/disable mock mode (takes effect on next start)
+#[tauri::command]
+fn set_mock_mode(enabled: bool, state: tauri::State<Mutex<AppState>>) {
+    let mut state = state.lock().unwrap();
+    state.settings.mock_mode = enabled;
+    save_state(&state);
 }
 
-/// Get path for internal token metadata
-fn get_internal_token_path() -> PathBuf {
-    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push("meter-ai");
-    fs::create_dir_all(&path).ok();
-    path.push("token_metadata.json");
-    path
+/// Base URL of the running mock server, if mock mode is active
+#[tauri::command]
+fn get_mock_backend_url() -> Option<String> {
+    http::mock_base_url().map(|url| url.to_string())
 }
 
-/// Get path for token history
-fn get_token_history_path() -> PathBuf {
-    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-    path.push("meter-ai");
-    fs::create_dir_all(&path).ok();
-    path.push("token_history.json");
-    path
+/// Mute notifications for `minutes` (0 resumes them)
+#[tauri::command]
+fn set_notifications_paused(minutes: u32, app: AppHandle) {
+    let until = (minutes > 0).then(|| Utc::now().timestamp() + minutes as i64 * 60);
+    pause_notifications(&app, until);
 }
 
-/// Compute SHA256 hash of a string, return first 16 hex chars
-fn compute_token_hash(token: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(token.as_bytes());
-    let result = hasher.finalize();
-    hex::encode(&result[..8]) // First 8 bytes = 16 hex chars
+/// Effective locale (configured or detected from the OS)
+#[tauri::command]
+fn get_locale() -> Locale {
+    i18n::current_locale()
 }
 
-/// Create masked token preview (e.g., "sk-ant-oaut01-...xxxx")
-fn mask_token(token: &str) -> String {
-    if token.len() <= 20 {
-        return "*".repeat(token.len());
-    }
-    let prefix = &token[..15];
-    let suffix = &token[token.len()-4..];
-    format!("{}...{}", prefix, suffix)
-}
-
-/// Save token to secure storage (keyring)
-fn save_internal_token(token: &str, refresh_token: Option<&str>) -> Result<(), AppError> {
-    let entry = keyring::Entry::new("meter-ai", "claude-internal-token")
-        .map_err(|e| AppError::KeyringError(e.to_string()))?;
-    entry
-        .set_password(token)
-        .map_err(|e| AppError::KeyringError(e.to_string()))?;
-
-    // Save refresh token if provided
-    if let Some(rt) = refresh_token {
-        if let Ok(rt_entry) = keyring::Entry::new("meter-ai", "claude-internal-refresh") {
-            rt_entry.set_password(rt).ok();
-        }
-    }
+/// Set the locale (`None` follows the OS locale) and return the effective one
+#[tauri::command]
+fn set_locale(locale: Option<Locale>, state: tauri::State<Mutex<AppState>>) -> Locale {
+    let mut state = state.lock().unwrap();
+    state.settings.locale = locale;
+    save_state(&state);
 
-    Ok(())
+    let effective = locale.unwrap_or_else(Locale::system);
+    i18n::set_locale(effective);
+    effective
 }
 
-/// Load token from secure storage
-fn load_internal_token() -> Option<String> {
-    let entry = keyring::Entry::new("meter-ai", "claude-internal-token").ok()?;
-    entry.get_password().ok()
+// ============== USAGE HISTORY COMMANDS ==============
+
+/// Raw samples in `[from, to]` (unix seconds, defaults to the last 24 hours)
+#[tauri::command]
+fn get_usage_history(
+    provider_id: Option<String>,
+    window: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Vec<UsageSample> {
+    let to = to.unwrap_or_else(|| Utc::now().timestamp());
+    let from = from.unwrap_or(to - 24 * 3600);
+    history::query(provider_id.as_deref(), window.as_deref(), from, to)
+}
+
+/// Hourly/daily/weekly aggregates in `[from, to]` (defaults to the last 30 days)
+#[tauri::command]
+fn get_usage_aggregates(
+    provider_id: Option<String>,
+    window: Option<String>,
+    bucket: HistoryBucket,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Vec<UsageAggregate> {
+    let to = to.unwrap_or_else(|| Utc::now().timestamp());
+    let from = from.unwrap_or(to - 30 * 86_400);
+    history::aggregate(provider_id.as_deref(), window.as_deref(), bucket, from, to)
 }
 
-/// Load refresh token from secure storage
-fn load_internal_refresh_token() -> Option<String> {
-    let entry = keyring::Entry::new("meter-ai", "claude-internal-refresh").ok()?;
-    entry.get_password().ok()
+#[tauri::command]
+fn get_history_retention(state: tauri::State<Mutex<AppState>>) -> u32 {
+    state.lock().unwrap().settings.history_retention_days
 }
 
-/// Delete internal token from secure storage
-fn delete_internal_token() -> Result<(), AppError> {
-    if let Ok(entry) = keyring::Entry::new("meter-ai", "claude-internal-token") {
-        entry.delete_password().ok();
-    }
-    if let Ok(entry) = keyring::Entry::new("meter-ai", "claude-internal-refresh") {
-        entry.delete_password().ok();
-    }
-    // Also delete metadata file
-    let path = get_internal_token_path();
-    if path.exists() {
-        fs::remove_file(path).ok();
+/// Set the retention (0 keeps everything) and prune immediately.
+/// Returns the number of samples removed.
+#[tauri::command]
+fn set_history_retention(days: u32, state: tauri::State<Mutex<AppState>>) -> Result<usize, AppError> {
+    {
+        let mut state = state.lock().unwrap();
+        state.settings.history_retention_days = days;
+        save_state(&state);
     }
-    Ok(())
+    history::apply_retention(days)
 }
 
-/// Save token metadata (non-sensitive data)
-fn save_token_metadata(data: &StoredTokenData) -> Result<(), AppError> {
-    let path = get_internal_token_path();
-    let json = serde_json::to_string_pretty(data)
-        .map_err(|e| AppError::ConfigError(e.to_string()))?;
-    fs::write(path, json)
-        .map_err(|e| AppError::ConfigError(e.to_string()))?;
-    Ok(())
-}
+// ============== FORECASTS ==============
 
-/// Load token metadata
-fn load_token_metadata() -> Option<StoredTokenData> {
-    let path = get_internal_token_path();
-    if !path.exists() {
-        return None;
-    }
-    let content = fs::read_to_string(path).ok()?;
-    let mut data: StoredTokenData = serde_json::from_str(&content).ok()?;
-    // Load actual token from keyring
-    data.token = load_internal_token();
-    data.refresh_token = load_internal_refresh_token();
-    Some(data)
-}
-
-/// Load token history
-fn load_token_history() -> TokenHistory {
-    let path = get_token_history_path();
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(&path) {
-            if let Ok(history) = serde_json::from_str(&content) {
-                return history;
-            }
-        }
+/// Burn rate and projected exhaustion of the windows of `provider_id`, or of
+/// every enabled provider
+#[tauri::command]
+fn get_usage_forecast(provider_id: Option<String>, state: tauri::State<Mutex<AppState>>) -> Vec<Forecast> {
+    let state = state.lock().unwrap();
+    let now = Utc::now().timestamp();
+    match provider_id {
+        Some(provider_id) => forecast::forecast(&state, &provider_id, now),
+        None => forecast::forecast_all(&state, now),
     }
-    TokenHistory::default()
 }
 
-/// Save token history
-fn save_token_history(history: &TokenHistory) -> Result<(), AppError> {
-    let path = get_token_history_path();
-    let json = serde_json::to_string_pretty(history)
-        .map_err(|e| AppError::ConfigError(e.to_string()))?;
-    fs::write(path, json)
-        .map_err(|e| AppError::ConfigError(e.to_string()))?;
-    Ok(())
+#[tauri::command]
+fn get_forecast_alerts(state: tauri::State<Mutex<AppState>>) -> bool {
+    state.lock().unwrap().settings.forecast_alerts
 }
 
-/// Read full credentials from source file (for export)
-fn read_source_credentials(custom_path: Option<&str>) -> Option<(String, ClaudeCodeCredentials)> {
-    // Try custom path first
-    if let Some(path) = custom_path {
-        let path_buf = PathBuf::from(path);
-        if path_buf.exists() {
-            if let Ok(content) = fs::read_to_string(&path_buf) {
-                if let Ok(creds) = serde_json::from_str::<ClaudeCodeCredentials>(&content) {
-                    return Some((path.to_string(), creds));
-                }
-            }
-        }
-    }
+/// Alert when a window is projected to run out before its reset
+#[tauri::command]
+fn set_forecast_alerts(enabled: bool, state: tauri::State<Mutex<AppState>>) {
+    let mut state = state.lock().unwrap();
+    state.settings.forecast_alerts = enabled;
+    save_state(&state);
+}
 
-    // Try auto-detect paths
-    for path in get_credential_paths() {
-        if path.exists() {
-            if let Ok(content) = fs::read_to_string(&path) {
-                if let Ok(creds) = serde_json::from_str::<ClaudeCodeCredentials>(&content) {
-                    return Some((path.to_string_lossy().to_string(), creds));
-                }
-            }
-        }
-    }
+// ============== BUDGETS ==============
 
-    None
+#[tauri::command]
+fn get_budgets(state: tauri::State<Mutex<AppState>>) -> Budgets {
+    state.lock().unwrap().settings.budgets.clone()
 }
 
-/// Copy token from source to internal storage
 #[tauri::command]
-fn copy_token_to_internal(state: tauri::State<Mutex<AppState>>) -> Result<TokenStatus, String> {
-    let state = state.lock().unwrap();
-    let custom_path = state.settings.custom_credentials_path.as_deref();
-
-    // Read source credentials
-    let (source_path, creds) = read_source_credentials(custom_path)
-        .ok_or("No Claude Code credentials found. Please ensure Claude Code is installed and logged in.")?;
-
-    // Extract token
-    let token = extract_token_from_creds(&creds)
-        .ok_or("Token not found in credentials file")?;
-
-    // Extract refresh token and expiration
-    let (refresh_token, expires_at) = if let Some(ref oauth) = creds.claude_ai_oauth {
-        (
-            oauth.refresh_token.clone(),
-            oauth.expires_at.map(|ts| {
-                DateTime::from_timestamp(ts, 0)
-                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                    .unwrap_or_else(|| ts.to_string())
-            })
-        )
-    } else {
-        (creds.refresh_token.clone(), creds.expires_at.map(|ts| {
-            DateTime::from_timestamp(ts, 0)
-                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                .unwrap_or_else(|| ts.to_string())
-        }))
-    };
+fn set_budgets(budgets: Budgets, state: tauri::State<Mutex<AppState>>) -> Result<(), AppError> {
+    budgets.validate()?;
+    let mut state = state.lock().unwrap();
+    state.settings.budgets = budgets;
+    save_state(&state);
+    Ok(())
+}
 
-    // Compute hash
-    let token_hash = compute_token_hash(&token);
-
-    // Check if this is a change from existing internal token
-    let old_metadata = load_token_metadata();
-    let changed = old_metadata.as_ref()
-        .map(|m| m.token_hash != token_hash)
-        .unwrap_or(true);
-
-    // Log change if applicable
-    if changed {
-        let mut history = load_token_history();
-        history.entries.push(TokenChangeEntry {
-            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-            changed: true,
-            old_hash: old_metadata.as_ref().map(|m| m.token_hash.clone()),
-            new_hash: Some(token_hash.clone()),
-            source: source_path.clone(),
-        });
-        // Keep only last 100 entries
-        if history.entries.len() > 100 {
-            history.entries = history.entries.split_off(history.entries.len() - 100);
-        }
-        history.last_check = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
-        save_token_history(&history).ok();
-    }
+/// Spend, projection and budget of the month: global first, then per provider
+#[tauri::command]
+fn get_budget_status(state: tauri::State<Mutex<AppState>>) -> Vec<BudgetStatus> {
+    budgets::status_all(&state.lock().unwrap(), Utc::now().timestamp())
+}
 
-    // Save to keyring
-    save_internal_token(&token, refresh_token.as_deref())
-        .map_err(|e| e.to_string())?;
+// ============== CLAUDE CODE TOKEN COMMANDS ==============
 
-    // Save metadata
-    let metadata = StoredTokenData {
-        token: Some(token.clone()),
-        token_hash: token_hash.clone(),
-        copied_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        expires_at: expires_at.clone(),
-        source_path: Some(source_path.clone()),
-        refresh_token,
-    };
-    save_token_metadata(&metadata).map_err(|e| e.to_string())?;
-
-    Ok(TokenStatus {
-        has_internal_token: true,
-        token_preview: Some(mask_token(&token)),
-        token_hash: Some(token_hash),
-        copied_at: Some(metadata.copied_at),
-        expires_at,
-        source: source_path,
-        source_differs: false,
-        source_hash: None,
+/// Token and cost totals from the local Claude Code transcripts in
+/// `[from, to]` (defaults to the last 30 days), read up to date first
+#[tauri::command]
+async fn get_claude_token_usage(
+    from: Option<i64>,
+    to: Option<i64>,
+    group: Option<TokenGroup>,
+) -> Result<TokenUsageReport, AppError> {
+    let to = to.unwrap_or_else(|| Utc::now().timestamp());
+    let from = from.unwrap_or(to - 30 * 86_400);
+    tauri::async_runtime::spawn_blocking(move || -> Result<TokenUsageReport, AppError> {
+        transcripts::index(false)?;
+        Ok(transcripts::report(from, to, group))
     })
+    .await
+    .map_err(|e| AppError::ConfigError(e.to_string()))?
 }
 
-/// Get current token status
+/// Forget the indexed totals and read every transcript again
 #[tauri::command]
-fn get_token_status(state: tauri::State<Mutex<AppState>>) -> TokenStatus {
-    let state = state.lock().unwrap();
-    let custom_path = state.settings.custom_credentials_path.as_deref();
-
-    // Load internal token metadata
-    let internal = load_token_metadata();
-
-    // Check source token
-    let source_info = read_source_credentials(custom_path);
-    let source_hash = source_info.as_ref()
-        .and_then(|(_, creds)| extract_token_from_creds(creds))
-        .map(|t| compute_token_hash(&t));
-
-    let source_path = source_info.as_ref()
-        .map(|(p, _)| p.clone())
-        .unwrap_or_else(|| "none".to_string());
-
-    if let Some(meta) = internal {
-        let source_differs = source_hash.as_ref()
-            .map(|sh| sh != &meta.token_hash)
-            .unwrap_or(false);
-
-        TokenStatus {
-            has_internal_token: true,
-            token_preview: meta.token.as_ref().map(|t| mask_token(t)),
-            token_hash: Some(meta.token_hash),
-            copied_at: Some(meta.copied_at),
-            expires_at: meta.expires_at,
-            source: source_path,
-            source_differs,
-            source_hash,
-        }
-    } else {
-        TokenStatus {
-            has_internal_token: false,
-            token_preview: None,
-            token_hash: None,
-            copied_at: None,
-            expires_at: None,
-            source: source_path,
-            source_differs: source_hash.is_some(),
-            source_hash,
-        }
-    }
+async fn reindex_claude_transcripts() -> Result<IndexSummary, AppError> {
+    tauri::async_runtime::spawn_blocking(|| transcripts::index(true))
+        .await
+        .map_err(|e| AppError::ConfigError(e.to_string()))?
 }
 
-/// Check if source token has changed and log it
-#[tauri::command]
-fn check_token_change(state: tauri::State<Mutex<AppState>>) -> Result<TokenChangeEntry, String> {
-    let state = state.lock().unwrap();
-    let custom_path = state.settings.custom_credentials_path.as_deref();
+// ============== MODEL PRICING ==============
 
-    let internal = load_token_metadata();
-    let source_info = read_source_credentials(custom_path);
+/// Built-in prices with the local `pricing.json` overrides applied
+#[tauri::command]
+fn get_pricing_catalog() -> PricingCatalog {
+    PricingCatalog::load()
+}
 
-    let source_hash = source_info.as_ref()
-        .and_then(|(_, creds)| extract_token_from_creds(creds))
-        .map(|t| compute_token_hash(&t));
+/// USD cost of `tokens` sent to `model` on `date` (today by default)
+#[tauri::command]
+fn estimate_token_cost(
+    provider: ProviderType,
+    model: String,
+    tokens: TokenCounts,
+    date: Option<NaiveDate>,
+    batch: Option<bool>,
+) -> Result<CostEstimate, AppError> {
+    let date = date.unwrap_or_else(|| Utc::now().date_naive());
+    PricingCatalog::load()
+        .cost(&provider, &model, &tokens, date, batch.unwrap_or(false))
+        .ok_or_else(|| AppError::ConfigError(tr!("error.no_model_price", model = model)))
+}
 
-    let source_path = source_info.as_ref()
-        .map(|(p, _)| p.clone())
-        .unwrap_or_else(|| "unknown".to_string());
+// ============== LOCAL HTTP API ==============
 
-    let internal_hash = internal.as_ref().map(|m| m.token_hash.clone());
+#[tauri::command]
+fn get_api_server_config(state: tauri::State<Mutex<AppState>>) -> ApiServerConfig {
+    state.lock().unwrap().settings.api_server.clone()
+}
 
-    let changed = match (&internal_hash, &source_hash) {
-        (Some(ih), Some(sh)) => ih != sh,
-        (None, Some(_)) => true,
-        _ => false,
+/// Enable/disable the local API or change its port, restarting it right away.
+/// A token is generated the first time it is enabled.
+#[tauri::command]
+async fn set_api_server_config(
+    enabled: bool,
+    port: u16,
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<ApiServerConfig, AppError> {
+    let config = {
+        let mut state = state.lock().unwrap();
+        let config = &mut state.settings.api_server;
+        config.enabled = enabled;
+        config.port = port;
+        if config.token.is_none() {
+            config.token = Some(local_api::generate_token());
+        }
+        let config = config.clone();
+        save_state(&state);
+        config
     };
+    api_server::apply(app, config.clone()).await?;
+    Ok(config)
+}
 
-    let entry = TokenChangeEntry {
-        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        changed,
-        old_hash: internal_hash,
-        new_hash: source_hash,
-        source: source_path,
+/// Replace the API token; clients using the old one are rejected from now on
+#[tauri::command]
+async fn regenerate_api_server_token(
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<ApiServerConfig, AppError> {
+    let config = {
+        let mut state = state.lock().unwrap();
+        state.settings.api_server.token = Some(local_api::generate_token());
+        save_state(&state);
+        state.settings.api_server.clone()
     };
+    api_server::apply(app, config.clone()).await?;
+    Ok(config)
+}
 
-    // Log this check
-    let mut history = load_token_history();
-    history.entries.push(entry.clone());
-    if history.entries.len() > 100 {
-        history.entries = history.entries.split_off(history.entries.len() - 100);
-    }
-    history.last_check = Some(entry.timestamp.clone());
-    save_token_history(&history).ok();
+// ============== METERING PROXY ==============
 
-    Ok(entry)
+#[tauri::command]
+fn get_metering_proxy_config(state: tauri::State<Mutex<AppState>>) -> MeteringProxyConfig {
+    state.lock().unwrap().settings.metering_proxy.clone()
 }
 
-/// Get token change history
+/// Save the proxy configuration and restart the proxy right away
 #[tauri::command]
-fn get_token_history() -> TokenHistory {
-    load_token_history()
+async fn set_metering_proxy_config(
+    config: MeteringProxyConfig,
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<MeteringProxyConfig, AppError> {
+    {
+        let mut state = state.lock().unwrap();
+        config.validate(&state)?;
+        state.settings.metering_proxy = config.clone();
+        save_state(&state);
+    }
+    proxy_server::apply(app).await?;
+    Ok(config)
 }
 
-/// Export token data (for transfer to another PC)
+/// Calls charged through the proxy, by provider ID
 #[tauri::command]
-fn export_token_data() -> Result<String, String> {
-    let metadata = load_token_metadata()
-        .ok_or("No internal token stored")?;
-
-    let token = metadata.token
-        .ok_or("Token not found in secure storage")?;
-
-    // Create export structure (similar to Claude Code credentials format)
-    let export_data = serde_json::json!({
-        "claudeAiOauth": {
-            "accessToken": token,
-            "refreshToken": metadata.refresh_token,
-            "expiresAt": metadata.expires_at,
-        },
-        "exportedFrom": "MeterAI",
-        "exportedAt": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-    });
-
-    serde_json::to_string_pretty(&export_data)
-        .map_err(|e| e.to_string())
+fn get_metered_totals(state: tauri::State<Mutex<AppState>>) -> HashMap<String, MeteredTotals> {
+    state
+        .lock()
+        .unwrap()
+        .providers
+        .iter()
+        .filter_map(|(id, p)| p.metered.clone().map(|totals| (id.clone(), totals)))
+        .collect()
 }
 
-/// Import token data (from another PC)
 #[tauri::command]
-fn import_token_data(json_data: String) -> Result<TokenStatus, String> {
-    // Parse the imported data
-    let creds: ClaudeCodeCredentials = serde_json::from_str(&json_data)
-        .map_err(|e| format!("Invalid JSON format: {}", e))?;
-
-    // Extract token
-    let token = extract_token_from_creds(&creds)
-        .ok_or("No access token found in imported data")?;
-
-    // Extract refresh token and expiration
-    let (refresh_token, expires_at) = if let Some(ref oauth) = creds.claude_ai_oauth {
-        (
-            oauth.refresh_token.clone(),
-            oauth.expires_at.map(|ts| {
-                DateTime::from_timestamp(ts, 0)
-                    .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                    .unwrap_or_else(|| ts.to_string())
-            })
-        )
-    } else {
-        (creds.refresh_token.clone(), creds.expires_at.map(|ts| {
-            DateTime::from_timestamp(ts, 0)
-                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
-                .unwrap_or_else(|| ts.to_string())
-        }))
-    };
+fn reset_metered_totals(provider_id: String, state: tauri::State<Mutex<AppState>>) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    let provider = state
+        .providers
+        .get_mut(&provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    provider.metered = None;
+    save_state(&state);
+    Ok(())
+}
 
-    // Compute hash
-    let token_hash = compute_token_hash(&token);
+// ============== WEBHOOKS ==============
 
-    // Save to keyring
-    save_internal_token(&token, refresh_token.as_deref())
-        .map_err(|e| e.to_string())?;
+/// Save the webhook targets and apply them to the next alerts
+fn store_webhooks(state: &mut AppState, targets: Vec<WebhookTarget>) -> Vec<WebhookTarget> {
+    state.settings.webhooks = targets;
+    webhooks::configure(&state.settings);
+    save_state(state);
+    state.settings.webhooks.clone()
+}
 
-    // Save metadata
-    let metadata = StoredTokenData {
-        token: Some(token.clone()),
-        token_hash: token_hash.clone(),
-        copied_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        expires_at: expires_at.clone(),
-        source_path: Some("imported".to_string()),
-        refresh_token,
-    };
-    save_token_metadata(&metadata).map_err(|e| e.to_string())?;
-
-    // Log import
-    let mut history = load_token_history();
-    history.entries.push(TokenChangeEntry {
-        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        changed: true,
-        old_hash: None,
-        new_hash: Some(token_hash.clone()),
-        source: "imported".to_string(),
-    });
-    save_token_history(&history).ok();
-
-    Ok(TokenStatus {
-        has_internal_token: true,
-        token_preview: Some(mask_token(&token)),
-        token_hash: Some(token_hash),
-        copied_at: Some(metadata.copied_at),
-        expires_at,
-        source: "imported".to_string(),
-        source_differs: false,
-        source_hash: None,
-    })
+#[tauri::command]
+fn get_webhooks(state: tauri::State<Mutex<AppState>>) -> Vec<WebhookTarget> {
+    state.lock().unwrap().settings.webhooks.clone()
 }
 
-/// Delete internal token
+/// Add a target (empty `id`) or replace the one with the same ID
 #[tauri::command]
-fn clear_internal_token() -> Result<(), String> {
-    delete_internal_token().map_err(|e| e.to_string())
+fn save_webhook(mut target: WebhookTarget, state: tauri::State<Mutex<AppState>>) -> Vec<WebhookTarget> {
+    let mut state = state.lock().unwrap();
+    let mut targets = state.settings.webhooks.clone();
+    match targets.iter_mut().find(|t| !target.id.is_empty() && t.id == target.id) {
+        Some(existing) => *existing = target,
+        None => {
+            if target.id.is_empty() {
+                target.id = webhooks::new_id();
+            }
+            targets.push(target);
+        }
+    }
+    store_webhooks(&mut state, targets)
 }
 
-// ============== OPENAI API INTEGRATION ==============
+#[tauri::command]
+fn delete_webhook(id: String, state: tauri::State<Mutex<AppState>>) -> Vec<WebhookTarget> {
+    let mut state = state.lock().unwrap();
+    let mut targets = state.settings.webhooks.clone();
+    targets.retain(|t| t.id != id);
+    store_webhooks(&mut state, targets)
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAIUsageResponse {
-    pub total_usage: f64, // Usage in cents
-    #[serde(default)]
-    pub daily_costs: Vec<OpenAIDailyCost>,
+/// Send a sample alert to a saved target and report the delivery error, if any
+#[tauri::command]
+async fn test_webhook(id: String, state: tauri::State<'_, Mutex<AppState>>) -> Result<(), AppError> {
+    let settings = state.lock().unwrap().settings.clone();
+    let target = settings
+        .webhooks
+        .iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.webhook_not_found", id = id)))?;
+    webhooks::send_test(&settings, target).await
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAIDailyCost {
-    pub timestamp: f64,
-    pub line_items: Vec<OpenAILineItem>,
+// ============== INTERNAL TOKEN STORAGE ==============
+//
+// Every command takes an optional `provider_id` to pick a Claude account; the
+// built-in "anthropic" provider is used when it is omitted.
+
+fn token_account(state: &tauri::State<Mutex<AppState>>, provider_id: Option<&str>) -> TokenAccount {
+    let state = state.lock().unwrap();
+    TokenAccount::new(&state, provider_id.unwrap_or(TokenAccount::DEFAULT_ID))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAILineItem {
-    pub name: String,
-    pub cost: f64,
+/// Copy token from source to internal storage
+#[tauri::command]
+fn copy_token_to_internal(
+    provider_id: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<TokenStatus, String> {
+    token_store::copy_token_to_internal(&token_account(&state, provider_id.as_deref()))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAISubscriptionResponse {
-    pub hard_limit_usd: Option<f64>,
-    pub soft_limit_usd: Option<f64>,
-    pub system_hard_limit_usd: Option<f64>,
+/// Renew the internal token now with its refresh token
+#[tauri::command]
+async fn refresh_internal_token(
+    provider_id: Option<String>,
+    state: tauri::State<'_, Mutex<AppState>>,
+) -> Result<TokenStatus, String> {
+    let account = token_account(&state, provider_id.as_deref());
+    let settings = state.lock().unwrap().settings.clone();
+    token_store::refresh_internal_token(&account, &settings)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(token_store::token_status(&account))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAIUsageResult {
-    pub success: bool,
-    pub error: Option<String>,
-    /// Total usage in USD for current billing period
-    pub usage_usd: Option<f64>,
-    /// Hard limit in USD
-    pub limit_usd: Option<f64>,
-    /// Usage percentage (0-100)
-    pub percent: Option<f64>,
-    /// Whether this is a pay-as-you-go account (no hard limit)
-    pub is_pay_as_you_go: bool,
-    /// Daily breakdown
-    pub daily_costs: Option<Vec<OpenAIDailyCostSummary>>,
-    /// Billing period start date
-    pub period_start: Option<String>,
-    /// Billing period end date
-    pub period_end: Option<String>,
+/// Get current token status
+#[tauri::command]
+fn get_token_status(provider_id: Option<String>, state: tauri::State<Mutex<AppState>>) -> TokenStatus {
+    token_store::token_status(&token_account(&state, provider_id.as_deref()))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAIDailyCostSummary {
-    pub date: String,
-    pub cost_usd: f64,
-}
-
-/// Fetch OpenAI API usage
-async fn fetch_openai_usage(api_key: &str) -> Result<OpenAIUsageResult, AppError> {
-    let client = reqwest::Client::new();
-
-    // Calculate date range for current month
-    let now = Local::now();
-    let start_date = now.format("%Y-%m-01").to_string();
-    let end_date = (now + chrono::Duration::days(1)).format("%Y-%m-%d").to_string();
-
-    // First, verify the API key is valid by making a simple models request
-    let models_response = client
-        .get("https://api.openai.com/v1/models")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-        .map_err(|e| AppError::NetworkError(e.to_string()))?;
-
-    if !models_response.status().is_success() {
-        let status = models_response.status();
-        return Err(AppError::ApiError(format!(
-            "Invalid API key or API error (status {})",
-            status
-        )));
-    }
+/// Check if source token has changed and log it
+#[tauri::command]
+fn check_token_change(
+    provider_id: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<TokenChangeEntry, String> {
+    Ok(token_store::check_token_change(&token_account(
+        &state,
+        provider_id.as_deref(),
+    )))
+}
 
-    // Try to fetch usage data (this is an internal API that may not work for all accounts)
-    let usage_url = format!(
-        "https://api.openai.com/v1/dashboard/billing/usage?start_date={}&end_date={}",
-        start_date, end_date
-    );
-
-    let usage_response = client
-        .get(&usage_url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .send()
-        .await;
-
-    let (usage_usd, daily_costs) = match usage_response {
-        Ok(resp) if resp.status().is_success() => {
-            match resp.json::<OpenAIUsageResponse>().await {
-                Ok(usage_data) => {
-                    let usage = usage_data.total_usage / 100.0;
-                    let costs: Vec<OpenAIDailyCostSummary> = usage_data.daily_costs
-                        .iter()
-                        .map(|day| {
-                            let total_cost: f64 = day.line_items.iter().map(|li| li.cost).sum();
-                            let date = DateTime::from_timestamp(day.timestamp as i64, 0)
-                                .map(|dt| dt.format("%Y-%m-%d").to_string())
-                                .unwrap_or_else(|| "Unknown".to_string());
-                            OpenAIDailyCostSummary {
-                                date,
-                                cost_usd: total_cost / 100.0,
-                            }
-                        })
-                        .collect();
-                    (Some(usage), Some(costs))
-                }
-                Err(_) => (Some(0.0), None) // API worked but parsing failed, assume 0 usage
-            }
-        }
-        _ => (Some(0.0), None) // API not available, assume 0 usage (pay-as-you-go)
-    };
+#[tauri::command]
+fn get_token_auto_sync(state: tauri::State<Mutex<AppState>>) -> bool {
+    state.lock().unwrap().settings.auto_sync_token
+}
 
-    // Try to fetch subscription/limits
-    let sub_response = client
-        .get("https://api.openai.com/v1/dashboard/billing/subscription")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .send()
-        .await;
-
-    let limit_usd = match sub_response {
-        Ok(resp) if resp.status().is_success() => {
-            resp.json::<OpenAISubscriptionResponse>()
-                .await
-                .ok()
-                .and_then(|sub_data| {
-                    sub_data.hard_limit_usd
-                        .or(sub_data.soft_limit_usd)
-                        .or(sub_data.system_hard_limit_usd)
-                })
-        }
-        _ => None
-    };
+/// Copy the token to internal storage whenever Claude Code rewrites its credentials
+#[tauri::command]
+fn set_token_auto_sync(enabled: bool, state: tauri::State<Mutex<AppState>>) {
+    let mut state = state.lock().unwrap();
+    state.settings.auto_sync_token = enabled;
+    save_state(&state);
+}
 
-    // Determine if pay-as-you-go (no limit set)
-    let is_pay_as_you_go = limit_usd.is_none();
+/// Get token change history
+#[tauri::command]
+fn get_token_history(provider_id: Option<String>, state: tauri::State<Mutex<AppState>>) -> TokenHistory {
+    token_store::load_token_history(&token_account(&state, provider_id.as_deref()))
+}
 
-    // Calculate percentage (0% if pay-as-you-go or no usage)
-    let percent = if let (Some(usage), Some(limit)) = (usage_usd, limit_usd) {
-        if limit > 0.0 {
-            Some((usage / limit * 100.0).min(100.0))
-        } else {
-            Some(0.0)
-        }
-    } else {
-        // Pay-as-you-go: show 0% (no limit to compare against)
-        Some(0.0)
-    };
+/// Export token data (for transfer to another PC)
+#[tauri::command]
+fn export_token_data(
+    provider_id: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<String, String> {
+    token_store::export_token_data(&token_account(&state, provider_id.as_deref()))
+}
 
-    Ok(OpenAIUsageResult {
-        success: true,
-        error: None,
-        usage_usd,
-        limit_usd,
-        percent,
-        is_pay_as_you_go,
-        daily_costs,
-        period_start: Some(start_date),
-        period_end: Some(end_date),
-    })
+/// Import token data (from another PC)
+#[tauri::command]
+fn import_token_data(
+    json_data: String,
+    provider_id: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<TokenStatus, String> {
+    token_store::import_token_data(&token_account(&state, provider_id.as_deref()), &json_data)
 }
 
+/// Delete internal token
+#[tauri::command]
+fn clear_internal_token(
+    provider_id: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<(), String> {
+    token_store::delete_internal_token(&token_account(&state, provider_id.as_deref()))
+        .map_err(|e| e.to_string())
+}
+
+// ============== OPENAI API INTEGRATION ==============
+
 /// Get OpenAI API usage
 #[tauri::command]
 async fn get_openai_api_usage(state: tauri::State<'_, Mutex<AppState>>) -> Result<OpenAIUsageResult, String> {
     // Get API key from state
-    let api_key = {
+    let (api_key, settings) = {
         let state = state.lock().unwrap();
-        state.providers
+        let api_key = state.providers
             .get("openai")
-            .and_then(|p| p.config.api_key.clone())
+            .and_then(|p| p.config.api_key.clone());
+        (api_key, state.settings.clone())
     };
 
     let api_key = match api_key {
         Some(key) if !key.is_empty() => key,
         _ => {
-            return Ok(OpenAIUsageResult {
-                success: false,
-                error: Some("No OpenAI API key configured. Please add your API key in settings.".to_string()),
-                usage_usd: None,
-                limit_usd: None,
-                percent: None,
-                is_pay_as_you_go: false,
-                daily_costs: None,
-                period_start: None,
-                period_end: None,
-            });
+            return Ok(OpenAIUsageResult::failed(
+                tr!("error.no_openai_key"),
+            ));
         }
     };
 
-    match fetch_openai_usage(&api_key).await {
-        Ok(result) => Ok(result),
-        Err(e) => Ok(OpenAIUsageResult {
-            success: false,
-            error: Some(e.to_string()),
-            usage_usd: None,
-            limit_usd: None,
-            percent: None,
-            is_pay_as_you_go: false,
-            daily_costs: None,
-            period_start: None,
-            period_end: None,
-        }),
+    let endpoint = match Endpoint::openai(&settings) {
+        Ok(endpoint) => endpoint,
+        Err(e) => return Ok(OpenAIUsageResult::failed(e.to_string())),
+    };
+
+    match fetch_openai_usage(endpoint, &api_key).await {
+        Ok(result) => {
+            history::record_openai(&result);
+            Ok(result)
+        }
+        Err(e) => {
+            metrics::record_fetch_error("openai", &e);
+            Ok(OpenAIUsageResult::failed(e.to_string()))
+        }
     }
 }
 
@@ -1791,33 +1003,14 @@ fn has_openai_api_key(state: tauri::State<Mutex<AppState>>) -> bool {
         .unwrap_or(false)
 }
 
-/// Save OpenAI API key
+/// Save OpenAI API key (legacy wrapper around `save_provider_api_key`)
 #[tauri::command]
 fn save_openai_api_key(
     api_key: String,
     state: tauri::State<Mutex<AppState>>,
+    registry: tauri::State<ProviderRegistry>,
 ) -> Result<(), String> {
-    if api_key.is_empty() {
-        return Err("API key cannot be empty".to_string());
-    }
-
-    // Validate API key format (should start with sk-)
-    if !api_key.starts_with("sk-") {
-        return Err("Invalid API key format. OpenAI API keys start with 'sk-'".to_string());
-    }
-
-    // Save to keyring
-    save_api_key("openai", &api_key).map_err(|e| e.to_string())?;
-
-    // Update state
-    let mut state = state.lock().unwrap();
-    if let Some(provider) = state.providers.get_mut("openai") {
-        provider.config.api_key = Some(api_key);
-        provider.config.has_api_key = true;
-    }
-    save_state(&state);
-
-    Ok(())
+    store_provider_api_key("openai", api_key, &state, &registry).map_err(|e| e.to_string())
 }
 
 /// Remove OpenAI API key
@@ -1853,116 +1046,346 @@ fn get_openai_api_key_preview(state: tauri::State<Mutex<AppState>>) -> Option<St
 
 /// Get Claude Code usage using internal token (fallback to source if not available)
 #[tauri::command]
-async fn get_claude_code_usage_internal() -> ClaudeCodeUsageResult {
-    // Try internal token first
-    let token = if let Some(meta) = load_token_metadata() {
-        meta.token
-    } else {
-        None
-    };
+async fn get_claude_code_usage_internal(
+    state: tauri::State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<ClaudeCodeUsageResult, String> {
+    let settings = state.lock().unwrap().settings.clone();
 
     // Get subscription type from credentials (if available)
     let subscription_type = get_claude_code_credentials_info()
         .and_then(|info| info.subscription_type);
 
-    // Fall back to source token if internal not available
-    let token = match token {
-        Some(t) => t,
-        None => {
-            match get_claude_code_oauth_token() {
-                Some(t) => t,
-                None => {
-                    return ClaudeCodeUsageResult {
-                        success: false,
-                        error: Some("No token available. Please copy token to internal storage or ensure Claude Code is connected.".to_string()),
-                        five_hour_percent: None,
-                        five_hour_reset: None,
-                        seven_day_percent: None,
-                        seven_day_reset: None,
-                        subscription_type: None,
-                    };
-                }
-            }
-        }
+    let endpoint = match Endpoint::anthropic(&settings) {
+        Ok(endpoint) => endpoint,
+        Err(e) => return Ok(ClaudeCodeUsageResult::failed(e.to_string())),
     };
 
-    // Fetch usage
-    match fetch_claude_code_usage(&token).await {
+    // Fetch usage (the internal token is renewed when needed)
+    let account = TokenAccount::default_account(&settings);
+    match fetch_claude_code_usage_internal(&account, &settings, &endpoint).await {
         Ok(usage) => {
-            ClaudeCodeUsageResult {
-                success: true,
-                error: None,
-                five_hour_percent: usage.five_hour.as_ref().map(|w| w.utilization),
-                five_hour_reset: usage.five_hour.and_then(|w| w.resets_at),
-                seven_day_percent: usage.seven_day.as_ref().map(|w| w.utilization),
-                seven_day_reset: usage.seven_day.and_then(|w| w.resets_at),
-                subscription_type,
-            }
+            let result = ClaudeCodeUsageResult::from_response(usage, subscription_type);
+            let mut state = state.lock().unwrap();
+            let alerts = apply_claude_code_result(&mut state, &result);
+            tray::update(&app, &state);
+            drop(state);
+            alerts.into_iter().for_each(raise_alert);
+            Ok(result)
         }
         Err(e) => {
-            ClaudeCodeUsageResult {
-                success: false,
-                error: Some(e.to_string()),
-                five_hour_percent: None,
-                five_hour_reset: None,
-                seven_day_percent: None,
-                seven_day_reset: None,
-                subscription_type: None,
-            }
+            metrics::record_fetch_error("anthropic", &e);
+            Ok(ClaudeCodeUsageResult::failed(e.to_string()))
         }
     }
 }
 
-// ============== SYSTEM TRAY ==============
+// ============== GENERIC PROVIDER COMMANDS ==============
 
-fn create_tray_menu() -> SystemTrayMenu {
-    let show = CustomMenuItem::new("show".to_string(), "Show");
-    let quit = CustomMenuItem::new("quit".to_string(), "Quit");
+/// List the registered usage backends and the windows they report
+#[tauri::command]
+fn list_usage_providers(registry: tauri::State<ProviderRegistry>) -> Vec<ProviderDescriptor> {
+    registry.describe()
+}
 
-    SystemTrayMenu::new()
-        .add_item(show)
-        .add_native_item(SystemTrayMenuItem::Separator)
-        .add_item(quit)
+/// Fetch usage for any configured provider
+#[tauri::command]
+async fn fetch_provider_usage(
+    provider_id: String,
+    state: tauri::State<'_, Mutex<AppState>>,
+    registry: tauri::State<'_, ProviderRegistry>,
+    app: AppHandle,
+) -> Result<UsageReport, AppError> {
+    let report = providers::fetch_provider(&state, &registry, &provider_id).await?;
+    scheduler::publish_usage(&app, &provider_id, &report);
+    Ok(report)
 }
 
-fn handle_tray_event(app: &tauri::AppHandle, event: SystemTrayEvent) {
-    match event {
-        SystemTrayEvent::LeftClick { .. } => {
-            if let Some(window) = app.get_window("main") {
-                window.show().ok();
-                window.set_focus().ok();
-            }
-        }
-        SystemTrayEvent::MenuItemClick { id, .. } => {
-            let window = app.get_window("main");
-
-            match id.as_str() {
-                "show" => {
-                    if let Some(w) = window {
-                        w.show().ok();
-                        w.set_focus().ok();
-                    }
-                }
-                "quit" => {
-                    std::process::exit(0);
-                }
-                _ => {}
-            }
+/// Set the background polling interval of a provider (0 disables polling)
+#[tauri::command]
+fn set_provider_poll_interval(
+    provider_id: String,
+    seconds: u32,
+    state: tauri::State<Mutex<AppState>>,
+) -> Result<(), AppError> {
+    let mut state = state.lock().unwrap();
+    let provider = state
+        .providers
+        .get_mut(&provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    provider.config.poll_interval_secs = seconds;
+    save_state(&state);
+    Ok(())
+}
+
+/// Check the stored credentials of a provider against its backend
+#[tauri::command]
+async fn validate_provider_credentials(
+    provider_id: String,
+    state: tauri::State<'_, Mutex<AppState>>,
+    registry: tauri::State<'_, ProviderRegistry>,
+) -> Result<(), AppError> {
+    providers::validate_provider(&state, &registry, &provider_id).await
+}
+
+/// Save an API key for any provider
+#[tauri::command]
+fn save_provider_api_key(
+    provider_id: String,
+    api_key: String,
+    state: tauri::State<Mutex<AppState>>,
+    registry: tauri::State<ProviderRegistry>,
+) -> Result<(), AppError> {
+    store_provider_api_key(&provider_id, api_key, &state, &registry)
+}
+
+// ============== PROVIDER ACCOUNTS ==============
+//
+// Named accounts per provider type. Once added, an account is configured and
+// fetched like any provider through its ID ("anthropic:work").
+
+#[tauri::command]
+fn list_provider_accounts(state: tauri::State<Mutex<AppState>>) -> Vec<ProviderAccount> {
+    accounts::list(&state.lock().unwrap())
+}
+
+/// Add a (disabled) account of `provider_type` and return its ID
+#[tauri::command]
+fn add_provider_account(
+    provider_type: ProviderType,
+    name: String,
+    state: tauri::State<Mutex<AppState>>,
+    registry: tauri::State<ProviderRegistry>,
+    app: AppHandle,
+) -> Result<String, AppError> {
+    let id = accounts::add(&state, &registry, &provider_type, &name)?;
+    tray::update(&app, &state.lock().unwrap());
+    Ok(id)
+}
+
+#[tauri::command]
+fn rename_provider_account(
+    provider_id: String,
+    name: String,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), AppError> {
+    accounts::rename(&state, &provider_id, &name)?;
+    tray::update(&app, &state.lock().unwrap());
+    Ok(())
+}
+
+/// Remove an added account with its API key and internal token
+#[tauri::command]
+fn remove_provider_account(
+    provider_id: String,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), AppError> {
+    accounts::remove(&state, &provider_id)?;
+    {
+        let state = state.lock().unwrap();
+        tray::update(&app, &state);
+        if let Some(provider) = state.providers.get(&state.active_provider) {
+            emit_usage(&app, &provider.usage);
         }
-        _ => {}
     }
+    credential_watcher::restart(&app);
+    Ok(())
+}
+
+/// Set the Claude Code credentials file of a Claude account
+#[tauri::command]
+fn set_provider_credentials_path(
+    provider_id: String,
+    path: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), AppError> {
+    accounts::set_credentials_path(&state, &provider_id, path)?;
+    credential_watcher::restart(&app);
+    Ok(())
+}
+
+// ============== MANUAL COUNTERS ==============
+
+/// Payload of `counter-updated`
+#[derive(Serialize)]
+struct CounterUpdate<'a> {
+    provider_id: &'a str,
+    usage: &'a UsageData,
+}
+
+/// Push a counter's new usage to the window, the tray and the local HTTP API
+fn publish_counter(app: &AppHandle, state: &AppState, provider_id: &str, usage: &UsageData) {
+    tray::update(app, state);
+    let update = CounterUpdate { provider_id, usage };
+    app.emit_all("counter-updated", &update).ok();
+    api_server::broadcast("counter-updated", &update);
+    if state.active_provider == provider_id {
+        emit_usage(app, usage);
+    }
+}
+
+#[tauri::command]
+fn list_manual_counters(state: tauri::State<Mutex<AppState>>) -> Vec<Counter> {
+    counters::list(&state.lock().unwrap())
+}
+
+/// Create a counter and return its ID
+#[tauri::command]
+fn create_manual_counter(
+    settings: CounterSettings,
+    state: tauri::State<Mutex<AppState>>,
+    registry: tauri::State<ProviderRegistry>,
+    app: AppHandle,
+) -> Result<String, AppError> {
+    let id = counters::create(&state, &registry, settings)?;
+    tray::update(&app, &state.lock().unwrap());
+    Ok(id)
+}
+
+#[tauri::command]
+fn update_manual_counter(
+    provider_id: String,
+    settings: CounterSettings,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<UsageData, AppError> {
+    let usage = counters::update(&state, &provider_id, settings)?;
+    publish_counter(&app, &state.lock().unwrap(), &provider_id, &usage);
+    Ok(usage)
+}
+
+#[tauri::command]
+fn rename_manual_counter(
+    provider_id: String,
+    name: String,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), AppError> {
+    accounts::rename(&state, &provider_id, &name)?;
+    tray::update(&app, &state.lock().unwrap());
+    Ok(())
+}
+
+#[tauri::command]
+fn delete_manual_counter(
+    provider_id: String,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), AppError> {
+    counters::delete(&state, &provider_id)?;
+    let state = state.lock().unwrap();
+    tray::update(&app, &state);
+    if let Some(provider) = state.providers.get(&state.active_provider) {
+        emit_usage(&app, &provider.usage);
+    }
+    Ok(())
+}
+
+/// Set the reset schedule of any manual counter, the built-in one included
+#[tauri::command]
+fn set_counter_reset_policy(
+    provider_id: String,
+    policy: ResetPolicy,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<UsageData, AppError> {
+    let usage = counters::set_reset_policy(&state, &provider_id, policy)?;
+    publish_counter(&app, &state.lock().unwrap(), &provider_id, &usage);
+    Ok(usage)
+}
+
+/// Add `count` to any counter, active or not
+#[tauri::command]
+fn add_counter_requests(
+    provider_id: String,
+    count: u32,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<UsageData, AppError> {
+    add_counter_usage(provider_id, count as f64, None, state, app)
+}
+
+/// Add `amount` to a counter, multiplied by the weight of `kind` when given
+/// (e.g. 1 use of kind "opus" weighted 5 adds 5)
+#[tauri::command]
+fn add_counter_usage(
+    provider_id: String,
+    amount: f64,
+    kind: Option<String>,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<UsageData, AppError> {
+    let mut state = state.lock().unwrap();
+    let config = state
+        .providers
+        .get(&provider_id)
+        .map(|p| p.config.clone())
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    let amount = counters::weighted_amount(&config, amount, kind.as_deref())?;
+    let (usage, alerts) = meter_ai::add_counter_usage(&mut state, &provider_id, amount)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    publish_counter(&app, &state, &provider_id, &usage);
+    drop(state);
+    alerts.into_iter().for_each(raise_alert);
+    Ok(usage)
+}
+
+#[tauri::command]
+fn reset_counter(
+    provider_id: String,
+    state: tauri::State<Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<UsageData, AppError> {
+    let mut state = state.lock().unwrap();
+    let usage = meter_ai::reset_counter(&mut state, &provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    publish_counter(&app, &state, &provider_id, &usage);
+    Ok(usage)
 }
 
 // ============== MAIN ==============
 
+/// Longest wait for a `meterai` writing data.json before starting anyway
+const APP_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Take the app lock, so `meterai` queues its counter changes instead of
+/// overwriting data.json. Without it (another instance) the app still starts.
+fn lock_app() -> Option<fs::File> {
+    let deadline = Instant::now() + APP_LOCK_TIMEOUT;
+    loop {
+        match meter_ai::try_lock_app() {
+            Ok(Some(lock)) => return Some(lock),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(100)),
+            _ => return None,
+        }
+    }
+}
+
 fn main() {
+    // Held for the whole run
+    let _app_lock = lock_app();
     let state = load_state();
-    let tray = SystemTray::new().with_menu(create_tray_menu());
+    i18n::set_locale(state.settings.locale.unwrap_or_else(Locale::system));
+
+    if mock_server::is_enabled(&state.settings) {
+        match tauri::async_runtime::block_on(mock_server::start(([127, 0, 0, 1], 0).into())) {
+            Ok(base_url) => http::enable_mock_mode(base_url),
+            Err(e) => notify_error(&e.to_string()),
+        }
+    }
+
+    history::apply_retention(state.settings.history_retention_days).ok();
+
+    let system_tray = SystemTray::new().with_menu(tray::build_menu(&state));
 
     tauri::Builder::default()
         .manage(Mutex::new(state))
-        .system_tray(tray)
-        .on_system_tray_event(handle_tray_event)
+        .manage(ProviderRegistry::builtin())
+        .system_tray(system_tray)
+        .on_system_tray_event(tray::handle_event)
         .invoke_handler(tauri::generate_handler![
             get_usage,
             get_all_providers,
@@ -1982,8 +1405,50 @@ fn main() {
             browse_credentials_file,
             set_custom_credentials_path,
             get_custom_credentials_path,
+            get_endpoint_configs,
+            set_endpoint_config,
+            set_mock_mode,
+            get_mock_backend_url,
+            get_locale,
+            set_locale,
+            set_notifications_paused,
+            // Usage history
+            get_usage_history,
+            get_usage_aggregates,
+            get_history_retention,
+            set_history_retention,
+            // Forecasts
+            get_usage_forecast,
+            get_forecast_alerts,
+            set_forecast_alerts,
+            // Budgets
+            get_budgets,
+            set_budgets,
+            get_budget_status,
+            // Claude Code transcripts
+            get_claude_token_usage,
+            reindex_claude_transcripts,
+            // Model pricing
+            get_pricing_catalog,
+            estimate_token_cost,
+            // Local HTTP API
+            get_api_server_config,
+            set_api_server_config,
+            regenerate_api_server_token,
+            // Metering proxy
+            get_metering_proxy_config,
+            set_metering_proxy_config,
+            get_metered_totals,
+            reset_metered_totals,
+            get_webhooks,
+            save_webhook,
+            delete_webhook,
+            test_webhook,
             // Internal token management
             copy_token_to_internal,
+            refresh_internal_token,
+            get_token_auto_sync,
+            set_token_auto_sync,
             get_token_status,
             check_token_change,
             get_token_history,
@@ -1991,13 +1456,52 @@ fn main() {
             import_token_data,
             clear_internal_token,
             get_claude_code_usage_internal,
+            // Anthropic Admin API
             // OpenAI API
             get_openai_api_usage,
             has_openai_api_key,
             save_openai_api_key,
             remove_openai_api_key,
-            get_openai_api_key_preview
+            get_openai_api_key_preview,
+            // Generic provider backends
+            list_usage_providers,
+            fetch_provider_usage,
+            validate_provider_credentials,
+            save_provider_api_key,
+            set_provider_poll_interval,
+            // Provider accounts
+            list_provider_accounts,
+            add_provider_account,
+            rename_provider_account,
+            remove_provider_account,
+            set_provider_credentials_path,
+            // Manual counters
+            list_manual_counters,
+            create_manual_counter,
+            update_manual_counter,
+            rename_manual_counter,
+            delete_manual_counter,
+            set_counter_reset_policy,
+            add_counter_requests,
+            add_counter_usage,
+            reset_counter
         ])
+        .setup(|app| {
+            tray::refresh(&app.handle());
+            scheduler::start(app.handle());
+            credential_watcher::restart(&app.handle());
+
+            let state = app.state::<Mutex<AppState>>();
+            let api_config = state.lock().unwrap().settings.api_server.clone();
+            let started = api_server::apply(app.handle(), api_config);
+            if let Err(e) = tauri::async_runtime::block_on(started) {
+                notify_error(&e.to_string());
+            }
+            if let Err(e) = tauri::async_runtime::block_on(proxy_server::apply(app.handle())) {
+                notify_error(&e.to_string());
+            }
+            Ok(())
+        })
         .on_window_event(|event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event.event() {
                 event.window().hide().ok();
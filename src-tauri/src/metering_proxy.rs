@@ -0,0 +1,700 @@
+// ============== METERING PROXY ==============
+//
+// Optional reverse proxy on 127.0.0.1 that tools use instead of the real API,
+// e.g. `ANTHROPIC_BASE_URL=http://127.0.0.1:47601/anthropic`. The first path
+// segment picks a route; the rest of the request is forwarded to the route's
+// provider endpoint (so endpoint overrides and mock mode apply, which also
+// makes a local stub upstream a matter of setting its `baseUrl`). Responses
+// are streamed back untouched while their `usage` block (plain JSON or SSE
+// events) and rate-limit headers are read, and every call is reported as a
+// `MeteredCall` for the app to charge with `record`, which leaves saving the
+// state to the app so a busy proxy doesn't write data.json on every call.
+//
+// Request and response bodies are never logged or stored: only the model
+// name, token counts and rate-limit headers are kept.
+
+use chrono::Utc;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+use crate::http::Endpoint;
+use crate::i18n::tr;
+use crate::pricing::{PricingCatalog, TokenCounts};
+use crate::webhooks::Alert;
+use crate::{count_usage, AppError, AppState, ProviderType, UsageData};
+
+/// Non-streamed responses larger than this are forwarded without metering
+const MAX_BUFFERED_BODY: usize = 8 * 1024 * 1024;
+
+/// Hop-by-hop headers, plus the ones reqwest and hyper set themselves.
+/// `accept-encoding` is dropped so responses come back readable.
+const SKIPPED_HEADERS: &[&str] = &[
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+    "accept-encoding",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeteringProxyConfig {
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+    #[serde(default = "default_routes")]
+    pub routes: Vec<ProxyRoute>,
+}
+
+impl Default for MeteringProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47601,
+            routes: default_routes(),
+        }
+    }
+}
+
+fn default_routes() -> Vec<ProxyRoute> {
+    ["anthropic", "openai"]
+        .into_iter()
+        .map(|id| ProxyRoute {
+            prefix: id.to_string(),
+            provider_id: id.to_string(),
+            counter: None,
+            measure: ProxyMeasure::default(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyRoute {
+    /// First path segment ("anthropic" serves /anthropic/v1/messages)
+    pub prefix: String,
+    /// Account the calls are charged to ("anthropic", "openai:work", ...)
+    #[serde(rename = "providerId")]
+    pub provider_id: String,
+    /// Manual counter increased by every successful call
+    #[serde(default)]
+    pub counter: Option<String>,
+    /// What a call adds to the counter
+    #[serde(default)]
+    pub measure: ProxyMeasure,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMeasure {
+    /// 1 per call
+    #[default]
+    Requests,
+    /// Input, output and cache tokens
+    Tokens,
+    /// Estimated cost in USD
+    Cost,
+}
+
+/// Rate-limit headers of the last response (`anthropic-ratelimit-*` or
+/// `x-ratelimit-*`); reset values are passed through as sent
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub requests_limit: Option<u64>,
+    pub requests_remaining: Option<u64>,
+    pub requests_reset: Option<String>,
+    pub tokens_limit: Option<u64>,
+    pub tokens_remaining: Option<u64>,
+    pub tokens_reset: Option<String>,
+}
+
+/// One proxied call
+#[derive(Debug, Clone, Serialize)]
+pub struct MeteredCall {
+    pub provider_id: String,
+    pub provider_type: ProviderType,
+    pub model: Option<String>,
+    pub status: u16,
+    pub tokens: TokenCounts,
+    /// Estimated from the pricing catalog (None for unknown models)
+    pub cost_usd: Option<f64>,
+    pub rate_limits: Option<RateLimits>,
+    pub at: i64,
+}
+
+/// Calls charged to an account through the proxy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeteredTotals {
+    /// Start of the totals (first call or last reset)
+    pub since: i64,
+    pub requests: u64,
+    pub tokens: TokenCounts,
+    pub cost_usd: f64,
+    #[serde(rename = "lastCallAt", default)]
+    pub last_call_at: Option<i64>,
+    #[serde(rename = "rateLimits", default)]
+    pub rate_limits: Option<RateLimits>,
+}
+
+/// Receives every proxied call, once its response has been fully streamed
+pub type CallHandler = Arc<dyn Fn(&ProxyRoute, MeteredCall) + Send + Sync>;
+
+fn invalid_route(route: &ProxyRoute) -> AppError {
+    AppError::ConfigError(tr!("error.invalid_proxy_route", prefix = route.prefix))
+}
+
+impl MeteringProxyConfig {
+    /// Every route needs a unique single-segment prefix, a fetched provider
+    /// account and, if set, a manual counter
+    pub fn validate(&self, state: &AppState) -> Result<(), AppError> {
+        for (index, route) in self.routes.iter().enumerate() {
+            let duplicate = self.routes[..index].iter().any(|r| r.prefix == route.prefix);
+            if route.prefix.is_empty() || route.prefix.contains('/') || duplicate {
+                return Err(invalid_route(route));
+            }
+            let provider_type = state.providers.get(&route.provider_id).map(|p| &p.config.provider_type);
+            if !matches!(provider_type, Some(ProviderType::Anthropic | ProviderType::OpenAI)) {
+                return Err(invalid_route(route));
+            }
+            if let Some(counter) = &route.counter {
+                let counter_type = state.providers.get(counter).map(|p| &p.config.provider_type);
+                if counter_type != Some(&ProviderType::Manual) {
+                    return Err(invalid_route(route));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RateLimits {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let text = |names: [&str; 2]| {
+            names
+                .iter()
+                .find_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+                .map(|v| v.trim().to_string())
+        };
+        let number = |names: [&str; 2]| text(names).and_then(|v| v.parse::<u64>().ok());
+        let limits = RateLimits {
+            requests_limit: number(["anthropic-ratelimit-requests-limit", "x-ratelimit-limit-requests"]),
+            requests_remaining: number(["anthropic-ratelimit-requests-remaining", "x-ratelimit-remaining-requests"]),
+            requests_reset: text(["anthropic-ratelimit-requests-reset", "x-ratelimit-reset-requests"]),
+            tokens_limit: number(["anthropic-ratelimit-tokens-limit", "x-ratelimit-limit-tokens"]),
+            tokens_remaining: number(["anthropic-ratelimit-tokens-remaining", "x-ratelimit-remaining-tokens"]),
+            tokens_reset: text(["anthropic-ratelimit-tokens-reset", "x-ratelimit-reset-tokens"]),
+        };
+        (limits != RateLimits::default()).then_some(limits)
+    }
+}
+
+/// Token fields seen so far; later events overwrite earlier values, as
+/// streamed totals are cumulative
+#[derive(Default)]
+struct UsageFields {
+    input: Option<u64>,
+    output: Option<u64>,
+    cache_creation: Option<u64>,
+    cache_read: Option<u64>,
+    /// Cached tokens counted in `input` (OpenAI)
+    cached_input: Option<u64>,
+}
+
+/// Extracts the model and usage from a response body as it streams by,
+/// keeping only the current SSE line (or the JSON body) in memory
+struct UsageReader {
+    streaming: bool,
+    buffer: Vec<u8>,
+    overflow: bool,
+    model: Option<String>,
+    usage: UsageFields,
+}
+
+impl UsageReader {
+    fn new(streaming: bool) -> Self {
+        Self {
+            streaming,
+            buffer: Vec::new(),
+            overflow: false,
+            model: None,
+            usage: UsageFields::default(),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        if self.overflow {
+            return;
+        }
+        self.buffer.extend_from_slice(chunk);
+        if !self.streaming {
+            if self.buffer.len() > MAX_BUFFERED_BODY {
+                self.overflow = true;
+                self.buffer = Vec::new();
+            }
+            return;
+        }
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.read_sse_line(&line);
+        }
+    }
+
+    /// `data: {...}` lines of Anthropic and OpenAI streams
+    fn read_sse_line(&mut self, line: &[u8]) {
+        let Some(data) = line.strip_prefix(b"data:") else {
+            return;
+        };
+        if let Ok(event) = serde_json::from_slice::<Value>(data) {
+            self.read_event(&event);
+        }
+    }
+
+    /// Usage and model at the top level (JSON bodies, OpenAI chunks), under
+    /// `message` (Anthropic `message_start`) or `response` (OpenAI Responses)
+    fn read_event(&mut self, event: &Value) {
+        for container in [Some(event), event.get("message"), event.get("response")]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(model) = container.get("model").and_then(Value::as_str) {
+                self.model = Some(model.to_string());
+            }
+            if let Some(usage) = container.get("usage").filter(|u| u.is_object()) {
+                self.usage.merge(usage);
+            }
+        }
+    }
+
+    fn finish(mut self) -> (Option<String>, TokenCounts) {
+        if self.streaming {
+            let line = std::mem::take(&mut self.buffer);
+            self.read_sse_line(&line);
+        } else if !self.overflow {
+            if let Ok(body) = serde_json::from_slice::<Value>(&self.buffer) {
+                self.read_event(&body);
+            }
+        }
+        let usage = &self.usage;
+        let cached = usage.cached_input.unwrap_or(0);
+        let tokens = TokenCounts {
+            input: usage.input.unwrap_or(0).saturating_sub(cached),
+            output: usage.output.unwrap_or(0),
+            cache_creation: usage.cache_creation.unwrap_or(0),
+            cache_read: usage.cache_read.unwrap_or(0) + cached,
+        };
+        (self.model, tokens)
+    }
+}
+
+impl UsageFields {
+    fn merge(&mut self, usage: &Value) {
+        let field = |names: &[&str]| names.iter().find_map(|name| usage.pointer(name).and_then(Value::as_u64));
+        let fields = [
+            (&mut self.input, field(&["/input_tokens", "/prompt_tokens"])),
+            (&mut self.output, field(&["/output_tokens", "/completion_tokens"])),
+            (&mut self.cache_creation, field(&["/cache_creation_input_tokens"])),
+            (&mut self.cache_read, field(&["/cache_read_input_tokens"])),
+            (
+                &mut self.cached_input,
+                field(&["/prompt_tokens_details/cached_tokens", "/input_tokens_details/cached_tokens"]),
+            ),
+        ];
+        for (slot, value) in fields {
+            if value.is_some() {
+                *slot = value;
+            }
+        }
+    }
+}
+
+/// A route with its upstream
+struct Upstream {
+    route: ProxyRoute,
+    provider_type: ProviderType,
+    endpoint: Endpoint,
+}
+
+struct Proxy {
+    upstreams: Vec<Arc<Upstream>>,
+    catalog: PricingCatalog,
+    on_call: CallHandler,
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "error": { "type": "proxy_error", "message": message } });
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn forwarded(name: &HeaderName) -> bool {
+    !SKIPPED_HEADERS.contains(&name.as_str())
+}
+
+async fn forward(proxy: Arc<Proxy>, req: Request<Body>) -> Response<Body> {
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string();
+    let (prefix, rest) = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .map(|(prefix, rest)| (prefix.to_string(), format!("/{}", rest)))
+        .unwrap_or_else(|| (path.trim_start_matches('/').to_string(), "/".to_string()));
+    let Some(upstream) = proxy.upstreams.iter().find(|u| u.route.prefix == prefix).cloned() else {
+        return error_response(StatusCode::NOT_FOUND, &tr!("error.no_proxy_route", prefix = prefix));
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let mut request = upstream.endpoint.request(parts.method, &rest).body(body);
+    for (name, value) in parts.headers.iter().filter(|(name, _)| forwarded(name)) {
+        request = request.header(name, value);
+    }
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return error_response(StatusCode::BAD_GATEWAY, &e.without_url().to_string()),
+    };
+
+    let status = response.status();
+    let rate_limits = RateLimits::from_headers(response.headers());
+    let streaming = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let mut builder = Response::builder().status(status);
+    for (name, value) in response.headers().iter().filter(|(name, _)| forwarded(name)) {
+        builder = builder.header(name, value);
+    }
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut reader = UsageReader::new(streaming);
+        while let Ok(Some(chunk)) = response.chunk().await {
+            reader.feed(&chunk);
+            // The client hung up: the upstream call is charged with what was read
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+        }
+        let (model, tokens) = reader.finish();
+        let cost_usd = model.as_deref().and_then(|model| {
+            proxy
+                .catalog
+                .cost(&upstream.provider_type, model, &tokens, Utc::now().date_naive(), false)
+                .map(|estimate| estimate.total_usd)
+        });
+        let call = MeteredCall {
+            provider_id: upstream.route.provider_id.clone(),
+            provider_type: upstream.provider_type.clone(),
+            model,
+            status: status.as_u16(),
+            tokens,
+            cost_usd,
+            rate_limits,
+            at: Utc::now().timestamp(),
+        };
+        (proxy.on_call)(&upstream.route, call);
+    });
+
+    builder
+        .body(body)
+        .unwrap_or_else(|e| error_response(StatusCode::BAD_GATEWAY, &e.to_string()))
+}
+
+/// Start the proxy on `config.port` (0 picks a free port) with the routes of
+/// `config`, and return its address with the server task
+pub fn start(
+    state: &AppState,
+    config: &MeteringProxyConfig,
+    on_call: CallHandler,
+) -> Result<(SocketAddr, JoinHandle<()>), AppError> {
+    config.validate(state)?;
+    let mut upstreams = Vec::new();
+    for route in &config.routes {
+        let provider_type = state.providers[&route.provider_id].config.provider_type.clone();
+        let endpoint = match provider_type {
+            ProviderType::OpenAI => Endpoint::openai(&state.settings)?,
+            _ => Endpoint::anthropic(&state.settings)?,
+        };
+        upstreams.push(Arc::new(Upstream {
+            route: route.clone(),
+            provider_type,
+            endpoint,
+        }));
+    }
+    let proxy = Arc::new(Proxy {
+        upstreams,
+        catalog: PricingCatalog::load(),
+        on_call,
+    });
+
+    let make_svc = make_service_fn(move |_conn| {
+        let proxy = proxy.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let proxy = proxy.clone();
+                async move { Ok::<_, Infallible>(forward(proxy, req).await) }
+            }))
+        }
+    });
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
+    let server = Server::try_bind(&addr)
+        .map_err(|e| {
+            AppError::ConfigError(tr!("error.server_start", server = "Metering proxy", addr = addr, detail = e))
+        })?
+        .serve(make_svc);
+    let local_addr = server.local_addr();
+
+    let task = tokio::spawn(async move {
+        server.await.ok();
+    });
+    Ok((local_addr, task))
+}
+
+/// What `record` changed in the state
+#[derive(Debug, Default)]
+pub struct Charge {
+    /// The state needs saving
+    pub changed: bool,
+    /// The route's counter with its new usage, and the alerts to raise once
+    /// the state lock is released
+    pub counter: Option<(String, UsageData, Vec<Alert>)>,
+}
+
+/// Charge a call to its account's totals and, when successful, to the
+/// route's counter. Nothing is saved.
+pub fn record(state: &mut AppState, route: &ProxyRoute, call: &MeteredCall) -> Charge {
+    let success = (200..300).contains(&call.status);
+    let Some(provider) = state.providers.get_mut(&call.provider_id) else {
+        return Charge::default();
+    };
+    if !success && call.rate_limits.is_none() {
+        return Charge::default();
+    }
+    let totals = provider.metered.get_or_insert_with(|| MeteredTotals {
+        since: call.at,
+        ..Default::default()
+    });
+    if call.rate_limits.is_some() {
+        totals.rate_limits = call.rate_limits.clone();
+    }
+    if !success {
+        return Charge {
+            changed: true,
+            counter: None,
+        };
+    }
+    totals.requests += 1;
+    totals.tokens.add(&call.tokens);
+    totals.cost_usd += call.cost_usd.unwrap_or(0.0);
+    totals.last_call_at = Some(call.at);
+
+    let amount = match route.measure {
+        ProxyMeasure::Requests => Some(1.0),
+        ProxyMeasure::Tokens => Some(call.tokens.total() as f64),
+        ProxyMeasure::Cost => call.cost_usd,
+    };
+    let counter = route.counter.as_deref().zip(amount).and_then(|(counter, amount)| {
+        count_usage(state, counter, amount).map(|(usage, alerts)| (counter.to_string(), usage, alerts))
+    });
+    Charge { changed: true, counter }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{self, EndpointConfig};
+    use hyper::body::Bytes;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    const SSE: &str = "event: message_start\n\
+        data: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-sonnet-4-5\",\"usage\":{\"input_tokens\":25,\"cache_read_input_tokens\":100,\"output_tokens\":1}}}\n\n\
+        event: message_delta\n\
+        data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":42}}\n\n";
+
+    /// Upstream with a JSON (OpenAI), an SSE (Anthropic) and a stalled SSE endpoint
+    async fn stub_upstream(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let response = Response::builder();
+        Ok(match req.uri().path() {
+            "/v1/chat/completions" => response
+                .header(CONTENT_TYPE, "application/json")
+                .header("x-ratelimit-remaining-requests", "99")
+                .body(Body::from(
+                    r#"{"model":"gpt-4o","usage":{"prompt_tokens":100,"completion_tokens":20,"prompt_tokens_details":{"cached_tokens":40}}}"#,
+                )),
+            path => {
+                let stalls = path == "/v1/stalled";
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    // Split in the middle of a line
+                    let (first, second) = SSE.split_at(60);
+                    sender.send_data(Bytes::from(first)).await.ok();
+                    tokio::time::sleep(Duration::from_millis(if stalls { 300 } else { 10 })).await;
+                    sender.send_data(Bytes::from(second)).await.ok();
+                    if stalls {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                    }
+                });
+                response.header(CONTENT_TYPE, "text/event-stream").body(body)
+            }
+        }
+        .unwrap())
+    }
+
+    /// Proxy with the default routes in front of the stub, and the calls it reports
+    async fn start_proxy() -> (String, mpsc::UnboundedReceiver<MeteredCall>) {
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(stub_upstream)) });
+        let upstream = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let base_url = format!("http://{}", upstream.local_addr());
+        tokio::spawn(upstream);
+
+        let mut state = AppState::default();
+        for key in [http::ANTHROPIC, http::OPENAI] {
+            let config = EndpointConfig {
+                base_url: Some(base_url.clone()),
+                ..Default::default()
+            };
+            state.settings.endpoints.insert(key.to_string(), config);
+        }
+        let config = MeteringProxyConfig {
+            enabled: true,
+            port: 0,
+            routes: default_routes(),
+        };
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let on_call: CallHandler = Arc::new(move |_, call| {
+            sender.send(call).ok();
+        });
+        let (addr, _) = start(&state, &config, on_call).unwrap();
+        (format!("http://{}", addr), receiver)
+    }
+
+    async fn next_call(receiver: &mut mpsc::UnboundedReceiver<MeteredCall>) -> MeteredCall {
+        tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_usage_is_metered() {
+        let (proxy, mut calls) = start_proxy().await;
+        let body = reqwest::Client::new()
+            .post(format!("{}/openai/v1/chat/completions", proxy))
+            .body("{}")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(body.contains("\"gpt-4o\""));
+
+        let call = next_call(&mut calls).await;
+        assert_eq!(call.provider_id, "openai");
+        assert_eq!(call.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(call.status, 200);
+        assert_eq!((call.tokens.input, call.tokens.cache_read, call.tokens.output), (60, 40, 20));
+        assert_eq!(call.rate_limits.unwrap().requests_remaining, Some(99));
+    }
+
+    #[tokio::test]
+    async fn sse_usage_is_metered() {
+        let (proxy, mut calls) = start_proxy().await;
+        let body = reqwest::Client::new()
+            .post(format!("{}/anthropic/v1/messages", proxy))
+            .body("{}")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, SSE);
+
+        let call = next_call(&mut calls).await;
+        assert_eq!(call.provider_id, "anthropic");
+        assert_eq!(call.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!((call.tokens.input, call.tokens.cache_read, call.tokens.output), (25, 100, 42));
+    }
+
+    #[tokio::test]
+    async fn client_hang_up_is_still_charged() {
+        let (proxy, mut calls) = start_proxy().await;
+        let mut response = reqwest::Client::new()
+            .post(format!("{}/anthropic/v1/stalled", proxy))
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        response.chunk().await.unwrap();
+        drop(response);
+
+        // Charged when the next chunk finds the client gone, with the usage read so far
+        let call = next_call(&mut calls).await;
+        assert_eq!(call.status, 200);
+        assert_eq!(call.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(call.tokens.input, 25);
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() {
+        let (proxy, mut calls) = start_proxy().await;
+        let response = reqwest::get(format!("{}/gemini/v1/models", proxy)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "proxy_error");
+        assert!(calls.try_recv().is_err());
+    }
+
+    #[test]
+    fn only_calls_that_change_the_totals_need_saving() {
+        let mut state = AppState::default();
+        let route = ProxyRoute {
+            prefix: "anthropic".to_string(),
+            provider_id: "anthropic".to_string(),
+            counter: None,
+            measure: ProxyMeasure::Requests,
+        };
+        let mut call = MeteredCall {
+            provider_id: "anthropic".to_string(),
+            provider_type: ProviderType::Anthropic,
+            model: Some("claude-sonnet-4-5".to_string()),
+            status: 500,
+            tokens: TokenCounts::default(),
+            cost_usd: None,
+            rate_limits: None,
+            at: 1_700_000_000,
+        };
+
+        assert!(!record(&mut state, &route, &call).changed);
+        assert!(state.providers["anthropic"].metered.is_none());
+
+        call.status = 200;
+        let charge = record(&mut state, &route, &call);
+        assert!(charge.changed);
+        assert!(charge.counter.is_none());
+        assert_eq!(state.providers["anthropic"].metered.as_ref().unwrap().requests, 1);
+
+        call.provider_id = "openai:gone".to_string();
+        assert!(!record(&mut state, &route, &call).changed);
+    }
+}
@@ -0,0 +1,261 @@
+// ============== METRICS ==============
+//
+// Prometheus text exposition of the polled usage, served on `/metrics` by the
+// local HTTP API. Gauges are computed from the last report of every provider;
+// fetch errors are counted per provider and `AppError` kind since startup, and
+// dropped webhook deliveries per target.
+
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::providers::anthropic_admin::AnthropicUsageResult;
+use crate::providers::UsageReport;
+use crate::{AppError, AppState, ProviderType};
+
+/// Fetch errors by (provider ID, error kind)
+static FETCH_ERRORS: Mutex<BTreeMap<(String, &'static str), u64>> = Mutex::new(BTreeMap::new());
+
+/// Webhook deliveries given up on, by target ID
+static WEBHOOK_DROPS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Count a failed fetch of `provider_id`
+pub fn record_fetch_error(provider_id: &str, error: &AppError) {
+    let mut errors = FETCH_ERRORS.lock().unwrap();
+    *errors.entry((provider_id.to_string(), error.kind())).or_default() += 1;
+}
+
+/// Count a delivery to webhook `target_id` dropped after its last attempt
+pub fn record_webhook_drop(target_id: &str) {
+    *WEBHOOK_DROPS.lock().unwrap().entry(target_id.to_string()).or_default() += 1;
+}
+
+/// Label value with `\`, `"` and newlines escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Samples of one metric family, rendered under a single HELP/TYPE header
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: vec![],
+        }
+    }
+
+    fn add(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push((labels, value));
+    }
+
+    fn render(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        writeln!(out, "# HELP {} {}", self.name, self.help).ok();
+        writeln!(out, "# TYPE {} {}", self.name, self.kind).ok();
+        for (labels, value) in &self.samples {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                .collect();
+            writeln!(out, "{}{{{}}} {}", self.name, labels.join(","), value).ok();
+        }
+    }
+}
+
+/// Spend per model when the provider reports a breakdown, otherwise the
+/// provider total as `model="all"`
+fn model_costs(report: &UsageReport) -> Vec<(String, f64)> {
+    let models = report
+        .details
+        .get("admin_api")
+        .and_then(|admin| serde_json::from_value::<AnthropicUsageResult>(admin.clone()).ok())
+        .and_then(|admin| admin.models)
+        .filter(|models| !models.is_empty());
+    match (models, report.cost_usd) {
+        (Some(models), _) => models
+            .into_iter()
+            .map(|m| (m.model, m.usage.cost_usd))
+            .collect(),
+        (None, Some(cost)) => vec![("all".to_string(), cost)],
+        (None, None) => vec![],
+    }
+}
+
+/// Render every metric in the Prometheus text format (version 0.0.4)
+pub fn render(state: &AppState) -> String {
+    let mut usage = Family::new(
+        "meterai_usage_percent",
+        "gauge",
+        "Usage of a provider window, in percent of its limit",
+    );
+    let mut reset = Family::new(
+        "meterai_reset_seconds",
+        "gauge",
+        "Seconds until a provider window resets",
+    );
+    let mut cost = Family::new(
+        "meterai_cost_usd",
+        "gauge",
+        "Spend in USD over the current billing period",
+    );
+    let mut fetched = Family::new(
+        "meterai_last_fetch_timestamp_seconds",
+        "gauge",
+        "Unix time of the last successful fetch",
+    );
+    let mut errors = Family::new(
+        "meterai_fetch_errors_total",
+        "counter",
+        "Failed fetches since startup, by error kind",
+    );
+    let mut dropped = Family::new(
+        "meterai_webhook_dropped_total",
+        "counter",
+        "Webhook deliveries dropped after their last retry since startup",
+    );
+
+    let now = Utc::now().timestamp();
+    let mut providers: Vec<_> = state.providers.iter().collect();
+    providers.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (id, provider) in &providers {
+        let label = |window: &str| vec![("provider", id.to_string()), ("window", window.to_string())];
+
+        if provider.config.provider_type == ProviderType::Manual {
+            usage.add(label("quota"), provider.usage.percent as f64);
+            reset.add(label("quota"), (provider.usage.reset_time - now).max(0) as f64);
+            continue;
+        }
+
+        let report = match provider.last_report.as_ref() {
+            Some(report) => report,
+            None => continue,
+        };
+        for window in &report.windows {
+            if let Some(percent) = window.percent {
+                usage.add(label(&window.id), percent);
+            }
+            let resets_at = window
+                .resets_at
+                .as_deref()
+                .and_then(|r| DateTime::parse_from_rfc3339(r).ok());
+            if let Some(resets_at) = resets_at {
+                reset.add(label(&window.id), (resets_at.timestamp() - now).max(0) as f64);
+            }
+        }
+        for (model, usd) in model_costs(report) {
+            cost.add(vec![("provider", id.to_string()), ("model", model)], usd);
+        }
+        fetched.add(vec![("provider", id.to_string())], report.fetched_at as f64);
+    }
+
+    // Every kind of every polled provider, so a first error shows up as an increase
+    let counts = FETCH_ERRORS.lock().unwrap();
+    for (id, provider) in &providers {
+        if provider.config.provider_type == ProviderType::Manual {
+            continue;
+        }
+        for kind in AppError::KINDS {
+            let count = counts.get(&(id.to_string(), *kind)).copied().unwrap_or(0);
+            errors.add(
+                vec![("provider", id.to_string()), ("error", kind.to_string())],
+                count as f64,
+            );
+        }
+    }
+
+    let drops = WEBHOOK_DROPS.lock().unwrap();
+    for target in &state.settings.webhooks {
+        let count = drops.get(&target.id).copied().unwrap_or(0);
+        dropped.add(vec![("webhook", target.id.clone())], count as f64);
+    }
+
+    let mut out = String::new();
+    for family in [usage, reset, cost, fetched, errors, dropped] {
+        family.render(&mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::WindowUsage;
+
+    /// Needs escaping: a backslash, quotes and a newline
+    const COUNTER_ID: &str = "manual:a\\b \"c\"\nd";
+    const OPENAI_ID: &str = "openai:metrics-test";
+
+    /// A manual counter and an OpenAI account fetched without a model breakdown
+    fn state() -> AppState {
+        let mut state = AppState::default();
+        let mut counter = state.providers.remove("manual").unwrap();
+        counter.usage.percent = 42;
+        counter.usage.reset_time = 0;
+        let mut openai = state.providers.remove("openai").unwrap();
+        openai.last_report = Some(UsageReport {
+            provider_id: OPENAI_ID.to_string(),
+            provider_type: ProviderType::OpenAI,
+            windows: vec![WindowUsage {
+                id: "billing_period".to_string(),
+                percent: Some(7.875),
+                used: Some(9.45),
+                limit: Some(120.0),
+                resets_at: None,
+            }],
+            cost_usd: Some(9.45),
+            subscription_type: None,
+            details: serde_json::json!({ "data_source": "organization" }),
+            fetched_at: 1_749_044_710,
+        });
+
+        state.providers.clear();
+        state.providers.insert(COUNTER_ID.to_string(), counter);
+        state.providers.insert(OPENAI_ID.to_string(), openai);
+        state
+    }
+
+    #[test]
+    fn renders_the_text_exposition_format() {
+        record_fetch_error(OPENAI_ID, &AppError::RateLimited(30));
+
+        let expected = r#"# HELP meterai_usage_percent Usage of a provider window, in percent of its limit
+# TYPE meterai_usage_percent gauge
+meterai_usage_percent{provider="manual:a\\b \"c\"\nd",window="quota"} 42
+meterai_usage_percent{provider="openai:metrics-test",window="billing_period"} 7.875
+# HELP meterai_reset_seconds Seconds until a provider window resets
+# TYPE meterai_reset_seconds gauge
+meterai_reset_seconds{provider="manual:a\\b \"c\"\nd",window="quota"} 0
+# HELP meterai_cost_usd Spend in USD over the current billing period
+# TYPE meterai_cost_usd gauge
+meterai_cost_usd{provider="openai:metrics-test",model="all"} 9.45
+# HELP meterai_last_fetch_timestamp_seconds Unix time of the last successful fetch
+# TYPE meterai_last_fetch_timestamp_seconds gauge
+meterai_last_fetch_timestamp_seconds{provider="openai:metrics-test"} 1749044710
+# HELP meterai_fetch_errors_total Failed fetches since startup, by error kind
+# TYPE meterai_fetch_errors_total counter
+meterai_fetch_errors_total{provider="openai:metrics-test",error="api"} 0
+meterai_fetch_errors_total{provider="openai:metrics-test",error="network"} 0
+meterai_fetch_errors_total{provider="openai:metrics-test",error="config"} 0
+meterai_fetch_errors_total{provider="openai:metrics-test",error="keyring"} 0
+meterai_fetch_errors_total{provider="openai:metrics-test",error="rate_limited"} 1
+meterai_fetch_errors_total{provider="openai:metrics-test",error="unauthorized"} 0
+"#;
+        assert_eq!(render(&state()), expected);
+    }
+}
@@ -0,0 +1,257 @@
+// ============== MOCK BACKEND ==============
+//
+// Local HTTP server answering the provider endpoints with recorded responses
+// from `fixtures/mock/`. Enabled with `AppSettings.mock_mode` or METERAI_MOCK=1
+// for offline development and integration tests of the fetch path. A file with
+// the same name in `<data dir>/mock/` replaces the built-in recording.
+//
+// Errors are simulated from the credentials: a key or token ending in "-401"
+// is rejected and one ending in "-429" is rate limited (with Retry-After).
+
+use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::i18n::tr;
+use crate::{get_data_dir, AppError, AppSettings};
+
+/// Retry-After of simulated rate limits
+pub const RETRY_AFTER_SECS: u64 = 30;
+
+/// (request path, fixture file, built-in recording)
+const FIXTURES: &[(&str, &str, &str)] = &[
+    (
+        "/api/oauth/usage",
+        "claude_oauth_usage.json",
+        include_str!("../fixtures/mock/claude_oauth_usage.json"),
+    ),
+    (
+        "/v1/oauth/token",
+        "claude_oauth_token.json",
+        include_str!("../fixtures/mock/claude_oauth_token.json"),
+    ),
+    (
+        "/v1/organizations/usage_report/messages",
+        "anthropic_usage_report.json",
+        include_str!("../fixtures/mock/anthropic_usage_report.json"),
+    ),
+    (
+        "/v1/organizations/cost_report",
+        "anthropic_cost_report.json",
+        include_str!("../fixtures/mock/anthropic_cost_report.json"),
+    ),
+    (
+        "/v1/models",
+        "openai_models.json",
+        include_str!("../fixtures/mock/openai_models.json"),
+    ),
+    (
+        "/v1/organization/costs",
+        "openai_costs.json",
+        include_str!("../fixtures/mock/openai_costs.json"),
+    ),
+    (
+        "/v1/organization/usage/completions",
+        "openai_usage_completions.json",
+        include_str!("../fixtures/mock/openai_usage_completions.json"),
+    ),
+    (
+        "/v1/dashboard/billing/subscription",
+        "openai_subscription.json",
+        include_str!("../fixtures/mock/openai_subscription.json"),
+    ),
+    // Upstream of the metering proxy
+    (
+        "/v1/messages",
+        "anthropic_messages.json",
+        include_str!("../fixtures/mock/anthropic_messages.json"),
+    ),
+    (
+        "/v1/chat/completions",
+        "openai_chat_completions.json",
+        include_str!("../fixtures/mock/openai_chat_completions.json"),
+    ),
+];
+
+/// Whether mock mode was requested in the settings or the environment
+pub fn is_enabled(settings: &AppSettings) -> bool {
+    settings.mock_mode
+        || env::var("METERAI_MOCK")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false)
+}
+
+fn load_fixtures() -> HashMap<&'static str, String> {
+    let override_dir = get_data_dir().join("mock");
+    FIXTURES
+        .iter()
+        .map(|(path, file, builtin)| {
+            let body = fs::read_to_string(override_dir.join(file))
+                .unwrap_or_else(|_| builtin.to_string());
+            (*path, body)
+        })
+        .collect()
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+/// Error simulated for the credentials of `req`, if any
+fn simulated_error(req: &Request<Body>) -> Option<Response<Body>> {
+    let credentials = [req.headers().get("x-api-key"), req.headers().get(AUTHORIZATION)];
+    let credential = credentials
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.ends_with("-401") || value.ends_with("-429"))?;
+
+    if credential.ends_with("-401") {
+        return Some(json_response(
+            StatusCode::UNAUTHORIZED,
+            r#"{"error":{"type":"authentication_error","message":"Invalid credentials"}}"#.to_string(),
+        ));
+    }
+    let mut response = json_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        r#"{"error":{"type":"rate_limit_error","message":"Rate limited"}}"#.to_string(),
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    Some(response)
+}
+
+fn respond(fixtures: &HashMap<&'static str, String>, req: &Request<Body>) -> Response<Body> {
+    if let Some(response) = simulated_error(req) {
+        return response;
+    }
+    match fixtures.get(req.uri().path()) {
+        Some(body) => json_response(StatusCode::OK, body.clone()),
+        None => json_response(
+            StatusCode::NOT_FOUND,
+            r#"{"error":{"type":"not_found_error","message":"No recorded response for this path"}}"#
+                .to_string(),
+        ),
+    }
+}
+
+/// Start the mock server on `addr` (port 0 picks a free port) and return its base URL
+pub async fn start(addr: SocketAddr) -> Result<String, AppError> {
+    let fixtures = Arc::new(load_fixtures());
+
+    let make_svc = make_service_fn(move |_conn| {
+        let fixtures = fixtures.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let response = respond(&fixtures, &req);
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| {
+            AppError::ConfigError(tr!("error.server_start", server = "Mock server", addr = addr, detail = e))
+        })?
+        .serve(make_svc);
+    let base_url = format!("http://{}", server.local_addr());
+
+    tokio::spawn(async move {
+        server.await.ok();
+    });
+
+    Ok(base_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{self, Endpoint, EndpointConfig};
+    use crate::providers::anthropic_admin::fetch_anthropic_admin_usage;
+    use crate::providers::claude_code::fetch_claude_code_usage;
+    use crate::providers::openai::{fetch_openai_usage, OpenAIDataSource};
+    use chrono::DateTime;
+
+    /// Settings pointing every endpoint at a fresh mock server
+    async fn mock_settings() -> AppSettings {
+        let base_url = start(([127, 0, 0, 1], 0).into()).await.unwrap();
+        let mut settings = AppSettings::default();
+        for key in [http::ANTHROPIC, http::OPENAI, http::ANTHROPIC_OAUTH] {
+            let config = EndpointConfig {
+                base_url: Some(base_url.clone()),
+                ..Default::default()
+            };
+            settings.endpoints.insert(key.to_string(), config);
+        }
+        settings
+    }
+
+    #[tokio::test]
+    async fn claude_oauth_usage() {
+        let endpoint = Endpoint::anthropic(&mock_settings().await).unwrap();
+
+        let usage = fetch_claude_code_usage(&endpoint, "sk-ant-oat01-mock").await.unwrap();
+        assert_eq!(usage.five_hour.map(|w| w.utilization), Some(42.0));
+        assert_eq!(usage.seven_day.map(|w| w.utilization), Some(18.0));
+
+        let error = fetch_claude_code_usage(&endpoint, "sk-ant-oat01-mock-401").await;
+        assert!(matches!(error, Err(AppError::Unauthorized(_))));
+        let error = fetch_claude_code_usage(&endpoint, "sk-ant-oat01-mock-429").await;
+        assert!(matches!(error, Err(AppError::RateLimited(RETRY_AFTER_SECS))));
+    }
+
+    #[tokio::test]
+    async fn anthropic_admin_usage() {
+        let endpoint = Endpoint::anthropic(&mock_settings().await).unwrap();
+
+        let result = fetch_anthropic_admin_usage(endpoint.clone(), "sk-ant-admin01-mock").await.unwrap();
+        let cost = result.cost_usd.unwrap();
+        assert!((cost - 3.844582).abs() < 1e-9, "{}", cost);
+        let totals = result.totals.unwrap();
+        assert_eq!(totals.input_tokens, 152_340 + 20_110 + 98_020);
+        assert_eq!(totals.cache_creation_tokens, 20_480 + 8_192);
+        assert_eq!(result.daily_costs.map(|days| days.len()), Some(2));
+
+        let error = fetch_anthropic_admin_usage(endpoint.clone(), "sk-ant-admin01-mock-401").await;
+        assert!(matches!(error, Err(AppError::ApiError(_))));
+        let error = fetch_anthropic_admin_usage(endpoint, "sk-ant-admin01-mock-429").await;
+        assert!(matches!(error, Err(AppError::RateLimited(RETRY_AFTER_SECS))));
+    }
+
+    #[tokio::test]
+    async fn openai_usage() {
+        let endpoint = Endpoint::openai(&mock_settings().await).unwrap();
+
+        for key in ["sk-proj-mock", "sk-admin-mock"] {
+            let result = fetch_openai_usage(endpoint.clone(), key).await.unwrap();
+            assert_eq!(result.data_source, OpenAIDataSource::Organization);
+            let usage = result.usage_usd.unwrap();
+            assert!((usage - 9.45).abs() < 1e-9, "{}", usage);
+            assert_eq!(result.limit_usd, Some(120.0));
+            let resets_at = result.resets_at.unwrap();
+            assert!(DateTime::parse_from_rfc3339(&resets_at).is_ok(), "{}", resets_at);
+        }
+
+        // A rejected regular key fails on /v1/models
+        let error = fetch_openai_usage(endpoint.clone(), "sk-proj-mock-401").await;
+        assert!(matches!(error, Err(AppError::ApiError(_))));
+        // So does a rejected admin key, on the Costs API
+        let error = fetch_openai_usage(endpoint.clone(), "sk-admin-mock-401").await;
+        assert!(matches!(error, Err(AppError::Unauthorized(_))));
+
+        let error = fetch_openai_usage(endpoint, "sk-admin-mock-429").await;
+        assert!(matches!(error, Err(AppError::RateLimited(RETRY_AFTER_SECS))));
+    }
+}
@@ -0,0 +1,289 @@
+// ============== MODEL PRICING ==============
+//
+// Per-model API prices and the cost engine turning token counts into USD: the
+// API-equivalent value of subscription usage (Claude Code transcripts) and a
+// cross-check of API bills. The built-in catalog (`pricing/catalog.json`) is
+// versioned by the date of its last price change; a `pricing.json` in the
+// data folder, with the same layout, replaces entries with the same provider,
+// model and effective date and adds the others.
+//
+// Rates are USD per million tokens. A model uses its catalog entry in effect
+// on the day of the usage, or that of the longest catalog name it is a dated
+// snapshot of ("claude-sonnet-4-5-20250929" → "claude-sonnet-4-5"). Other
+// variants ("o3-pro", "claude-opus-4-6") are unpriced rather than guessed.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{get_data_dir, ProviderType};
+
+/// Token counts of one or more requests. `input` excludes cached tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenCounts {
+    #[serde(default)]
+    pub input: u64,
+    #[serde(default)]
+    pub output: u64,
+    #[serde(default)]
+    pub cache_creation: u64,
+    #[serde(default)]
+    pub cache_read: u64,
+}
+
+impl TokenCounts {
+    pub fn add(&mut self, other: &TokenCounts) {
+        self.input += other.input;
+        self.output += other.output;
+        self.cache_creation += other.cache_creation;
+        self.cache_read += other.cache_read;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.input + self.output + self.cache_creation + self.cache_read
+    }
+}
+
+/// Rates of one model from a given day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub provider: ProviderType,
+    /// Model name, also matching its dated snapshots
+    pub model: String,
+    /// First day the rates apply (always when None)
+    #[serde(rename = "effectiveFrom", default)]
+    pub effective_from: Option<NaiveDate>,
+    pub input: f64,
+    pub output: f64,
+    /// Cache writes (input rate when None)
+    #[serde(rename = "cacheWrite", default)]
+    pub cache_write: Option<f64>,
+    /// Cache reads (input rate when None)
+    #[serde(rename = "cacheRead", default)]
+    pub cache_read: Option<f64>,
+    /// Batch API rates (regular rates when None)
+    #[serde(rename = "batchInput", default)]
+    pub batch_input: Option<f64>,
+    #[serde(rename = "batchOutput", default)]
+    pub batch_output: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingCatalog {
+    pub version: String,
+    #[serde(default)]
+    pub models: Vec<ModelPrice>,
+}
+
+/// USD cost of some token counts
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CostEstimate {
+    pub input_usd: f64,
+    pub output_usd: f64,
+    pub cache_write_usd: f64,
+    pub cache_read_usd: f64,
+    pub total_usd: f64,
+}
+
+/// Local overrides of the built-in catalog
+pub fn get_override_path() -> PathBuf {
+    get_data_dir().join("pricing.json")
+}
+
+impl ModelPrice {
+    pub fn cost(&self, tokens: &TokenCounts, batch: bool) -> CostEstimate {
+        let (input, output) = match (batch, self.batch_input, self.batch_output) {
+            (true, Some(input), Some(output)) => (input, output),
+            _ => (self.input, self.output),
+        };
+        // Cache rates get the same batch discount as input
+        let discount = if self.input > 0.0 { input / self.input } else { 1.0 };
+        let usd = |tokens: u64, rate: f64| tokens as f64 * rate / 1_000_000.0;
+
+        let mut estimate = CostEstimate {
+            input_usd: usd(tokens.input, input),
+            output_usd: usd(tokens.output, output),
+            cache_write_usd: usd(tokens.cache_creation, self.cache_write.unwrap_or(self.input) * discount),
+            cache_read_usd: usd(tokens.cache_read, self.cache_read.unwrap_or(self.input) * discount),
+            total_usd: 0.0,
+        };
+        estimate.total_usd =
+            estimate.input_usd + estimate.output_usd + estimate.cache_write_usd + estimate.cache_read_usd;
+        estimate
+    }
+}
+
+/// `model` is `name` or `name` with a date suffix ("-20250929", "-2024-05-13")
+fn is_snapshot_of(model: &str, name: &str) -> bool {
+    match model.strip_prefix(name) {
+        Some("") => true,
+        Some(suffix) => match suffix.strip_prefix('-') {
+            Some(date) if date.len() == 8 => date.bytes().all(|b| b.is_ascii_digit()),
+            Some(date) if date.len() == 10 => NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok(),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+impl PricingCatalog {
+    pub fn builtin() -> Self {
+        serde_json::from_str(include_str!("../pricing/catalog.json")).expect("invalid built-in pricing catalog")
+    }
+
+    /// Built-in catalog with the local overrides applied (an unreadable
+    /// override file is ignored)
+    pub fn load() -> Self {
+        let mut catalog = Self::builtin();
+        let overrides = fs::read_to_string(get_override_path())
+            .ok()
+            .and_then(|content| serde_json::from_str::<PricingCatalog>(&content).ok());
+        if let Some(overrides) = overrides {
+            catalog.merge(overrides);
+        }
+        catalog
+    }
+
+    fn merge(&mut self, overrides: PricingCatalog) {
+        for price in overrides.models {
+            self.models.retain(|p| {
+                !(p.provider == price.provider && p.model == price.model && p.effective_from == price.effective_from)
+            });
+            self.models.push(price);
+        }
+        // "2025-11-24+local"
+        self.version = format!("{}+{}", self.version, overrides.version);
+    }
+
+    /// Rates of `model` in effect on `date`
+    pub fn price(&self, provider: &ProviderType, model: &str, date: NaiveDate) -> Option<&ModelPrice> {
+        self.models
+            .iter()
+            .filter(|p| &p.provider == provider && is_snapshot_of(model, &p.model))
+            .filter(|p| p.effective_from.is_none_or(|from| from <= date))
+            .max_by_key(|p| (p.model.len(), p.effective_from))
+    }
+
+    /// Cost of `tokens` sent to `model` on `date`, None without a price
+    pub fn cost(
+        &self,
+        provider: &ProviderType,
+        model: &str,
+        tokens: &TokenCounts,
+        date: NaiveDate,
+        batch: bool,
+    ) -> Option<CostEstimate> {
+        self.price(provider, model, date).map(|price| price.cost(tokens, batch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn input_rate(catalog: &PricingCatalog, provider: ProviderType, model: &str, date: &str) -> Option<f64> {
+        catalog.price(&provider, model, day(date)).map(|p| p.input)
+    }
+
+    #[test]
+    fn models_match_their_name_or_a_dated_snapshot() {
+        let catalog = PricingCatalog::builtin();
+        let anthropic = |model| input_rate(&catalog, ProviderType::Anthropic, model, "2025-12-01");
+        let openai = |model| input_rate(&catalog, ProviderType::OpenAI, model, "2025-12-01");
+
+        assert_eq!(anthropic("claude-sonnet-4-5"), Some(3.0));
+        assert_eq!(anthropic("claude-sonnet-4-5-20250929"), Some(3.0));
+        assert_eq!(anthropic("claude-opus-4-5-20251101"), Some(5.0));
+        assert_eq!(anthropic("claude-opus-4-20250514"), Some(15.0));
+        assert_eq!(openai("gpt-4o-mini-2024-07-18"), Some(0.15));
+        // A listed snapshot has its own rates
+        assert_eq!(openai("gpt-4o-2024-05-13"), Some(5.0));
+        assert_eq!(openai("gpt-4o-2024-08-06"), Some(2.5));
+
+        // Other variants aren't guessed from a shorter name
+        assert_eq!(anthropic("claude-opus-4-6"), None);
+        assert_eq!(openai("o3-pro"), None);
+        assert_eq!(openai("gpt-4o-2024-13-45"), None);
+        assert_eq!(openai("gpt-4o-latest"), None);
+        // Nor across providers
+        assert_eq!(openai("claude-sonnet-4-5"), None);
+    }
+
+    #[test]
+    fn rates_follow_the_effective_date() {
+        let catalog = PricingCatalog::builtin();
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "o3", "2025-06-09"), Some(10.0));
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "o3-2025-04-16", "2025-06-10"), Some(2.0));
+        // Before its release a model is unpriced...
+        assert_eq!(input_rate(&catalog, ProviderType::Anthropic, "claude-opus-4-5", "2025-11-23"), None);
+
+        // ...or falls back to a shorter name in effect then
+        let mut catalog = catalog;
+        catalog.merge(PricingCatalog {
+            version: "local".to_string(),
+            models: vec![ModelPrice {
+                model: "gpt-4o-2024-08-06".to_string(),
+                effective_from: Some(day("2030-01-01")),
+                input: 1.0,
+                ..catalog.price(&ProviderType::OpenAI, "gpt-4o", day("2025-12-01")).unwrap().clone()
+            }],
+        });
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "gpt-4o-2024-08-06", "2025-12-01"), Some(2.5));
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "gpt-4o-2024-08-06", "2030-01-01"), Some(1.0));
+    }
+
+    #[test]
+    fn batch_discount_applies_to_cache_rates() {
+        let catalog = PricingCatalog::builtin();
+        let price = catalog.price(&ProviderType::Anthropic, "claude-sonnet-4-5", day("2025-12-01")).unwrap();
+        let tokens = TokenCounts {
+            input: 1_000_000,
+            output: 1_000_000,
+            cache_creation: 1_000_000,
+            cache_read: 1_000_000,
+        };
+
+        let regular = price.cost(&tokens, false);
+        assert_eq!(regular.input_usd, 3.0);
+        assert_eq!(regular.output_usd, 15.0);
+        assert_eq!(regular.cache_write_usd, 3.75);
+        assert_eq!(regular.cache_read_usd, 0.3);
+        assert_eq!(regular.total_usd, 22.05);
+
+        let batch = price.cost(&tokens, true);
+        assert_eq!(batch.input_usd, 1.5);
+        assert_eq!(batch.output_usd, 7.5);
+        assert_eq!(batch.cache_write_usd, 1.875);
+        assert_eq!(batch.cache_read_usd, 0.15);
+    }
+
+    #[test]
+    fn overrides_replace_same_date_entries_and_add_others() {
+        let mut catalog = PricingCatalog::builtin();
+        let entries = catalog.models.len();
+        let base = catalog.price(&ProviderType::OpenAI, "o3", day("2025-12-01")).unwrap().clone();
+        catalog.merge(PricingCatalog {
+            version: "local".to_string(),
+            models: vec![
+                ModelPrice { input: 1.5, ..base.clone() },
+                ModelPrice {
+                    model: "o3-pro".to_string(),
+                    effective_from: None,
+                    input: 20.0,
+                    ..base
+                },
+            ],
+        });
+
+        assert_eq!(catalog.models.len(), entries + 1);
+        assert!(catalog.version.ends_with("+local"));
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "o3", "2025-12-01"), Some(1.5));
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "o3", "2025-01-01"), Some(10.0));
+        assert_eq!(input_rate(&catalog, ProviderType::OpenAI, "o3-pro", "2025-12-01"), Some(20.0));
+    }
+}
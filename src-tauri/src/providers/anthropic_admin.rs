@@ -0,0 +1,383 @@
+// Claude API usage and cost via the Anthropic Admin API
+//
+// Requires an admin key (sk-ant-admin...). Usage comes from
+// /v1/organizations/usage_report/messages and spend from
+// /v1/organizations/cost_report, both bucketed by day.
+
+use chrono::{DateTime, Datelike, Duration, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::http::{self, Endpoint};
+use crate::i18n::tr;
+use crate::pricing::{PricingCatalog, TokenCounts};
+use crate::{AppError, ProviderType};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Prefix of Anthropic admin keys
+pub const ADMIN_KEY_PREFIX: &str = "sk-ant-admin";
+/// Safety net against a backend that keeps answering has_more
+const MAX_PAGES: usize = 50;
+
+// ---------- Raw API types ----------
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReportPage<T> {
+    data: Vec<ReportBucket<T>>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReportBucket<T> {
+    starting_at: String,
+    results: Vec<T>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct CacheCreation {
+    #[serde(default)]
+    ephemeral_5m_input_tokens: u64,
+    #[serde(default)]
+    ephemeral_1h_input_tokens: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MessagesUsageResult {
+    #[serde(default)]
+    uncached_input_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+    #[serde(default)]
+    cache_creation: CacheCreation,
+    #[serde(default)]
+    output_tokens: u64,
+    model: Option<String>,
+    workspace_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CostResult {
+    /// Cost in the lowest currency unit (cents), as a decimal string
+    amount: String,
+    #[serde(default)]
+    currency: Option<String>,
+    model: Option<String>,
+    workspace_id: Option<String>,
+}
+
+// ---------- Summaries returned to the UI ----------
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnthropicTokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicModelUsage {
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: AnthropicTokenUsage,
+    /// Cost of the tokens at catalog prices, to cross-check `cost_usd`
+    pub estimated_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicWorkspaceUsage {
+    /// None for the default workspace
+    pub workspace_id: Option<String>,
+    #[serde(flatten)]
+    pub usage: AnthropicTokenUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicDailyCostSummary {
+    pub date: String,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicUsageResult {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Total spend in USD for the current month
+    pub cost_usd: Option<f64>,
+    /// Totals across all models and workspaces
+    pub totals: Option<AnthropicTokenUsage>,
+    /// Per-model breakdown, most expensive first
+    pub models: Option<Vec<AnthropicModelUsage>>,
+    /// Per-workspace breakdown, most expensive first
+    pub workspaces: Option<Vec<AnthropicWorkspaceUsage>>,
+    /// Daily spend
+    pub daily_costs: Option<Vec<AnthropicDailyCostSummary>>,
+    pub period_start: Option<String>,
+    pub period_end: Option<String>,
+}
+
+impl AnthropicUsageResult {
+    pub fn failed(error: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            cost_usd: None,
+            totals: None,
+            models: None,
+            workspaces: None,
+            daily_costs: None,
+            period_start: None,
+            period_end: None,
+        }
+    }
+}
+
+// ---------- Client ----------
+
+pub struct AnthropicAdminClient {
+    endpoint: Endpoint,
+    api_key: String,
+}
+
+impl AnthropicAdminClient {
+    pub fn new(endpoint: Endpoint, api_key: &str) -> Self {
+        Self {
+            endpoint,
+            api_key: api_key.to_string(),
+        }
+    }
+
+    /// Fetch every page of a report endpoint
+    async fn fetch_report<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Vec<ReportBucket<T>>, AppError> {
+        let mut buckets = Vec::new();
+        let mut page: Option<String> = None;
+
+        for _ in 0..MAX_PAGES {
+            let mut request = self
+                .endpoint
+                .get(path)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .query(query);
+            if let Some(ref token) = page {
+                request = request.query(&[("page", token)]);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+            http::check_rate_limit(&response)?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(AppError::ApiError(tr!(
+                    "error.api_status",
+                    service = "Admin API",
+                    status = status,
+                    body = body
+                )));
+            }
+
+            let report: ReportPage<T> = response
+                .json()
+                .await
+                .map_err(|e| AppError::ApiError(tr!("error.parse_response", detail = e)))?;
+
+            buckets.extend(report.data);
+            match (report.has_more, report.next_page) {
+                (true, Some(next)) => page = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok(buckets)
+    }
+
+    /// Verify the admin key with a minimal one-day usage request
+    pub async fn validate(&self) -> Result<(), AppError> {
+        let end = Utc::now();
+        let start = end - Duration::days(1);
+        self.fetch_report::<MessagesUsageResult>(
+            "/v1/organizations/usage_report/messages",
+            &[
+                ("starting_at", rfc3339(start)),
+                ("ending_at", rfc3339(end)),
+                ("bucket_width", "1d".to_string()),
+                ("limit", "1".to_string()),
+            ],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Usage and cost for `[start, end)`, bucketed by day
+    pub async fn fetch_usage(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<AnthropicUsageResult, AppError> {
+        let range = [("starting_at", rfc3339(start)), ("ending_at", rfc3339(end))];
+
+        let usage_buckets = self
+            .fetch_report::<MessagesUsageResult>(
+                "/v1/organizations/usage_report/messages",
+                &[
+                    range[0].clone(),
+                    range[1].clone(),
+                    ("bucket_width", "1d".to_string()),
+                    ("group_by[]", "model".to_string()),
+                    ("group_by[]", "workspace_id".to_string()),
+                ],
+            )
+            .await?;
+
+        let cost_buckets = self
+            .fetch_report::<CostResult>(
+                "/v1/organizations/cost_report",
+                &[
+                    range[0].clone(),
+                    range[1].clone(),
+                    ("group_by[]", "workspace_id".to_string()),
+                    ("group_by[]", "description".to_string()),
+                ],
+            )
+            .await?;
+
+        Ok(summarize(&usage_buckets, &cost_buckets, start, end))
+    }
+}
+
+fn rfc3339(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Convert a cost report amount (cents, decimal string) to USD
+fn cents_to_usd(result: &CostResult) -> f64 {
+    match result.currency.as_deref() {
+        Some(currency) if !currency.eq_ignore_ascii_case("USD") => 0.0,
+        _ => result.amount.parse::<f64>().unwrap_or(0.0) / 100.0,
+    }
+}
+
+fn summarize(
+    usage_buckets: &[ReportBucket<MessagesUsageResult>],
+    cost_buckets: &[ReportBucket<CostResult>],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> AnthropicUsageResult {
+    let mut totals = AnthropicTokenUsage::default();
+    let mut models: BTreeMap<String, AnthropicTokenUsage> = BTreeMap::new();
+    let mut workspaces: BTreeMap<Option<String>, AnthropicTokenUsage> = BTreeMap::new();
+    let mut daily: BTreeMap<String, f64> = BTreeMap::new();
+
+    for bucket in usage_buckets {
+        for result in &bucket.results {
+            let cache_creation = result.cache_creation.ephemeral_5m_input_tokens
+                + result.cache_creation.ephemeral_1h_input_tokens;
+            let model = result.model.clone().unwrap_or_else(|| "unknown".to_string());
+            for entry in [
+                &mut totals,
+                models.entry(model).or_default(),
+                workspaces.entry(result.workspace_id.clone()).or_default(),
+            ] {
+                entry.input_tokens += result.uncached_input_tokens;
+                entry.output_tokens += result.output_tokens;
+                entry.cache_read_tokens += result.cache_read_input_tokens;
+                entry.cache_creation_tokens += cache_creation;
+            }
+        }
+    }
+
+    for bucket in cost_buckets {
+        let date = bucket.starting_at.get(..10).unwrap_or(bucket.starting_at.as_str()).to_string();
+        for result in &bucket.results {
+            let cost = cents_to_usd(result);
+            totals.cost_usd += cost;
+            *daily.entry(date.clone()).or_default() += cost;
+            // Non-token costs (web search, code execution) have no model
+            if let Some(ref model) = result.model {
+                models.entry(model.clone()).or_default().cost_usd += cost;
+            }
+            workspaces
+                .entry(result.workspace_id.clone())
+                .or_default()
+                .cost_usd += cost;
+        }
+    }
+
+    // Cache writes are priced at the 5-minute rate
+    let catalog = PricingCatalog::load();
+    let mut models: Vec<AnthropicModelUsage> = models
+        .into_iter()
+        .map(|(model, usage)| {
+            let tokens = TokenCounts {
+                input: usage.input_tokens,
+                output: usage.output_tokens,
+                cache_creation: usage.cache_creation_tokens,
+                cache_read: usage.cache_read_tokens,
+            };
+            let estimated_cost_usd = catalog
+                .cost(&ProviderType::Anthropic, &model, &tokens, end.date_naive(), false)
+                .map(|estimate| estimate.total_usd);
+            AnthropicModelUsage {
+                model,
+                usage,
+                estimated_cost_usd,
+            }
+        })
+        .collect();
+    models.sort_by(|a, b| b.usage.cost_usd.total_cmp(&a.usage.cost_usd));
+
+    let mut workspaces: Vec<AnthropicWorkspaceUsage> = workspaces
+        .into_iter()
+        .map(|(workspace_id, usage)| AnthropicWorkspaceUsage { workspace_id, usage })
+        .collect();
+    workspaces.sort_by(|a, b| b.usage.cost_usd.total_cmp(&a.usage.cost_usd));
+
+    AnthropicUsageResult {
+        success: true,
+        error: None,
+        cost_usd: Some(totals.cost_usd),
+        totals: Some(totals),
+        models: Some(models),
+        workspaces: Some(workspaces),
+        daily_costs: Some(
+            daily
+                .into_iter()
+                .map(|(date, cost_usd)| AnthropicDailyCostSummary { date, cost_usd })
+                .collect(),
+        ),
+        period_start: Some(start.format("%Y-%m-%d").to_string()),
+        period_end: Some(end.format("%Y-%m-%d").to_string()),
+    }
+}
+
+/// Start of the current calendar month (UTC) and now
+pub fn current_month_range() -> (DateTime<Utc>, DateTime<Utc>) {
+    let now = Utc::now();
+    let start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now);
+    (start, now)
+}
+
+/// Fetch current-month usage and cost with an admin key
+pub async fn fetch_anthropic_admin_usage(
+    endpoint: Endpoint,
+    api_key: &str,
+) -> Result<AnthropicUsageResult, AppError> {
+    let (start, end) = current_month_range();
+    AnthropicAdminClient::new(endpoint, api_key)
+        .fetch_usage(start, end)
+        .await
+}
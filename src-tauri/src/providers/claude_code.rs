@@ -0,0 +1,427 @@
+// Claude usage: Pro/Max windows via the Claude Code OAuth API, plus API spend
+// via the Admin API when an admin key is configured on the provider
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::anthropic_admin::{self, AnthropicAdminClient, AnthropicUsageResult};
+use super::{FetchContext, UsageProvider, UsageReport, WindowDescriptor, WindowUsage};
+use crate::http::{self, Endpoint};
+use crate::i18n::tr;
+use crate::token_store::{self, load_token_metadata, TokenAccount};
+use crate::{AppError, AppSettings, ProviderType};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeUsageWindow {
+    pub utilization: f64,
+    pub resets_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeUsageResponse {
+    pub five_hour: Option<ClaudeUsageWindow>,
+    pub seven_day: Option<ClaudeUsageWindow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeCodeUsageResult {
+    pub success: bool,
+    pub error: Option<String>,
+    pub five_hour_percent: Option<f64>,
+    pub five_hour_reset: Option<String>,
+    pub seven_day_percent: Option<f64>,
+    pub seven_day_reset: Option<String>,
+    pub subscription_type: Option<String>, // "pro", "max", etc.
+}
+
+impl ClaudeCodeUsageResult {
+    pub fn from_response(usage: ClaudeUsageResponse, subscription_type: Option<String>) -> Self {
+        // API returns utilization already as percentage (0-100), no need to multiply
+        Self {
+            success: true,
+            error: None,
+            five_hour_percent: usage.five_hour.as_ref().map(|w| w.utilization),
+            five_hour_reset: usage.five_hour.and_then(|w| w.resets_at),
+            seven_day_percent: usage.seven_day.as_ref().map(|w| w.utilization),
+            seven_day_reset: usage.seven_day.and_then(|w| w.resets_at),
+            subscription_type,
+        }
+    }
+
+    /// Subscription windows in the provider-agnostic shape
+    pub fn windows(&self) -> Vec<WindowUsage> {
+        vec![
+            WindowUsage {
+                id: "five_hour".to_string(),
+                percent: self.five_hour_percent,
+                used: None,
+                limit: None,
+                resets_at: self.five_hour_reset.clone(),
+            },
+            WindowUsage {
+                id: "seven_day".to_string(),
+                percent: self.seven_day_percent,
+                used: None,
+                limit: None,
+                resets_at: self.seven_day_reset.clone(),
+            },
+        ]
+    }
+
+    pub fn failed(error: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            five_hour_percent: None,
+            five_hour_reset: None,
+            seven_day_percent: None,
+            seven_day_reset: None,
+            subscription_type: None,
+        }
+    }
+}
+
+/// Fetch usage from Claude Code OAuth API
+pub async fn fetch_claude_code_usage(
+    endpoint: &Endpoint,
+    token: &str,
+) -> Result<ClaudeUsageResponse, AppError> {
+    let response = endpoint
+        .get("/api/oauth/usage")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("anthropic-beta", "oauth-2025-04-20")
+        .header("User-Agent", "claude-code/2.0.32")
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+    http::check_rate_limit(&response)?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::Unauthorized(body));
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::ApiError(tr!(
+            "error.api_status",
+            service = "API",
+            status = status,
+            body = body
+        )));
+    }
+
+    let usage: ClaudeUsageResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::ApiError(tr!("error.parse_response", detail = e)))?;
+
+    Ok(usage)
+}
+
+/// Fetch usage with the internal token of `account`, renewing it shortly
+/// before it expires and once on a 401. Without an internal token, the Claude
+/// Code credentials on disk are used as-is (Claude Code keeps them fresh).
+pub async fn fetch_claude_code_usage_internal(
+    account: &TokenAccount,
+    settings: &AppSettings,
+    endpoint: &Endpoint,
+) -> Result<ClaudeUsageResponse, AppError> {
+    let metadata = match load_token_metadata(account).filter(|meta| meta.token.is_some()) {
+        Some(metadata) => metadata,
+        None => {
+            let token = account
+                .source_token()
+                .ok_or_else(|| AppError::ConfigError(tr!("error.no_token_available")))?;
+            return fetch_claude_code_usage(endpoint, &token).await;
+        }
+    };
+
+    let mut token = metadata.token.clone().unwrap_or_default();
+    let mut refreshed = false;
+    if metadata.expires_soon() {
+        match token_store::refresh_internal_token(account, settings).await {
+            Ok(data) => {
+                token = data.token.unwrap_or_default();
+                refreshed = true;
+            }
+            // Still valid for a few minutes: the next fetch tries again
+            Err(_) if metadata.seconds_left().is_some_and(|left| left > 0) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    match fetch_claude_code_usage(endpoint, &token).await {
+        Err(AppError::Unauthorized(_)) if !refreshed => {
+            let data = token_store::refresh_internal_token(account, settings).await?;
+            fetch_claude_code_usage(endpoint, &data.token.unwrap_or_default()).await
+        }
+        result => result,
+    }
+}
+
+pub struct ClaudeCodeProvider;
+
+impl ClaudeCodeProvider {
+    /// Account of the provider instance being fetched
+    fn account(ctx: &FetchContext) -> TokenAccount {
+        TokenAccount::with_path(&ctx.settings, &ctx.provider_id, ctx.config.credentials_path.clone())
+    }
+
+    /// Whether an internal token or a Claude Code login is available
+    fn has_token(account: &TokenAccount) -> bool {
+        load_token_metadata(account).and_then(|meta| meta.token).is_some()
+            || account.source_token().is_some()
+    }
+
+    /// Whether Claude Code is logged in for the provider being fetched
+    pub fn is_logged_in(ctx: &FetchContext) -> bool {
+        Self::has_token(&Self::account(ctx))
+    }
+
+    /// Admin key configured on the provider, if any. Regular API keys saved
+    /// there by earlier versions are ignored.
+    fn admin_key(ctx: &FetchContext) -> Option<&str> {
+        ctx.config
+            .api_key
+            .as_deref()
+            .filter(|key| key.starts_with(anthropic_admin::ADMIN_KEY_PREFIX))
+    }
+}
+
+#[async_trait]
+impl UsageProvider for ClaudeCodeProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Anthropic
+    }
+
+    fn default_id(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Anthropic (Claude)"
+    }
+
+    fn windows(&self) -> Vec<WindowDescriptor> {
+        vec![
+            WindowDescriptor {
+                id: "five_hour".to_string(),
+                label: "5 hours".to_string(),
+                duration_hours: Some(5),
+            },
+            WindowDescriptor {
+                id: "seven_day".to_string(),
+                label: "7 days".to_string(),
+                duration_hours: Some(7 * 24),
+            },
+            WindowDescriptor {
+                id: "billing_period".to_string(),
+                label: "API spend (month)".to_string(),
+                duration_hours: None,
+            },
+        ]
+    }
+
+    fn check_api_key_format(&self, api_key: &str) -> Result<(), AppError> {
+        if !api_key.starts_with(anthropic_admin::ADMIN_KEY_PREFIX) {
+            return Err(AppError::ConfigError(tr!("error.invalid_admin_key_format")));
+        }
+        Ok(())
+    }
+
+    async fn validate_credentials(&self, ctx: &FetchContext) -> Result<(), AppError> {
+        let endpoint = Endpoint::anthropic(&ctx.settings)?;
+        if let Some(api_key) = Self::admin_key(ctx) {
+            return AnthropicAdminClient::new(endpoint, api_key).validate().await;
+        }
+        fetch_claude_code_usage_internal(&Self::account(ctx), &ctx.settings, &endpoint)
+            .await
+            .map(|_| ())
+    }
+
+    async fn fetch_usage(&self, ctx: &FetchContext) -> Result<UsageReport, AppError> {
+        let endpoint = Endpoint::anthropic(&ctx.settings)?;
+        let admin_key = Self::admin_key(ctx);
+
+        // Subscription windows (Claude Code OAuth)
+        let account = Self::account(ctx);
+        let oauth = if Self::has_token(&account) {
+            let usage = fetch_claude_code_usage_internal(&account, &ctx.settings, &endpoint).await;
+            Some(usage.map(|usage| {
+                let subscription_type = account.source_info().and_then(|info| info.subscription_type);
+                ClaudeCodeUsageResult::from_response(usage, subscription_type)
+            }))
+        } else if admin_key.is_none() {
+            // A missing Claude Code login is only an error without an admin key
+            Some(Err(AppError::ConfigError(tr!("error.no_token_available"))))
+        } else {
+            None
+        };
+
+        // API spend (Admin API)
+        let admin = match admin_key {
+            Some(api_key) => Some(anthropic_admin::fetch_anthropic_admin_usage(endpoint, api_key).await),
+            None => None,
+        };
+
+        // Nothing fetched: report the first error
+        let (oauth, admin) = match (oauth, admin) {
+            (Some(Err(e)), None | Some(Err(_))) | (None, Some(Err(e))) => return Err(e),
+            (None, None) => return Err(AppError::ConfigError(tr!("error.no_claude_credentials"))),
+            results => results,
+        };
+
+        let mut windows = Vec::new();
+        if let Some(Ok(result)) = &oauth {
+            windows.extend(result.windows());
+        }
+        if let Some(Ok(result)) = &admin {
+            windows.push(WindowUsage {
+                id: "billing_period".to_string(),
+                percent: None,
+                used: result.cost_usd,
+                limit: None,
+                resets_at: None,
+            });
+        }
+        // The half that failed stays in the details with its error
+        let oauth_result = oauth.map(|r| r.unwrap_or_else(|e| ClaudeCodeUsageResult::failed(e.to_string())));
+        let admin_result = admin.map(|r| r.unwrap_or_else(|e| AnthropicUsageResult::failed(e.to_string())));
+
+        Ok(UsageReport {
+            provider_id: ctx.provider_id.clone(),
+            provider_type: ProviderType::Anthropic,
+            windows,
+            cost_usd: admin_result.as_ref().and_then(|r| r.cost_usd),
+            subscription_type: oauth_result.as_ref().and_then(|r| r.subscription_type.clone()),
+            details: serde_json::json!({
+                "claude_code": oauth_result,
+                "admin_api": admin_result,
+            }),
+            fetched_at: Utc::now().timestamp(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::EndpointConfig;
+    use crate::mock_server;
+    use crate::providers::default_entry;
+    use std::fs;
+
+    /// Context of a named Claude account fetched from a fresh mock server,
+    /// logged in to Claude Code with `token` and configured with `api_key`
+    async fn context(token: Option<&str>, api_key: Option<&str>) -> FetchContext {
+        let base_url = mock_server::start(([127, 0, 0, 1], 0).into()).await.unwrap();
+        let mut settings = AppSettings::default();
+        let endpoint = EndpointConfig {
+            base_url: Some(base_url),
+            ..Default::default()
+        };
+        settings.endpoints.insert(http::ANTHROPIC.to_string(), endpoint);
+
+        // Named accounts only read their own credentials file
+        let account = format!("test-{}", rand::random::<u32>());
+        let entry = default_entry(&ClaudeCodeProvider);
+        let mut config = entry.config;
+        config.api_key = api_key.map(str::to_string);
+        config.credentials_path = token.map(|token| {
+            let path = std::env::temp_dir().join(format!("meterai-{}.json", account));
+            let credentials = serde_json::json!({
+                "claudeAiOauth": { "accessToken": token, "subscriptionType": "max" }
+            });
+            fs::write(&path, credentials.to_string()).unwrap();
+            path.to_string_lossy().to_string()
+        });
+
+        FetchContext {
+            provider_id: format!("anthropic:{}", account),
+            config,
+            usage: entry.usage,
+            settings,
+        }
+    }
+
+    async fn fetch(token: Option<&str>, api_key: Option<&str>) -> Result<UsageReport, AppError> {
+        let ctx = context(token, api_key).await;
+        let report = ClaudeCodeProvider.fetch_usage(&ctx).await;
+        if let Some(path) = &ctx.config.credentials_path {
+            fs::remove_file(path).ok();
+        }
+        report
+    }
+
+    fn window_ids(report: &UsageReport) -> Vec<&str> {
+        report.windows.iter().map(|w| w.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn admin_usage_is_parsed() {
+        let report = fetch(Some("sk-ant-oat01-mock"), Some("sk-ant-admin01-mock")).await.unwrap();
+        assert_eq!(window_ids(&report), ["five_hour", "seven_day", "billing_period"]);
+        assert_eq!(report.subscription_type.as_deref(), Some("max"));
+
+        let admin: AnthropicUsageResult = serde_json::from_value(report.details["admin_api"].clone()).unwrap();
+        assert!((admin.cost_usd.unwrap() - 3.844582).abs() < 1e-9);
+        assert_eq!(report.cost_usd, admin.cost_usd);
+
+        // Most expensive model first; web search has no model
+        let models = admin.models.unwrap();
+        let names: Vec<&str> = models.iter().map(|m| m.model.as_str()).collect();
+        assert_eq!(names, ["claude-sonnet-4-20250514", "claude-opus-4-20250514"]);
+        assert!((models[0].usage.cost_usd - 2.965557).abs() < 1e-9);
+        assert_eq!(models[0].usage.output_tokens, 48_210 + 30_125);
+        assert_eq!(models[1].usage.input_tokens, 20_110);
+
+        let workspaces = admin.workspaces.unwrap();
+        assert_eq!(workspaces.len(), 2);
+        assert_eq!(workspaces[0].workspace_id, None);
+
+        let days = admin.daily_costs.unwrap();
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, "2025-06-01");
+        assert!((days[1].cost_usd - 0.551875).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn admin_failure_keeps_subscription_windows() {
+        let report = fetch(Some("sk-ant-oat01-mock"), Some("sk-ant-admin01-mock-401")).await.unwrap();
+        assert_eq!(window_ids(&report), ["five_hour", "seven_day"]);
+        assert_eq!(report.cost_usd, None);
+
+        let admin = &report.details["admin_api"];
+        assert_eq!(admin["success"], false);
+        assert!(admin["error"].as_str().is_some_and(|e| !e.is_empty()));
+        assert_eq!(report.details["claude_code"]["success"], true);
+    }
+
+    #[tokio::test]
+    async fn oauth_failure_keeps_admin_spend() {
+        let report = fetch(Some("sk-ant-oat01-mock-429"), Some("sk-ant-admin01-mock")).await.unwrap();
+        assert_eq!(window_ids(&report), ["billing_period"]);
+        assert!(report.cost_usd.is_some());
+        assert_eq!(report.details["claude_code"]["success"], false);
+    }
+
+    #[tokio::test]
+    async fn regular_api_key_is_not_used_as_admin_key() {
+        let report = fetch(Some("sk-ant-oat01-mock"), Some("sk-ant-api03-mock")).await.unwrap();
+        assert_eq!(window_ids(&report), ["five_hour", "seven_day"]);
+        assert!(report.details["admin_api"].is_null());
+    }
+
+    #[tokio::test]
+    async fn nothing_fetched_is_an_error() {
+        let error = fetch(None, None).await;
+        assert!(matches!(error, Err(AppError::ConfigError(_))));
+        let error = fetch(Some("sk-ant-oat01-mock-401"), Some("sk-ant-admin01-mock-429")).await;
+        assert!(matches!(error, Err(AppError::Unauthorized(_))));
+        let error = fetch(None, Some("sk-ant-admin01-mock-429")).await;
+        assert!(matches!(error, Err(AppError::RateLimited(_))));
+    }
+}
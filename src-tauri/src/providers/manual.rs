@@ -0,0 +1,63 @@
+// Manual request counter (no remote backend)
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::{FetchContext, UsageProvider, UsageReport, WindowDescriptor, WindowUsage};
+use crate::reset_policy;
+use crate::{AppError, ProviderConfig, ProviderType};
+
+pub struct ManualProvider;
+
+#[async_trait]
+impl UsageProvider for ManualProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::Manual
+    }
+
+    fn default_id(&self) -> &'static str {
+        "manual"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Manual"
+    }
+
+    fn windows(&self) -> Vec<WindowDescriptor> {
+        vec![WindowDescriptor {
+            id: "quota".to_string(),
+            label: "Quota".to_string(),
+            duration_hours: None,
+        }]
+    }
+
+    fn default_config(&self) -> ProviderConfig {
+        ProviderConfig::default()
+    }
+
+    async fn validate_credentials(&self, _ctx: &FetchContext) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn fetch_usage(&self, ctx: &FetchContext) -> Result<UsageReport, AppError> {
+        let usage = &ctx.usage;
+        Ok(UsageReport {
+            provider_id: ctx.provider_id.clone(),
+            provider_type: ProviderType::Manual,
+            windows: vec![WindowUsage {
+                id: "quota".to_string(),
+                percent: Some(usage.percent as f64),
+                used: Some(usage.used),
+                limit: Some(usage.limit),
+                resets_at: Some(usage.reset_time)
+                    .filter(|at| *at != reset_policy::IDLE)
+                    .and_then(|at| DateTime::<Utc>::from_timestamp(at, 0))
+                    .map(|dt| dt.to_rfc3339()),
+            }],
+            cost_usd: None,
+            subscription_type: None,
+            details: serde_json::Value::Null,
+            fetched_at: Utc::now().timestamp(),
+        })
+    }
+}
@@ -0,0 +1,288 @@
+// ============== USAGE PROVIDERS ==============
+//
+// Every backend MeterAI can track implements `UsageProvider` and is registered
+// in the `ProviderRegistry`. Commands look providers up by their `ProviderType`
+// instead of branching on hard-coded IDs, so adding a backend only means adding
+// a module here and registering it in `ProviderRegistry::builtin`.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::budgets;
+use crate::forecast;
+use crate::history;
+use crate::metrics;
+use crate::i18n::tr;
+use crate::{check_window_alerts, raise_alert, save_state, AppError, AppSettings, AppState, ProviderConfig, ProviderType, ProviderUsage, UsageData};
+
+pub mod anthropic_admin;
+pub mod claude_code;
+pub mod manual;
+pub mod openai;
+
+/// Static description of a usage window exposed by a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowDescriptor {
+    /// Stable identifier (e.g. "five_hour", "billing_period")
+    pub id: String,
+    /// Human readable label
+    pub label: String,
+    /// Window length in hours (None for billing periods and plain counters)
+    pub duration_hours: Option<u32>,
+}
+
+/// Usage of a single window as reported by a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowUsage {
+    pub id: String,
+    /// Usage percentage (0-100)
+    pub percent: Option<f64>,
+    pub used: Option<f64>,
+    pub limit: Option<f64>,
+    /// When the window resets (RFC 3339)
+    pub resets_at: Option<String>,
+}
+
+/// Provider-agnostic result of a usage fetch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub provider_id: String,
+    pub provider_type: ProviderType,
+    pub windows: Vec<WindowUsage>,
+    /// Spend in USD for the current period (API providers only)
+    pub cost_usd: Option<f64>,
+    pub subscription_type: Option<String>,
+    /// Provider-specific payload (e.g. `ClaudeCodeUsageResult`, `OpenAIUsageResult`)
+    pub details: serde_json::Value,
+    /// Unix timestamp of the fetch
+    pub fetched_at: i64,
+}
+
+impl UsageReport {
+    /// First window that carries a percentage, used for the compact display
+    pub fn primary_window(&self) -> Option<&WindowUsage> {
+        self.windows.iter().find(|w| w.percent.is_some())
+    }
+}
+
+/// Description of a registered provider for the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderDescriptor {
+    pub provider_type: ProviderType,
+    pub default_id: String,
+    pub name: String,
+    pub requires_api_key: bool,
+    pub windows: Vec<WindowDescriptor>,
+}
+
+/// Everything a provider needs to fetch usage, snapshotted from `AppState`
+/// so no lock is held across network calls.
+#[derive(Debug, Clone)]
+pub struct FetchContext {
+    pub provider_id: String,
+    pub config: ProviderConfig,
+    pub usage: UsageData,
+    pub settings: AppSettings,
+}
+
+impl FetchContext {
+    pub fn from_state(state: &AppState, provider_id: &str) -> Option<Self> {
+        let provider = state.providers.get(provider_id)?;
+        Some(Self {
+            provider_id: provider_id.to_string(),
+            config: provider.config.clone(),
+            usage: provider.usage.clone(),
+            settings: state.settings.clone(),
+        })
+    }
+
+    /// Configured API key, or a configuration error if missing
+    pub fn require_api_key(&self) -> Result<&str, AppError> {
+        match self.config.api_key.as_deref() {
+            Some(key) if !key.is_empty() => Ok(key),
+            _ => Err(AppError::ConfigError(tr!(
+                "error.no_api_key",
+                provider = self.config.name
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+pub trait UsageProvider: Send + Sync {
+    fn provider_type(&self) -> ProviderType;
+
+    /// ID of the built-in instance in `AppState.providers`
+    fn default_id(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    /// Windows/limits this provider reports
+    fn windows(&self) -> Vec<WindowDescriptor>;
+
+    /// Whether the provider reads `ProviderConfig.api_key`
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    /// Default configuration for a fresh install
+    fn default_config(&self) -> ProviderConfig {
+        ProviderConfig {
+            provider_type: self.provider_type(),
+            name: self.display_name().to_string(),
+            enabled: false,
+            ..ProviderConfig::default()
+        }
+    }
+
+    /// Cheap offline check of an API key before it is stored
+    fn check_api_key_format(&self, _api_key: &str) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Check that the configured credentials are accepted by the backend
+    async fn validate_credentials(&self, ctx: &FetchContext) -> Result<(), AppError>;
+
+    async fn fetch_usage(&self, ctx: &FetchContext) -> Result<UsageReport, AppError>;
+}
+
+#[derive(Clone, Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn UsageProvider>>,
+}
+
+impl ProviderRegistry {
+    /// Registry with every provider shipped with MeterAI
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register(manual::ManualProvider);
+        registry.register(claude_code::ClaudeCodeProvider);
+        registry.register(openai::OpenAIProvider);
+        registry
+    }
+
+    pub fn register<P: UsageProvider + 'static>(&mut self, provider: P) {
+        self.providers.push(Arc::new(provider));
+    }
+
+    pub fn get(&self, provider_type: &ProviderType) -> Option<Arc<dyn UsageProvider>> {
+        self.providers
+            .iter()
+            .find(|p| &p.provider_type() == provider_type)
+            .cloned()
+    }
+
+    pub fn describe(&self) -> Vec<ProviderDescriptor> {
+        self.providers
+            .iter()
+            .map(|p| ProviderDescriptor {
+                provider_type: p.provider_type(),
+                default_id: p.default_id().to_string(),
+                name: p.display_name().to_string(),
+                requires_api_key: p.requires_api_key(),
+                windows: p.windows(),
+            })
+            .collect()
+    }
+
+    /// Add a default entry for every registered provider missing from the state
+    pub fn ensure_defaults(&self, state: &mut AppState) {
+        for provider in &self.providers {
+            state
+                .providers
+                .entry(provider.default_id().to_string())
+                .or_insert_with(|| default_entry(provider.as_ref()));
+        }
+    }
+}
+
+/// Fresh `ProviderUsage` for a provider using its default configuration
+pub fn default_entry(provider: &dyn UsageProvider) -> ProviderUsage {
+    let config = provider.default_config();
+    ProviderUsage {
+        usage: UsageData {
+            used: 0.0,
+            limit: config.limit,
+            percent: 0,
+            reset_time: Utc::now().timestamp() + (config.reset_interval_hours as i64 * 3600),
+            history: vec![],
+            provider_type: config.provider_type.clone(),
+            provider_name: config.name.clone(),
+        },
+        config,
+        notified_thresholds: vec![],
+        notified_windows: HashMap::new(),
+        last_report: None,
+        metered: None,
+    }
+}
+
+/// Look up the provider backing `provider_id` and snapshot its fetch context
+fn resolve(
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+    provider_id: &str,
+) -> Result<(Arc<dyn UsageProvider>, FetchContext), AppError> {
+    let state = state.lock().unwrap();
+    let ctx = FetchContext::from_state(&state, provider_id)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.provider_not_found")))?;
+    let provider = registry.get(&ctx.config.provider_type).ok_or_else(|| {
+        AppError::ConfigError(tr!("error.no_backend", provider = format!("{:?}", ctx.config.provider_type)))
+    })?;
+    Ok((provider, ctx))
+}
+
+/// Fetch usage for `provider_id` and store the report in the state
+pub async fn fetch_provider(
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+    provider_id: &str,
+) -> Result<UsageReport, AppError> {
+    let (provider, ctx) = resolve(state, registry, provider_id)?;
+    let report = match provider.fetch_usage(&ctx).await {
+        Ok(report) => report,
+        Err(e) => {
+            metrics::record_fetch_error(provider_id, &e);
+            return Err(e);
+        }
+    };
+
+    let mut alerts = Vec::new();
+    let mut state = state.lock().unwrap();
+    if let Some(entry) = state.providers.get_mut(provider_id) {
+        // Manual counters already own their percentage
+        if entry.config.provider_type != ProviderType::Manual {
+            if let Some(percent) = report.primary_window().and_then(|w| w.percent) {
+                entry.usage.percent = percent.round() as u32;
+            }
+            alerts = check_window_alerts(provider_id, entry, &report.windows);
+        }
+        entry.last_report = Some(report.clone());
+        alerts.extend(forecast::check_alerts(&mut state, provider_id, report.fetched_at));
+    }
+    if report.cost_usd.is_some() {
+        alerts.extend(budgets::check_alerts(&mut state, report.fetched_at));
+    }
+    save_state(&state);
+    drop(state);
+
+    // Showing a notification can block: only once the state is released
+    alerts.into_iter().for_each(raise_alert);
+
+    history::record_report(&report);
+
+    Ok(report)
+}
+
+/// Validate the credentials configured for `provider_id`
+pub async fn validate_provider(
+    state: &Mutex<AppState>,
+    registry: &ProviderRegistry,
+    provider_id: &str,
+) -> Result<(), AppError> {
+    let (provider, ctx) = resolve(state, registry, provider_id)?;
+    provider.validate_credentials(&ctx).await
+}
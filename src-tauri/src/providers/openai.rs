@@ -0,0 +1,734 @@
+// OpenAI API usage
+//
+// Spend comes from the organization Costs API and token usage from the
+// organization Usage API (both require an admin key, sk-admin-...). The
+// deprecated dashboard billing endpoints are only used as a fallback for keys
+// the organization APIs refuse. When no source answers, the result is marked
+// as unavailable instead of reporting zero usage; an admin key the Costs API
+// rejects with a 401 is an error, since no other source accepts it.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{FetchContext, UsageProvider, UsageReport, WindowDescriptor, WindowUsage};
+use crate::http::{self, Endpoint};
+use crate::i18n::tr;
+use crate::pricing::{PricingCatalog, TokenCounts};
+use crate::{AppError, ProviderType};
+
+/// Safety net against a backend that keeps answering has_more
+const MAX_PAGES: usize = 50;
+/// Prefix of organization admin keys, which only the organization APIs accept
+const ADMIN_KEY_PREFIX: &str = "sk-admin-";
+
+// ---------- Organization Usage / Costs API ----------
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrgPage<T> {
+    data: Vec<OrgBucket<T>>,
+    #[serde(default)]
+    has_more: bool,
+    next_page: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrgBucket<T> {
+    start_time: i64,
+    results: Vec<T>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrgCostAmount {
+    value: f64,
+    currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrgCostResult {
+    amount: OrgCostAmount,
+    project_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OrgCompletionsResult {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    input_cached_tokens: u64,
+    #[serde(default)]
+    num_model_requests: u64,
+    model: Option<String>,
+    project_id: Option<String>,
+    api_key_id: Option<String>,
+}
+
+// ---------- Legacy dashboard billing API (deprecated) ----------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIUsageResponse {
+    pub total_usage: f64, // Usage in cents
+    #[serde(default)]
+    pub daily_costs: Vec<OpenAIDailyCost>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIDailyCost {
+    pub timestamp: f64,
+    pub line_items: Vec<OpenAILineItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAILineItem {
+    pub name: String,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAISubscriptionResponse {
+    pub hard_limit_usd: Option<f64>,
+    pub soft_limit_usd: Option<f64>,
+    pub system_hard_limit_usd: Option<f64>,
+}
+
+// ---------- Result ----------
+
+/// Where the usage figures came from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenAIDataSource {
+    /// Organization Usage/Costs API (admin key)
+    Organization,
+    /// Deprecated dashboard billing endpoints
+    LegacyBilling,
+    /// No endpoint returned usage data
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenAITokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub requests: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIUsageGroup {
+    /// Model name, project ID or API key ID depending on the grouping
+    pub key: String,
+    #[serde(flatten)]
+    pub usage: OpenAITokenUsage,
+    /// Spend in USD (projects only; the Costs API does not split by model or key)
+    pub cost_usd: Option<f64>,
+    /// Cost of the tokens at catalog prices (models only)
+    pub estimated_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenAIUsageBreakdown {
+    pub models: Vec<OpenAIUsageGroup>,
+    pub projects: Vec<OpenAIUsageGroup>,
+    pub api_keys: Vec<OpenAIUsageGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIUsageResult {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Whether usage figures could be retrieved at all
+    pub usage_available: bool,
+    pub data_source: OpenAIDataSource,
+    /// Total usage in USD for current billing period (None when unavailable)
+    pub usage_usd: Option<f64>,
+    /// Hard limit in USD
+    pub limit_usd: Option<f64>,
+    /// Usage percentage (0-100), only when both usage and limit are known
+    pub percent: Option<f64>,
+    /// Whether this is a pay-as-you-go account (no hard limit)
+    pub is_pay_as_you_go: bool,
+    /// Daily breakdown
+    pub daily_costs: Option<Vec<OpenAIDailyCostSummary>>,
+    /// Token usage grouped by model, project and API key (organization API only)
+    pub breakdown: Option<OpenAIUsageBreakdown>,
+    /// Billing period start date
+    pub period_start: Option<String>,
+    /// Billing period end date
+    pub period_end: Option<String>,
+    /// Start of the next billing period (RFC 3339)
+    pub resets_at: Option<String>,
+}
+
+impl OpenAIUsageResult {
+    pub fn failed(error: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            usage_available: false,
+            data_source: OpenAIDataSource::Unavailable,
+            usage_usd: None,
+            limit_usd: None,
+            percent: None,
+            is_pay_as_you_go: false,
+            daily_costs: None,
+            breakdown: None,
+            period_start: None,
+            period_end: None,
+            resets_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIDailyCostSummary {
+    pub date: String,
+    pub cost_usd: f64,
+}
+
+// ---------- Client ----------
+
+pub struct OpenAIClient {
+    endpoint: Endpoint,
+    api_key: String,
+}
+
+/// Outcome of an optional endpoint: refused means the key lacks access
+enum Fetched<T> {
+    Ok(T),
+    Refused(reqwest::StatusCode),
+}
+
+impl OpenAIClient {
+    pub fn new(endpoint: Endpoint, api_key: &str) -> Self {
+        Self {
+            endpoint,
+            api_key: api_key.to_string(),
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.endpoint
+            .get(path)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+    }
+
+    fn is_admin_key(&self) -> bool {
+        self.api_key.starts_with(ADMIN_KEY_PREFIX)
+    }
+
+    /// Verify the API key with a simple models request, or a one-day costs
+    /// request for admin keys (which /v1/models refuses)
+    pub async fn validate(&self) -> Result<(), AppError> {
+        if self.is_admin_key() {
+            let now = Utc::now().timestamp();
+            return match self.fetch_org_costs(now - 86_400, now).await? {
+                Fetched::Ok(_) => Ok(()),
+                Fetched::Refused(reqwest::StatusCode::UNAUTHORIZED) => Err(admin_key_rejected()),
+                Fetched::Refused(status) => {
+                    Err(AppError::ApiError(tr!("error.invalid_openai_key", status = status)))
+                }
+            };
+        }
+
+        let response = self
+            .get("/v1/models")
+            .send()
+            .await
+            .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+        http::check_rate_limit(&response)?;
+        if !response.status().is_success() {
+            return Err(AppError::ApiError(tr!(
+                "error.invalid_openai_key",
+                status = response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fetch every page of an organization endpoint
+    async fn fetch_org_pages<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Fetched<Vec<OrgBucket<T>>>, AppError> {
+        let mut buckets = Vec::new();
+        let mut page: Option<String> = None;
+
+        for _ in 0..MAX_PAGES {
+            let mut request = self.get(path).query(query);
+            if let Some(ref token) = page {
+                request = request.query(&[("page", token)]);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+            http::check_rate_limit(&response)?;
+            let status = response.status();
+            if status == reqwest::StatusCode::UNAUTHORIZED
+                || status == reqwest::StatusCode::FORBIDDEN
+                || status == reqwest::StatusCode::NOT_FOUND
+            {
+                return Ok(Fetched::Refused(status));
+            }
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(AppError::ApiError(tr!(
+                    "error.api_status",
+                    service = "Organization API",
+                    status = status,
+                    body = body
+                )));
+            }
+
+            let report: OrgPage<T> = response
+                .json()
+                .await
+                .map_err(|e| AppError::ApiError(tr!("error.parse_response", detail = e)))?;
+
+            buckets.extend(report.data);
+            match (report.has_more, report.next_page) {
+                (true, Some(next)) => page = Some(next),
+                _ => break,
+            }
+        }
+
+        Ok(Fetched::Ok(buckets))
+    }
+
+    /// Daily spend from the organization Costs API
+    async fn fetch_org_costs(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Fetched<Vec<OrgBucket<OrgCostResult>>>, AppError> {
+        self.fetch_org_pages(
+            "/v1/organization/costs",
+            &[
+                ("start_time", start.to_string()),
+                ("end_time", end.to_string()),
+                ("bucket_width", "1d".to_string()),
+                ("group_by", "project_id".to_string()),
+                ("limit", "31".to_string()),
+            ],
+        )
+        .await
+    }
+
+    /// Token usage from the organization Usage API, grouped by model, project and key
+    async fn fetch_org_completions(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Fetched<Vec<OrgBucket<OrgCompletionsResult>>>, AppError> {
+        self.fetch_org_pages(
+            "/v1/organization/usage/completions",
+            &[
+                ("start_time", start.to_string()),
+                ("end_time", end.to_string()),
+                ("bucket_width", "1d".to_string()),
+                ("group_by", "model".to_string()),
+                ("group_by", "project_id".to_string()),
+                ("group_by", "api_key_id".to_string()),
+                ("limit", "31".to_string()),
+            ],
+        )
+        .await
+    }
+
+    /// Usage from the deprecated dashboard endpoint (amounts in cents)
+    async fn fetch_legacy_usage(
+        &self,
+        start_date: &str,
+        end_date: &str,
+    ) -> Option<(f64, Vec<OpenAIDailyCostSummary>)> {
+        let response = self
+            .get("/v1/dashboard/billing/usage")
+            .query(&[("start_date", start_date), ("end_date", end_date)])
+            .send()
+            .await
+            .ok()
+            .filter(|resp| resp.status().is_success())?;
+
+        let usage_data = response.json::<OpenAIUsageResponse>().await.ok()?;
+        let costs = usage_data
+            .daily_costs
+            .iter()
+            .map(|day| {
+                let total_cost: f64 = day.line_items.iter().map(|li| li.cost).sum();
+                let date = DateTime::from_timestamp(day.timestamp as i64, 0)
+                    .map(|dt| dt.format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| "Unknown".to_string());
+                OpenAIDailyCostSummary {
+                    date,
+                    cost_usd: total_cost / 100.0,
+                }
+            })
+            .collect();
+        Some((usage_data.total_usage / 100.0, costs))
+    }
+
+    /// Account hard limit from the deprecated subscription endpoint.
+    /// The organization APIs expose no equivalent, so this stays best-effort.
+    async fn fetch_legacy_limit(&self) -> Option<f64> {
+        let response = self
+            .get("/v1/dashboard/billing/subscription")
+            .send()
+            .await
+            .ok()
+            .filter(|resp| resp.status().is_success())?;
+
+        response
+            .json::<OpenAISubscriptionResponse>()
+            .await
+            .ok()
+            .and_then(|sub_data| {
+                sub_data
+                    .hard_limit_usd
+                    .or(sub_data.soft_limit_usd)
+                    .or(sub_data.system_hard_limit_usd)
+            })
+    }
+}
+
+fn admin_key_rejected() -> AppError {
+    AppError::Unauthorized(tr!("error.invalid_openai_key", status = reqwest::StatusCode::UNAUTHORIZED))
+}
+
+fn summarize_costs(buckets: &[OrgBucket<OrgCostResult>]) -> (f64, Vec<OpenAIDailyCostSummary>, BTreeMap<String, f64>) {
+    let mut total = 0.0;
+    let mut daily = Vec::new();
+    let mut projects: BTreeMap<String, f64> = BTreeMap::new();
+
+    for bucket in buckets {
+        let mut day_total = 0.0;
+        for result in &bucket.results {
+            let is_usd = result
+                .amount
+                .currency
+                .as_deref()
+                .map(|c| c.eq_ignore_ascii_case("usd"))
+                .unwrap_or(true);
+            if !is_usd {
+                continue;
+            }
+            day_total += result.amount.value;
+            let project = result.project_id.clone().unwrap_or_else(|| "default".to_string());
+            *projects.entry(project).or_default() += result.amount.value;
+        }
+        total += day_total;
+        daily.push(OpenAIDailyCostSummary {
+            date: DateTime::from_timestamp(bucket.start_time, 0)
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            cost_usd: day_total,
+        });
+    }
+
+    (total, daily, projects)
+}
+
+fn summarize_completions(
+    buckets: &[OrgBucket<OrgCompletionsResult>],
+    project_costs: &BTreeMap<String, f64>,
+) -> OpenAIUsageBreakdown {
+    let mut models: BTreeMap<String, OpenAITokenUsage> = BTreeMap::new();
+    let mut projects: BTreeMap<String, OpenAITokenUsage> = BTreeMap::new();
+    let mut api_keys: BTreeMap<String, OpenAITokenUsage> = BTreeMap::new();
+
+    for bucket in buckets {
+        for result in &bucket.results {
+            let keys = [
+                (&mut models, result.model.clone()),
+                (&mut projects, result.project_id.clone()),
+                (&mut api_keys, result.api_key_id.clone()),
+            ];
+            for (map, key) in keys {
+                let entry = map.entry(key.unwrap_or_else(|| "default".to_string())).or_default();
+                entry.input_tokens += result.input_tokens;
+                entry.output_tokens += result.output_tokens;
+                entry.cached_input_tokens += result.input_cached_tokens;
+                entry.requests += result.num_model_requests;
+            }
+        }
+    }
+
+    let into_groups = |map: BTreeMap<String, OpenAITokenUsage>, costs: Option<&BTreeMap<String, f64>>| {
+        let mut groups: Vec<OpenAIUsageGroup> = map
+            .into_iter()
+            .map(|(key, usage)| OpenAIUsageGroup {
+                cost_usd: costs.and_then(|c| c.get(&key).copied()),
+                key,
+                usage,
+                estimated_cost_usd: None,
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            (b.usage.input_tokens + b.usage.output_tokens)
+                .cmp(&(a.usage.input_tokens + a.usage.output_tokens))
+        });
+        groups
+    };
+
+    // `input_tokens` includes the cached ones
+    let catalog = PricingCatalog::load();
+    let today = Utc::now().date_naive();
+    let mut models = into_groups(models, None);
+    for group in &mut models {
+        let tokens = TokenCounts {
+            input: group.usage.input_tokens.saturating_sub(group.usage.cached_input_tokens),
+            output: group.usage.output_tokens,
+            cache_creation: 0,
+            cache_read: group.usage.cached_input_tokens,
+        };
+        group.estimated_cost_usd = catalog
+            .cost(&ProviderType::OpenAI, &group.key, &tokens, today, false)
+            .map(|estimate| estimate.total_usd);
+    }
+
+    OpenAIUsageBreakdown {
+        models,
+        projects: into_groups(projects, Some(project_costs)),
+        api_keys: into_groups(api_keys, None),
+    }
+}
+
+/// Fetch OpenAI API usage for the current month
+pub async fn fetch_openai_usage(endpoint: Endpoint, api_key: &str) -> Result<OpenAIUsageResult, AppError> {
+    let client = OpenAIClient::new(endpoint, api_key);
+
+    // First, verify the API key is valid. Admin keys are checked by the
+    // organization requests themselves.
+    if !client.is_admin_key() {
+        client.validate().await?;
+    }
+
+    // Calculate date range for current month (UTC, like the billing period)
+    let now = Utc::now();
+    let (month_start, next_month) = month_bounds(now);
+    let start_date = month_start.format("%Y-%m-%d").to_string();
+    let end_date = (now + chrono::Duration::days(1)).format("%Y-%m-%d").to_string();
+    let (month_start, now_ts) = (month_start.timestamp(), now.timestamp());
+
+    // Organization Costs API first, deprecated dashboard endpoint as fallback
+    let (data_source, usage_usd, daily_costs, breakdown) =
+        match client.fetch_org_costs(month_start, now_ts).await? {
+            Fetched::Ok(buckets) => {
+                let (total, daily, project_costs) = summarize_costs(&buckets);
+                // Token breakdown is optional: a failure here keeps the cost figures
+                let breakdown = match client.fetch_org_completions(month_start, now_ts).await {
+                    Ok(Fetched::Ok(buckets)) => Some(summarize_completions(&buckets, &project_costs)),
+                    _ => None,
+                };
+                (OpenAIDataSource::Organization, Some(total), Some(daily), breakdown)
+            }
+            Fetched::Refused(reqwest::StatusCode::UNAUTHORIZED) if client.is_admin_key() => {
+                return Err(admin_key_rejected())
+            }
+            Fetched::Refused(_) => match client.fetch_legacy_usage(&start_date, &end_date).await {
+                Some((total, daily)) => (OpenAIDataSource::LegacyBilling, Some(total), Some(daily), None),
+                None => (OpenAIDataSource::Unavailable, None, None, None),
+            },
+        };
+
+    let limit_usd = client.fetch_legacy_limit().await;
+    let usage_available = usage_usd.is_some();
+
+    // Percentage only makes sense with both a spend figure and a limit
+    let percent = match (usage_usd, limit_usd) {
+        (Some(usage), Some(limit)) if limit > 0.0 => Some((usage / limit * 100.0).min(100.0)),
+        _ => None,
+    };
+
+    Ok(OpenAIUsageResult {
+        success: true,
+        error: if usage_available {
+            None
+        } else {
+            Some(tr!("error.openai_usage_unavailable"))
+        },
+        usage_available,
+        data_source,
+        usage_usd,
+        limit_usd,
+        percent,
+        // Determine if pay-as-you-go (no limit set)
+        is_pay_as_you_go: usage_available && limit_usd.is_none(),
+        daily_costs,
+        breakdown,
+        period_start: Some(start_date),
+        period_end: Some(end_date),
+        resets_at: Some(next_month.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    })
+}
+
+/// First instant of the month of `now` and of the next month (UTC)
+fn month_bounds(now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    let start = Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0).single().unwrap_or(now);
+    let next = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single().unwrap_or(now);
+    (start, next)
+}
+
+pub struct OpenAIProvider;
+
+#[async_trait]
+impl UsageProvider for OpenAIProvider {
+    fn provider_type(&self) -> ProviderType {
+        ProviderType::OpenAI
+    }
+
+    fn default_id(&self) -> &'static str {
+        "openai"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "OpenAI (ChatGPT)"
+    }
+
+    fn windows(&self) -> Vec<WindowDescriptor> {
+        vec![WindowDescriptor {
+            id: "billing_period".to_string(),
+            label: "Billing period".to_string(),
+            duration_hours: None,
+        }]
+    }
+
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    fn check_api_key_format(&self, api_key: &str) -> Result<(), AppError> {
+        if api_key.is_empty() {
+            return Err(AppError::ConfigError(tr!("error.empty_api_key")));
+        }
+        // OpenAI API keys start with sk-
+        if !api_key.starts_with("sk-") {
+            return Err(AppError::ConfigError(tr!("error.invalid_openai_key_format")));
+        }
+        Ok(())
+    }
+
+    async fn validate_credentials(&self, ctx: &FetchContext) -> Result<(), AppError> {
+        OpenAIClient::new(Endpoint::openai(&ctx.settings)?, ctx.require_api_key()?)
+            .validate()
+            .await
+    }
+
+    async fn fetch_usage(&self, ctx: &FetchContext) -> Result<UsageReport, AppError> {
+        let result = fetch_openai_usage(Endpoint::openai(&ctx.settings)?, ctx.require_api_key()?).await?;
+
+        Ok(UsageReport {
+            provider_id: ctx.provider_id.clone(),
+            provider_type: ProviderType::OpenAI,
+            windows: vec![WindowUsage {
+                id: "billing_period".to_string(),
+                percent: result.percent,
+                used: result.usage_usd,
+                limit: result.limit_usd,
+                resets_at: result.resets_at.clone(),
+            }],
+            cost_usd: result.usage_usd,
+            subscription_type: None,
+            details: serde_json::to_value(&result).unwrap_or_default(),
+            fetched_at: Utc::now().timestamp(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn buckets<T: serde::de::DeserializeOwned>(data: serde_json::Value) -> Vec<OrgBucket<T>> {
+        serde_json::from_value(data).unwrap()
+    }
+
+    #[test]
+    fn costs_add_up_per_day_and_project_in_usd_only() {
+        let buckets = buckets(json!([
+            {
+                "start_time": 1748736000,
+                "results": [
+                    { "amount": { "value": 3.5, "currency": "usd" }, "project_id": "proj_a" },
+                    { "amount": { "value": 1.25, "currency": "USD" }, "project_id": null },
+                    { "amount": { "value": 100.0, "currency": "eur" }, "project_id": "proj_a" }
+                ]
+            },
+            {
+                "start_time": 1748822400,
+                "results": [{ "amount": { "value": 2.0, "currency": null }, "project_id": "proj_a" }]
+            },
+            { "start_time": 1748908800, "results": [] }
+        ]));
+
+        let (total, daily, projects) = summarize_costs(&buckets);
+        assert_eq!(total, 6.75);
+        let daily: Vec<_> = daily.iter().map(|d| (d.date.as_str(), d.cost_usd)).collect();
+        assert_eq!(daily, [("2025-06-01", 4.75), ("2025-06-02", 2.0), ("2025-06-03", 0.0)]);
+        assert_eq!(projects.get("proj_a"), Some(&5.5));
+        assert_eq!(projects.get("default"), Some(&1.25));
+        assert_eq!(projects.len(), 2);
+    }
+
+    #[test]
+    fn completions_group_by_model_project_and_key() {
+        let buckets = buckets(json!([
+            {
+                "start_time": 1748736000,
+                "results": [
+                    {
+                        "input_tokens": 1_000_000, "output_tokens": 100_000, "input_cached_tokens": 400_000,
+                        "num_model_requests": 10, "model": "gpt-4o-2024-08-06", "project_id": "proj_a", "api_key_id": "key_1"
+                    },
+                    {
+                        "input_tokens": 50, "output_tokens": 5, "num_model_requests": 1,
+                        "model": "unpriced-model", "project_id": null, "api_key_id": "key_1"
+                    }
+                ]
+            },
+            {
+                "start_time": 1748822400,
+                "results": [{
+                    "input_tokens": 2_000_000, "output_tokens": 0, "num_model_requests": 3,
+                    "model": "gpt-4o-2024-08-06", "project_id": "proj_a", "api_key_id": null
+                }]
+            }
+        ]));
+        let project_costs = BTreeMap::from([("proj_a".to_string(), 12.5)]);
+
+        let breakdown = summarize_completions(&buckets, &project_costs);
+        let keys = |groups: &[OpenAIUsageGroup]| groups.iter().map(|g| g.key.clone()).collect::<Vec<_>>();
+        // Largest first
+        assert_eq!(keys(&breakdown.models), ["gpt-4o-2024-08-06", "unpriced-model"]);
+        assert_eq!(keys(&breakdown.projects), ["proj_a", "default"]);
+        assert_eq!(keys(&breakdown.api_keys), ["default", "key_1"]);
+
+        let model = &breakdown.models[0];
+        assert_eq!(model.usage.input_tokens, 3_000_000);
+        assert_eq!(model.usage.output_tokens, 100_000);
+        assert_eq!(model.usage.cached_input_tokens, 400_000);
+        assert_eq!(model.usage.requests, 13);
+        // 2.6M uncached at $2.5, 0.4M cached at $1.25 and 0.1M output at $10
+        assert!((model.estimated_cost_usd.unwrap() - 8.0).abs() < 1e-9, "{:?}", model.estimated_cost_usd);
+        assert_eq!(model.cost_usd, None);
+        assert_eq!(breakdown.models[1].estimated_cost_usd, None);
+
+        // Spend is only known per project
+        assert_eq!(breakdown.projects[0].cost_usd, Some(12.5));
+        assert_eq!(breakdown.projects[1].cost_usd, None);
+        assert_eq!(breakdown.api_keys[1].usage.requests, 11);
+    }
+}
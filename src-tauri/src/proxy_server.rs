@@ -0,0 +1,76 @@
+// ============== METERING PROXY SERVER ==============
+//
+// Runs the metering proxy (`meter_ai::metering_proxy`) for the app and
+// charges every call to the app state: the account's metered totals, the
+// route's counter (pushed like any counter change) and a `proxy-call` event
+// for the window and `/events`. The state is saved by the scheduler tick
+// rather than on every call.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tokio::task::JoinHandle;
+
+use meter_ai::metering_proxy::{self, MeteredCall, ProxyRoute};
+use meter_ai::{raise_alert, save_state, AppError, AppState};
+
+use crate::{api_server, publish_counter};
+
+static RUNNING: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+/// Calls were charged since the last save
+static UNSAVED: AtomicBool = AtomicBool::new(false);
+
+/// Stop the running proxy, if any, and wait until its port is released
+pub async fn stop() {
+    let task = RUNNING.lock().unwrap().take();
+    if let Some(task) = task {
+        task.abort();
+        task.await.ok();
+    }
+}
+
+/// (Re)start the proxy with the saved configuration, or stop it when disabled
+pub async fn apply(app: AppHandle) -> Result<(), AppError> {
+    stop().await;
+
+    let on_call = {
+        let app = app.clone();
+        Arc::new(move |route: &ProxyRoute, call: MeteredCall| charge(&app, route, call))
+    };
+    let state = app.state::<Mutex<AppState>>();
+    let state = state.lock().unwrap();
+    let config = &state.settings.metering_proxy;
+    if !config.enabled {
+        return Ok(());
+    }
+    let (_, task) = metering_proxy::start(&state, config, on_call)?;
+    *RUNNING.lock().unwrap() = Some(task);
+    Ok(())
+}
+
+fn charge(app: &AppHandle, route: &ProxyRoute, call: MeteredCall) {
+    let alerts = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        let charge = metering_proxy::record(&mut state, route, &call);
+        if charge.changed {
+            UNSAVED.store(true, Ordering::Relaxed);
+        }
+
+        app.emit_all("proxy-call", &call).ok();
+        api_server::broadcast("proxy-call", &call);
+        charge.counter.map_or_else(Vec::new, |(counter_id, usage, alerts)| {
+            publish_counter(app, &state, &counter_id, &usage);
+            alerts
+        })
+    };
+    alerts.into_iter().for_each(raise_alert);
+}
+
+/// Save the calls charged since the last save (scheduler tick and quit)
+pub fn save_charges(app: &AppHandle) {
+    if UNSAVED.swap(false, Ordering::Relaxed) {
+        let state = app.state::<Mutex<AppState>>();
+        save_state(&state.lock().unwrap());
+    }
+}
@@ -0,0 +1,334 @@
+// ============== RESET SCHEDULES ==============
+//
+// When a manual counter starts a new period. Besides the historical
+// `reset_interval_hours` after the previous reset, a counter can follow a
+// rolling window opened by its first use, a calendar time (daily, weekly,
+// monthly, in any IANA timezone) or the `resets_at` a fetched provider
+// reports. Due resets are applied by a background timer (`apply_due_resets`),
+// not only on the next `add_request`.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::tr;
+use crate::{AppError, AppState};
+
+/// `UsageData.reset_time` of a rolling window waiting for its first use
+pub const IDLE: i64 = 0;
+
+/// Whether the period ending at `reset_time` is over (never for an idle
+/// rolling window)
+pub fn is_due(reset_time: i64, now: i64) -> bool {
+    reset_time != IDLE && now >= reset_time
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ResetPolicy {
+    /// `reset_interval_hours` after the previous reset
+    #[default]
+    Interval,
+    /// `hours` from the first use after a reset
+    Rolling { hours: u32 },
+    /// Every day at `time` ("HH:MM", local time when `timezone` is None)
+    Daily {
+        time: String,
+        #[serde(default)]
+        timezone: Option<String>,
+    },
+    /// Every week on `weekday` ("mon", "tuesday", ...) at `time`
+    Weekly {
+        weekday: Weekday,
+        time: String,
+        #[serde(default)]
+        timezone: Option<String>,
+    },
+    /// Every month on `day` (the last day of shorter months) at `time`
+    Monthly {
+        day: u32,
+        time: String,
+        #[serde(default)]
+        timezone: Option<String>,
+    },
+    /// The `resets_at` of a fetched provider's window (its primary window
+    /// when `window` is None)
+    Provider {
+        #[serde(rename = "providerId")]
+        provider_id: String,
+        #[serde(default)]
+        window: Option<String>,
+    },
+}
+
+fn invalid(detail: impl std::fmt::Display) -> AppError {
+    AppError::ConfigError(tr!("error.invalid_reset_policy", detail = detail))
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, AppError> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| invalid(time))
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz, AppError> {
+    timezone.parse::<Tz>().map_err(|_| invalid(timezone))
+}
+
+impl ResetPolicy {
+    /// Check the times, timezones and ranges before the policy is stored
+    pub fn validate(&self, state: &AppState) -> Result<(), AppError> {
+        let (time, timezone) = match self {
+            ResetPolicy::Interval => return Ok(()),
+            ResetPolicy::Rolling { hours } => {
+                return if *hours > 0 { Ok(()) } else { Err(invalid(hours)) };
+            }
+            ResetPolicy::Provider { provider_id, .. } => {
+                return if state.providers.contains_key(provider_id) {
+                    Ok(())
+                } else {
+                    Err(AppError::ConfigError(tr!("error.provider_not_found")))
+                };
+            }
+            ResetPolicy::Monthly { day, .. } if !(1..=31).contains(day) => return Err(invalid(day)),
+            ResetPolicy::Daily { time, timezone }
+            | ResetPolicy::Weekly { time, timezone, .. }
+            | ResetPolicy::Monthly { time, timezone, .. } => (time, timezone),
+        };
+        parse_time(time)?;
+        if let Some(timezone) = timezone {
+            parse_timezone(timezone)?;
+        }
+        Ok(())
+    }
+
+    /// Reset time of a period starting at `now`: `IDLE` for rolling windows,
+    /// the next calendar time or the provider's `resets_at`, and
+    /// `interval_hours` from now otherwise (and when the schedule can't be
+    /// evaluated)
+    pub fn next_reset(&self, interval_hours: u32, state: &AppState, now: i64) -> i64 {
+        let scheduled = match self {
+            ResetPolicy::Interval => None,
+            ResetPolicy::Rolling { .. } => return IDLE,
+            ResetPolicy::Provider { provider_id, window } => provider_reset(state, provider_id, window.as_deref()),
+            ResetPolicy::Daily { time, timezone }
+            | ResetPolicy::Weekly { time, timezone, .. }
+            | ResetPolicy::Monthly { time, timezone, .. } => {
+                let now = DateTime::<Utc>::from_timestamp(now, 0).unwrap_or_else(Utc::now);
+                match (parse_time(time), timezone.as_deref().map(parse_timezone)) {
+                    (Ok(time), None) => next_calendar(&Local, self, time, now),
+                    (Ok(time), Some(Ok(zone))) => next_calendar(&zone, self, time, now),
+                    _ => None,
+                }
+            }
+        };
+        scheduled
+            .filter(|at| *at > now)
+            .unwrap_or(now + interval_hours as i64 * 3600)
+    }
+
+    /// Length of a rolling window once it is opened
+    pub fn rolling_hours(&self) -> Option<u32> {
+        match self {
+            ResetPolicy::Rolling { hours } => Some(*hours),
+            _ => None,
+        }
+    }
+
+    /// Reset time of an idle period first used at `now`: a rolling window
+    /// opens for its length, other schedules keep `next_reset`
+    pub fn open_window(&self, next_reset: i64, now: i64) -> i64 {
+        match self.rolling_hours() {
+            Some(hours) => now + hours as i64 * 3600,
+            None => next_reset,
+        }
+    }
+}
+
+/// `resets_at` of `window` in the last report of `provider_id`
+pub fn provider_reset(state: &AppState, provider_id: &str, window: Option<&str>) -> Option<i64> {
+    let report = state.providers.get(provider_id)?.last_report.as_ref()?;
+    let window = match window {
+        Some(id) => report.windows.iter().find(|w| w.id == id),
+        None => report.primary_window(),
+    }?;
+    DateTime::parse_from_rfc3339(window.resets_at.as_deref()?)
+        .ok()
+        .map(|at| at.timestamp())
+}
+
+/// Next occurrence of a daily, weekly or monthly schedule after `now`
+fn next_calendar<Z: TimeZone>(zone: &Z, policy: &ResetPolicy, time: NaiveTime, now: DateTime<Utc>) -> Option<i64> {
+    let today = now.with_timezone(zone).date_naive();
+    let dates: Vec<NaiveDate> = match policy {
+        ResetPolicy::Daily { .. } => vec![today, today + Duration::days(1)],
+        ResetPolicy::Weekly { weekday, .. } => {
+            let ahead = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            let date = today + Duration::days(ahead as i64);
+            vec![date, date + Duration::days(7)]
+        }
+        ResetPolicy::Monthly { day, .. } => {
+            let (year, month) = (today.year(), today.month());
+            let next = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+            [(year, month), next]
+                .into_iter()
+                .filter_map(|(year, month)| month_day(year, month, *day))
+                .collect()
+        }
+        _ => return None,
+    };
+    dates
+        .into_iter()
+        .filter_map(|date| local_timestamp(zone, date.and_time(time)))
+        .find(|at| *at > now.timestamp())
+}
+
+/// `day` of the month, clamped to its last day
+fn month_day(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day).rev().find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+/// Timestamp of a local time, moved past a DST gap when it doesn't exist
+fn local_timestamp<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> Option<i64> {
+    zone.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| zone.from_local_datetime(&(naive + Duration::hours(1))).earliest())
+        .map(|at| at.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{UsageReport, WindowUsage};
+    use crate::ProviderType;
+
+    const INTERVAL_HOURS: u32 = 5;
+
+    fn at(rfc3339: &str) -> i64 {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().timestamp()
+    }
+
+    fn next(policy: &ResetPolicy, now: &str) -> i64 {
+        policy.next_reset(INTERVAL_HOURS, &AppState::default(), at(now))
+    }
+
+    fn paris() -> Option<String> {
+        Some("Europe/Paris".to_string())
+    }
+
+    #[test]
+    fn daily_across_dst() {
+        let daily = |time: &str| ResetPolicy::Daily {
+            time: time.to_string(),
+            timezone: paris(),
+        };
+        // 09:00 CET is 08:00 UTC, 09:00 CEST the day after the change is 07:00 UTC
+        assert_eq!(next(&daily("09:00"), "2026-03-28T12:00:00Z"), at("2026-03-29T07:00:00Z"));
+        // 02:30 doesn't exist on the spring-forward day: 03:30 CEST
+        assert_eq!(next(&daily("02:30"), "2026-03-28T12:00:00Z"), at("2026-03-29T01:30:00Z"));
+        // 02:30 happens twice on the fall-back day: the first one (CEST)
+        assert_eq!(next(&daily("02:30"), "2026-10-24T12:00:00Z"), at("2026-10-25T00:30:00Z"));
+        assert_eq!(next(&daily("09:00"), "2026-10-25T06:00:00Z"), at("2026-10-25T08:00:00Z"));
+    }
+
+    #[test]
+    fn weekly_across_dst() {
+        let monday = ResetPolicy::Weekly {
+            weekday: Weekday::Mon,
+            time: "09:00".to_string(),
+            timezone: paris(),
+        };
+        // Monday 10:00 CET, past this week's reset: next Monday, in CEST
+        assert_eq!(next(&monday, "2026-03-23T09:00:00Z"), at("2026-03-30T07:00:00Z"));
+        // Monday 08:00 CET, before this week's reset
+        assert_eq!(next(&monday, "2026-03-23T07:00:00Z"), at("2026-03-23T08:00:00Z"));
+        // Back to CET
+        assert_eq!(next(&monday, "2026-10-20T12:00:00Z"), at("2026-10-26T08:00:00Z"));
+    }
+
+    #[test]
+    fn monthly_across_dst() {
+        let first = ResetPolicy::Monthly {
+            day: 1,
+            time: "09:00".to_string(),
+            timezone: paris(),
+        };
+        assert_eq!(next(&first, "2026-10-15T12:00:00Z"), at("2026-11-01T08:00:00Z"));
+        assert_eq!(next(&first, "2026-03-15T12:00:00Z"), at("2026-04-01T07:00:00Z"));
+        // December rolls over to January
+        assert_eq!(next(&first, "2026-12-02T12:00:00Z"), at("2027-01-01T08:00:00Z"));
+    }
+
+    #[test]
+    fn billing_day_31_in_february() {
+        let last = ResetPolicy::Monthly {
+            day: 31,
+            time: "00:00".to_string(),
+            timezone: Some("UTC".to_string()),
+        };
+        assert_eq!(next(&last, "2026-02-10T00:00:00Z"), at("2026-02-28T00:00:00Z"));
+        assert_eq!(next(&last, "2026-02-28T12:00:00Z"), at("2026-03-31T00:00:00Z"));
+        assert_eq!(next(&last, "2028-02-10T00:00:00Z"), at("2028-02-29T00:00:00Z"));
+        assert_eq!(next(&last, "2026-04-05T00:00:00Z"), at("2026-04-30T00:00:00Z"));
+    }
+
+    #[test]
+    fn rolling_window_idle_then_expired() {
+        let rolling = ResetPolicy::Rolling { hours: 3 };
+        let now = at("2026-05-01T10:00:00Z");
+
+        // A new period waits for its first use, however long it stays idle
+        assert_eq!(next(&rolling, "2026-05-01T10:00:00Z"), IDLE);
+        assert!(!is_due(IDLE, now));
+        assert!(!is_due(IDLE, now + 365 * 86_400));
+
+        // The first use opens the window
+        let reset_time = rolling.open_window(IDLE, now);
+        assert_eq!(reset_time, now + 3 * 3600);
+        assert!(!is_due(reset_time, now + 3 * 3600 - 1));
+        assert!(is_due(reset_time, now + 3 * 3600));
+
+        // Other schedules keep their reset time on first use
+        assert_eq!(ResetPolicy::Interval.open_window(now + 60, now), now + 60);
+    }
+
+    #[test]
+    fn provider_policy_falls_back_to_interval() {
+        let now = "2026-05-01T10:00:00Z";
+        let fallback = at(now) + INTERVAL_HOURS as i64 * 3600;
+        let policy = |provider_id: &str, window: Option<&str>| ResetPolicy::Provider {
+            provider_id: provider_id.to_string(),
+            window: window.map(str::to_string),
+        };
+        let mut state = AppState::default();
+        let next = |state: &AppState, policy: &ResetPolicy| policy.next_reset(INTERVAL_HOURS, state, at(now));
+
+        // Unknown provider, no report yet
+        assert_eq!(next(&state, &policy("missing", None)), fallback);
+        assert_eq!(next(&state, &policy("anthropic", None)), fallback);
+
+        let window = |id: &str, resets_at: &str| WindowUsage {
+            id: id.to_string(),
+            percent: Some(40.0),
+            used: None,
+            limit: None,
+            resets_at: Some(resets_at.to_string()),
+        };
+        state.providers.get_mut("anthropic").unwrap().last_report = Some(UsageReport {
+            provider_id: "anthropic".to_string(),
+            provider_type: ProviderType::Anthropic,
+            windows: vec![
+                window("five_hour", "2026-05-01T12:00:00Z"),
+                window("seven_day", "2026-05-01T09:00:00Z"),
+            ],
+            cost_usd: None,
+            subscription_type: None,
+            details: serde_json::Value::Null,
+            fetched_at: at(now),
+        });
+
+        assert_eq!(next(&state, &policy("anthropic", None)), at("2026-05-01T12:00:00Z"));
+        assert_eq!(next(&state, &policy("anthropic", Some("five_hour"))), at("2026-05-01T12:00:00Z"));
+        // A reset time already past, or an unknown window
+        assert_eq!(next(&state, &policy("anthropic", Some("seven_day"))), fallback);
+        assert_eq!(next(&state, &policy("anthropic", Some("opus"))), fallback);
+    }
+}
@@ -0,0 +1,271 @@
+// ============== BACKGROUND POLLING ==============
+//
+// Polls every enabled provider backend on its own `poll_interval_secs`, so
+// usage stays fresh while the window is hidden. A Claude account is enabled
+// the first time Claude Code is found logged in for it, so it is polled and
+// shown everywhere without setup; disabling it afterwards sticks. Each
+// provider runs in its own task; polls are jittered to keep providers from
+// firing together, network errors back off exponentially and 429s wait for
+// the server's Retry-After. Every tick also applies the due resets of the
+// manual counters and the counter changes queued by `meterai`, and saves the
+// calls charged by the metering proxy; the usage history is pruned daily.
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+
+use meter_ai::counters;
+use meter_ai::history;
+use meter_ai::i18n::tr;
+use meter_ai::metrics;
+use meter_ai::providers::claude_code::ClaudeCodeProvider;
+use meter_ai::providers::{self, FetchContext, ProviderRegistry, UsageReport};
+use meter_ai::{raise_alert, save_state, AppError, AppState, ProviderType};
+
+use crate::{api_server, emit_usage, proxy_server, publish_counter, tray};
+
+/// How often due providers are checked
+const TICK: Duration = Duration::from_secs(5);
+/// Let the frontend finish its initial load before the first poll
+const STARTUP_DELAY: Duration = Duration::from_secs(10);
+/// A hung request must not stall the other providers
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+const MIN_INTERVAL_SECS: u64 = 30;
+const BACKOFF_BASE_SECS: u64 = 30;
+const BACKOFF_MAX_SECS: u64 = 3600;
+/// Maximum jitter as a fraction of the interval
+const JITTER_RATIO: f64 = 0.1;
+/// Keeps the reset countdowns of the tray menu current
+const TRAY_REFRESH: Duration = Duration::from_secs(60);
+/// Retention of the usage history while the app stays open (also applied at startup)
+const HISTORY_PRUNE: Duration = Duration::from_secs(24 * 3600);
+
+/// Spawn the scheduler on the Tauri async runtime
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(run(app));
+}
+
+/// Keep one polling task per pollable provider, restarted when its interval
+/// changes, so a slow provider never delays the others
+async fn run(app: AppHandle) {
+    let mut tasks: HashMap<String, (u64, JoinHandle<()>)> = HashMap::new();
+    tokio::time::sleep(STARTUP_DELAY).await;
+    let mut last_tray_refresh = Instant::now();
+    let mut last_history_prune = Instant::now();
+
+    loop {
+        if last_tray_refresh.elapsed() >= TRAY_REFRESH {
+            tray::refresh(&app);
+            last_tray_refresh = Instant::now();
+        }
+        if last_history_prune.elapsed() >= HISTORY_PRUNE {
+            let days = app.state::<Mutex<AppState>>().lock().unwrap().settings.history_retention_days;
+            history::apply_retention(days).ok();
+            last_history_prune = Instant::now();
+        }
+
+        apply_due_resets(&app);
+        apply_queued_changes(&app);
+        proxy_server::save_charges(&app);
+        enable_logged_in_accounts(&app);
+
+        let candidates = pollable_providers(&app);
+        tasks.retain(|id, (interval, task)| {
+            let keep = candidates.iter().any(|(candidate, i)| candidate == id && i == interval);
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+        for (provider_id, interval) in candidates {
+            tasks.entry(provider_id.clone()).or_insert_with(|| {
+                let task = tauri::async_runtime::spawn(poll_loop(app.clone(), provider_id, interval));
+                (interval, task)
+            });
+        }
+
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+/// Poll one provider on its own cadence until the scheduler aborts the task
+async fn poll_loop(app: AppHandle, provider_id: String, interval: u64) {
+    // Stagger the first round across providers
+    tokio::time::sleep(jitter(interval.min(60))).await;
+    // Consecutive network failures
+    let mut failures = 0;
+
+    loop {
+        let delay = match poll_provider(&app, &provider_id).await {
+            Ok(report) => {
+                failures = 0;
+                publish_usage(&app, &provider_id, &report);
+                Duration::from_secs(interval) + jitter(interval)
+            }
+            Err(AppError::NetworkError(_)) => {
+                failures += 1;
+                backoff(failures, interval)
+            }
+            Err(AppError::RateLimited(retry_after)) => {
+                Duration::from_secs(retry_after.max(MIN_INTERVAL_SECS)) + jitter(MIN_INTERVAL_SECS)
+            }
+            // Missing credentials or API errors: retry on the normal cadence
+            Err(_) => Duration::from_secs(interval) + jitter(interval),
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Apply the counter changes `meterai` queued while the app runs
+fn apply_queued_changes(app: &AppHandle) {
+    let changes = counters::take_queued();
+    if changes.is_empty() {
+        return;
+    }
+    let alerts = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        let mut alerts = Vec::new();
+        for change in &changes {
+            if let Some((provider_id, usage, raised)) = counters::apply_queued(&mut state, change) {
+                publish_counter(app, &state, &provider_id, &usage);
+                alerts.extend(raised);
+            }
+        }
+        alerts
+    };
+    alerts.into_iter().for_each(raise_alert);
+}
+
+/// Reset the manual counters whose period is over
+fn apply_due_resets(app: &AppHandle) {
+    let alerts = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().unwrap();
+        let (changed, alerts) = meter_ai::apply_due_resets(&mut state);
+        for (provider_id, usage) in changed {
+            publish_counter(app, &state, &provider_id, &usage);
+        }
+        alerts
+    };
+    alerts.into_iter().for_each(raise_alert);
+}
+
+/// Poll every pollable provider right away, side by side (tray "Refresh now")
+pub async fn poll_all(app: AppHandle) {
+    let tasks: Vec<JoinHandle<()>> = pollable_providers(&app)
+        .into_iter()
+        .map(|(provider_id, _)| {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Ok(report) = poll_provider(&app, &provider_id).await {
+                    publish_usage(&app, &provider_id, &report);
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.ok();
+    }
+}
+
+/// Backend providers to poll with their interval (in seconds): the enabled
+/// ones and the Claude accounts with a Claude Code login
+fn pollable_providers(app: &AppHandle) -> Vec<(String, u64)> {
+    let state = app.state::<Mutex<AppState>>();
+    let state = state.lock().unwrap();
+    state
+        .providers
+        .iter()
+        .filter(|(_, p)| {
+            p.config.enabled && p.config.provider_type != ProviderType::Manual && p.config.poll_interval_secs > 0
+        })
+        .map(|(id, p)| (id.clone(), (p.config.poll_interval_secs as u64).max(MIN_INTERVAL_SECS)))
+        .collect()
+}
+
+/// Enable the Claude accounts Claude Code was just found logged in for
+fn enable_logged_in_accounts(app: &AppHandle) {
+    let contexts: Vec<FetchContext> = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock().unwrap();
+        state
+            .providers
+            .iter()
+            .filter(|(_, p)| p.config.provider_type == ProviderType::Anthropic && !p.config.login_seen)
+            .filter_map(|(id, _)| FetchContext::from_state(&state, id))
+            .collect()
+    };
+    // The login lookup reads files: outside the state lock
+    let logged_in: Vec<String> = contexts
+        .into_iter()
+        .filter(ClaudeCodeProvider::is_logged_in)
+        .map(|ctx| ctx.provider_id)
+        .collect();
+    if logged_in.is_empty() {
+        return;
+    }
+
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    for id in &logged_in {
+        if let Some(provider) = state.providers.get_mut(id) {
+            provider.config.enabled = true;
+            provider.config.login_seen = true;
+        }
+    }
+    save_state(&state);
+    tray::update(app, &state);
+}
+
+async fn poll_provider(app: &AppHandle, provider_id: &str) -> Result<UsageReport, AppError> {
+    let state = app.state::<Mutex<AppState>>();
+    let registry = app.state::<ProviderRegistry>();
+    tokio::time::timeout(
+        FETCH_TIMEOUT,
+        providers::fetch_provider(&state, &registry, provider_id),
+    )
+    .await
+    .map_err(|_| {
+        let error = AppError::NetworkError(tr!("error.timeout", provider = provider_id));
+        metrics::record_fetch_error(provider_id, &error);
+        error
+    })?
+}
+
+/// Random delay in `[0, interval * JITTER_RATIO]`
+fn jitter(interval_secs: u64) -> Duration {
+    let max_ms = (interval_secs as f64 * JITTER_RATIO * 1000.0) as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max_ms))
+}
+
+/// 30s, 60s, 120s, ... capped at an hour (or the interval when longer)
+fn backoff(failures: u32, interval_secs: u64) -> Duration {
+    let secs = BACKOFF_BASE_SECS
+        .saturating_mul(1u64 << failures.saturating_sub(1).min(16))
+        .min(BACKOFF_MAX_SECS.max(interval_secs));
+    Duration::from_secs(secs) + jitter(secs)
+}
+
+/// Push a fresh report to the window and the tray
+pub fn publish_usage(app: &AppHandle, provider_id: &str, report: &UsageReport) {
+    let (usage, is_active) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock().unwrap();
+        tray::update(app, &state);
+        (
+            state.providers.get(provider_id).map(|p| p.usage.clone()),
+            state.active_provider == provider_id,
+        )
+    };
+
+    app.emit_all("provider-usage-updated", report).ok();
+    api_server::broadcast("provider-usage-updated", report);
+    // `usage-updated` drives the main gauge, which follows the active provider
+    if let (true, Some(usage)) = (is_active, usage) {
+        emit_usage(app, &usage);
+    }
+}
@@ -0,0 +1,226 @@
+// ============== USAGE SUMMARY ==============
+//
+// One-line text summaries of a provider's usage, e.g.
+// "Claude 5h 42% · 7d 18% — resets in 2h 13m", shown in the tray and printed
+// by `meterai status`, and the JSON snapshot served to other tools.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::i18n::{self, tr};
+use crate::providers::{ProviderRegistry, UsageReport};
+use crate::reset_policy;
+use crate::{AppState, ProviderType, ProviderUsage, UsageData};
+
+/// Compact provider name for the tooltip. Renamed entries and added
+/// accounts keep their name ("Claude Work").
+fn short_name(provider: &ProviderUsage) -> String {
+    let short = match provider.config.provider_type {
+        ProviderType::Anthropic => "Claude",
+        ProviderType::OpenAI => "OpenAI",
+        _ => return provider.config.name.clone(),
+    };
+    let builtin_name = ProviderRegistry::builtin()
+        .get(&provider.config.provider_type)
+        .is_some_and(|p| p.display_name() == provider.config.name);
+    if builtin_name {
+        short.to_string()
+    } else {
+        format!("{} {}", short, provider.config.name)
+    }
+}
+
+/// (short window label, percent) of every window carrying a percentage.
+/// Manual counters only have their own percentage.
+pub fn window_percents(provider: &ProviderUsage) -> Vec<(String, f64)> {
+    match provider.last_report.as_ref() {
+        Some(report) if provider.config.provider_type != ProviderType::Manual => report
+            .windows
+            .iter()
+            .filter_map(|w| w.percent.map(|p| (i18n::window_short_label(&w.id), p)))
+            .collect(),
+        _ => vec![(String::new(), provider.usage.percent as f64)],
+    }
+}
+
+/// "Claude 5h 42% · 7d 18%"
+pub fn summary(provider: &ProviderUsage) -> String {
+    let parts: Vec<String> = window_percents(provider)
+        .into_iter()
+        .map(|(label, percent)| {
+            if label.is_empty() {
+                format!("{:.0}%", percent)
+            } else {
+                format!("{} {:.0}%", label, percent)
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        return short_name(provider);
+    }
+    let mut line = format!("{} {}", short_name(provider), parts.join(" · "));
+    // "Midjourney 40% (12/30 fast hours)"
+    if let (ProviderType::Manual, Some(unit)) = (&provider.config.provider_type, &provider.config.unit) {
+        line.push_str(&format!(
+            " ({}/{} {})",
+            format_amount(provider.usage.used),
+            format_amount(provider.usage.limit),
+            unit
+        ));
+    }
+    line
+}
+
+/// "12", "2.5", "0.33": amounts rounded to 2 decimals
+pub fn format_amount(value: f64) -> String {
+    format!("{}", (value * 100.0).round() / 100.0)
+}
+
+/// "2h 05m", "3d 4h", "12m"
+fn format_countdown(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        tr!("duration.days_hours", days = days, hours = hours)
+    } else if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Next reset of the provider: first fetched window with a reset time, or
+/// the manual counter's reset
+fn next_reset(provider: &ProviderUsage) -> Option<i64> {
+    if provider.config.provider_type == ProviderType::Manual {
+        // A rolling window waiting for its first use has no reset
+        return Some(provider.usage.reset_time).filter(|at| *at != reset_policy::IDLE);
+    }
+    provider
+        .last_report
+        .as_ref()?
+        .windows
+        .iter()
+        .filter(|w| w.percent.is_some())
+        .find_map(|w| w.resets_at.as_deref())
+        .and_then(|r| DateTime::parse_from_rfc3339(r).ok())
+        .map(|r| r.timestamp())
+}
+
+/// "Claude 5h 42% · 7d 18% — resets in 2h 13m"
+pub fn status_line(provider: &ProviderUsage) -> String {
+    let mut line = if provider.config.provider_type != ProviderType::Manual
+        && provider.last_report.is_none()
+    {
+        format!("{} — {}", short_name(provider), tr!("tray.no_data"))
+    } else {
+        summary(provider)
+    };
+    if let Some(reset) = next_reset(provider) {
+        let remaining = reset - Utc::now().timestamp();
+        if remaining > 0 {
+            line.push_str(" — ");
+            line.push_str(&tr!("tray.resets_in", duration = format_countdown(remaining)));
+        }
+    }
+    line
+}
+
+/// Usage of one enabled provider, as served to other tools
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderSnapshot {
+    pub id: String,
+    pub name: String,
+    pub active: bool,
+    pub summary: String,
+    pub usage: UsageData,
+    pub last_report: Option<UsageReport>,
+}
+
+/// Usage of the enabled accounts of one provider type
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderTotals {
+    pub provider_type: ProviderType,
+    pub accounts: usize,
+    /// Summed spend of the accounts reporting one
+    pub cost_usd: Option<f64>,
+    /// Highest percentage of every window across the accounts, by window ID
+    /// ("counter" for manual counters)
+    pub peak_percent: BTreeMap<String, f64>,
+}
+
+/// Usage of every enabled provider (`meterai --json status`, `GET /usage`)
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSnapshot {
+    pub active_provider: String,
+    pub providers: Vec<ProviderSnapshot>,
+    /// Aggregated view of the accounts, one entry per provider type
+    pub totals: Vec<ProviderTotals>,
+}
+
+fn add_to_totals(totals: &mut Vec<ProviderTotals>, provider: &ProviderUsage) {
+    let provider_type = &provider.config.provider_type;
+    let index = match totals.iter().position(|t| &t.provider_type == provider_type) {
+        Some(index) => index,
+        None => {
+            totals.push(ProviderTotals {
+                provider_type: provider_type.clone(),
+                accounts: 0,
+                cost_usd: None,
+                peak_percent: BTreeMap::new(),
+            });
+            totals.len() - 1
+        }
+    };
+    let total = &mut totals[index];
+    total.accounts += 1;
+
+    let windows: Vec<(String, f64)> = if *provider_type == ProviderType::Manual {
+        vec![("counter".to_string(), provider.usage.percent as f64)]
+    } else if let Some(report) = provider.last_report.as_ref() {
+        if let Some(cost) = report.cost_usd {
+            *total.cost_usd.get_or_insert(0.0) += cost;
+        }
+        report
+            .windows
+            .iter()
+            .filter_map(|w| w.percent.map(|p| (w.id.clone(), p)))
+            .collect()
+    } else {
+        vec![]
+    };
+    for (id, percent) in windows {
+        let peak = total.peak_percent.entry(id).or_insert(percent);
+        *peak = peak.max(percent);
+    }
+}
+
+pub fn snapshot(state: &AppState) -> UsageSnapshot {
+    let mut providers: Vec<ProviderSnapshot> = state
+        .providers
+        .iter()
+        .filter(|(_, p)| p.config.enabled)
+        .map(|(id, p)| ProviderSnapshot {
+            id: id.clone(),
+            name: p.config.name.clone(),
+            active: *id == state.active_provider,
+            summary: status_line(p),
+            usage: p.usage.clone(),
+            last_report: p.last_report.clone(),
+        })
+        .collect();
+    providers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut totals = Vec::new();
+    for provider in state.providers.values().filter(|p| p.config.enabled) {
+        add_to_totals(&mut totals, provider);
+    }
+    totals.sort_by_key(|t| t.provider_type.as_str());
+
+    UsageSnapshot {
+        active_provider: state.active_provider.clone(),
+        providers,
+        totals,
+    }
+}
@@ -0,0 +1,959 @@
+// ============== INTERNAL TOKEN STORAGE ==============
+//
+// Internal copy of the Claude Code OAuth token: the token itself lives in the
+// keyring, its metadata and change log in the data dir. Used by the token
+// commands of the app and by `meterai token`.
+//
+// The copy is renewed with its refresh token shortly before it expires (or on
+// a 401), unless Claude Code has meanwhile written a newer token to its
+// credentials file, which is then copied instead.
+//
+// Every Claude account (`TokenAccount`) has its own keyring entries, metadata
+// and history; the built-in "anthropic" provider keeps the original names.
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::http::{self, Endpoint};
+use crate::i18n::tr;
+use crate::{
+    extract_token_from_creds, get_claude_code_credentials_info,
+    get_claude_code_oauth_token_with_custom, get_credential_paths, try_read_credentials_info,
+    AppError, AppSettings, AppState, ClaudeCodeCredentials, CredentialsInfo,
+};
+
+/// Format of `StoredTokenData.expires_at`
+const EXPIRY_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+/// Refresh the internal token when it expires within this many seconds
+pub const REFRESH_MARGIN_SECS: i64 = 300;
+/// OAuth client of Claude Code, which issued the refresh token
+const CLAUDE_CODE_CLIENT_ID: &str = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
+
+/// Stored token data (internal copy of Claude Code credentials)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTokenData {
+    /// The actual token (stored encrypted via keyring)
+    #[serde(skip)]
+    pub token: Option<String>,
+    /// SHA256 hash of the token (first 16 chars for display)
+    pub token_hash: String,
+    /// When the token was copied to internal storage
+    pub copied_at: String,
+    /// Token expiration time (if available from source)
+    pub expires_at: Option<String>,
+    /// Source path where the token was copied from
+    pub source_path: Option<String>,
+    /// Refresh token (if available)
+    #[serde(skip)]
+    pub refresh_token: Option<String>,
+}
+
+/// Token change history entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenChangeEntry {
+    pub timestamp: String,
+    pub changed: bool,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+    pub source: String,
+    /// Why a refresh failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Token status for UI display
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenStatus {
+    /// Whether internal token exists
+    pub has_internal_token: bool,
+    /// Masked token preview (e.g., "sk-ant-...xxxx")
+    pub token_preview: Option<String>,
+    /// Token hash (first 16 chars)
+    pub token_hash: Option<String>,
+    /// When copied
+    pub copied_at: Option<String>,
+    /// Expiration
+    pub expires_at: Option<String>,
+    /// Source used
+    pub source: String,
+    /// Whether source token differs from internal
+    pub source_differs: bool,
+    /// Source token hash (for comparison)
+    pub source_hash: Option<String>,
+}
+
+/// Token history data
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TokenHistory {
+    pub entries: Vec<TokenChangeEntry>,
+    pub last_check: Option<String>,
+}
+
+/// Claude account whose token is stored internally
+#[derive(Debug, Clone)]
+pub struct TokenAccount {
+    /// Provider ID ("anthropic", "anthropic:work", ...)
+    pub provider_id: String,
+    /// Credentials file of the account
+    pub credentials_path: Option<String>,
+}
+
+impl TokenAccount {
+    /// ID of the built-in Claude provider
+    pub const DEFAULT_ID: &'static str = "anthropic";
+
+    /// Account of `provider_id`. The built-in one falls back to the custom
+    /// credentials path of the settings.
+    pub fn new(state: &AppState, provider_id: &str) -> Self {
+        let credentials_path = state
+            .providers
+            .get(provider_id)
+            .and_then(|p| p.config.credentials_path.clone());
+        Self::with_path(&state.settings, provider_id, credentials_path)
+    }
+
+    pub fn with_path(settings: &AppSettings, provider_id: &str, credentials_path: Option<String>) -> Self {
+        let credentials_path = match credentials_path {
+            None if provider_id == Self::DEFAULT_ID => settings.custom_credentials_path.clone(),
+            path => path,
+        };
+        Self {
+            provider_id: provider_id.to_string(),
+            credentials_path,
+        }
+    }
+
+    /// The built-in account
+    pub fn default_account(settings: &AppSettings) -> Self {
+        Self::with_path(settings, Self::DEFAULT_ID, None)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.provider_id == Self::DEFAULT_ID
+    }
+
+    /// Suffix of keyring users and file names ("" for the built-in account)
+    fn suffix(&self) -> String {
+        match self.provider_id.split_once(':') {
+            Some((_, account)) => format!("-{}", account),
+            None => String::new(),
+        }
+    }
+
+    fn keyring_entry(&self, name: &str) -> Result<keyring::Entry, keyring::Error> {
+        keyring::Entry::new("meter-ai", &format!("{}{}", name, self.suffix()))
+    }
+
+    /// Credentials files of the account, in lookup order. Only the built-in
+    /// account uses the auto-detected Claude Code paths.
+    pub fn source_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.credentials_path.iter().map(PathBuf::from).collect();
+        if self.is_default() {
+            paths.extend(get_credential_paths());
+        }
+        paths
+    }
+
+    /// Token of the account's credentials (plus `CLAUDE_CODE_OAUTH_TOKEN` for the built-in one)
+    pub fn source_token(&self) -> Option<String> {
+        if self.is_default() {
+            return get_claude_code_oauth_token_with_custom(self.credentials_path.as_deref());
+        }
+        read_source_credentials(self).and_then(|(_, creds)| extract_token_from_creds(&creds))
+    }
+
+    /// Token and subscription type of the account's credentials
+    pub fn source_info(&self) -> Option<CredentialsInfo> {
+        if self.is_default() {
+            return get_claude_code_credentials_info();
+        }
+        self.source_paths().iter().find_map(try_read_credentials_info)
+    }
+}
+
+impl StoredTokenData {
+    /// Seconds until the token expires (None when the expiry is unknown)
+    pub fn seconds_left(&self) -> Option<i64> {
+        self.expires_at
+            .as_deref()
+            .and_then(parse_expiry)
+            .map(|at| at - Utc::now().timestamp())
+    }
+
+    /// Whether the token expires within `REFRESH_MARGIN_SECS`
+    pub fn expires_soon(&self) -> bool {
+        self.seconds_left().is_some_and(|left| left < REFRESH_MARGIN_SECS)
+    }
+}
+
+/// Unix seconds of a credentials `expiresAt` (Claude Code writes milliseconds)
+fn expiry_seconds(ts: i64) -> i64 {
+    if ts > 100_000_000_000 {
+        ts / 1000
+    } else {
+        ts
+    }
+}
+
+fn format_expiry(ts: i64) -> String {
+    DateTime::from_timestamp(expiry_seconds(ts), 0)
+        .map(|dt| dt.format(EXPIRY_FORMAT).to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Unix seconds of a stored `expires_at`
+fn parse_expiry(value: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(value, EXPIRY_FORMAT)
+        .ok()
+        .map(|dt| dt.and_utc().timestamp())
+        .or_else(|| value.parse().ok().map(expiry_seconds))
+}
+
+/// Get path for internal token metadata
+fn get_internal_token_path(account: &TokenAccount) -> PathBuf {
+    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("meter-ai");
+    fs::create_dir_all(&path).ok();
+    path.push(format!("token_metadata{}.json", account.suffix()));
+    path
+}
+
+/// Get path for token history
+fn get_token_history_path(account: &TokenAccount) -> PathBuf {
+    let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("meter-ai");
+    fs::create_dir_all(&path).ok();
+    path.push(format!("token_history{}.json", account.suffix()));
+    path
+}
+
+/// Compute SHA256 hash of a string, return first 16 hex chars
+fn compute_token_hash(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    let result = hasher.finalize();
+    hex::encode(&result[..8]) // First 8 bytes = 16 hex chars
+}
+
+/// Create masked token preview (e.g., "sk-ant-oaut01-...xxxx")
+fn mask_token(token: &str) -> String {
+    if token.len() <= 20 {
+        return "*".repeat(token.len());
+    }
+    let prefix = &token[..15];
+    let suffix = &token[token.len()-4..];
+    format!("{}...{}", prefix, suffix)
+}
+
+/// Save token to secure storage (keyring)
+fn save_internal_token(
+    account: &TokenAccount,
+    token: &str,
+    refresh_token: Option<&str>,
+) -> Result<(), AppError> {
+    let entry = account
+        .keyring_entry("claude-internal-token")
+        .map_err(|e| AppError::KeyringError(e.to_string()))?;
+    entry
+        .set_password(token)
+        .map_err(|e| AppError::KeyringError(e.to_string()))?;
+
+    // Save refresh token if provided
+    if let Some(rt) = refresh_token {
+        if let Ok(rt_entry) = account.keyring_entry("claude-internal-refresh") {
+            rt_entry.set_password(rt).ok();
+        }
+    }
+
+    Ok(())
+}
+
+/// Load token from secure storage
+fn load_internal_token(account: &TokenAccount) -> Option<String> {
+    let entry = account.keyring_entry("claude-internal-token").ok()?;
+    entry.get_password().ok()
+}
+
+/// Load refresh token from secure storage
+fn load_internal_refresh_token(account: &TokenAccount) -> Option<String> {
+    let entry = account.keyring_entry("claude-internal-refresh").ok()?;
+    entry.get_password().ok()
+}
+
+/// Delete internal token from secure storage
+pub fn delete_internal_token(account: &TokenAccount) -> Result<(), AppError> {
+    if let Ok(entry) = account.keyring_entry("claude-internal-token") {
+        entry.delete_password().ok();
+    }
+    if let Ok(entry) = account.keyring_entry("claude-internal-refresh") {
+        entry.delete_password().ok();
+    }
+    // Also delete metadata file
+    let path = get_internal_token_path(account);
+    if path.exists() {
+        fs::remove_file(path).ok();
+    }
+    Ok(())
+}
+
+/// Save token metadata (non-sensitive data)
+fn save_token_metadata(account: &TokenAccount, data: &StoredTokenData) -> Result<(), AppError> {
+    let path = get_internal_token_path(account);
+    let json = serde_json::to_string_pretty(data)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    fs::write(path, json)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    Ok(())
+}
+
+/// Load token metadata
+pub fn load_token_metadata(account: &TokenAccount) -> Option<StoredTokenData> {
+    let path = get_internal_token_path(account);
+    if !path.exists() {
+        return None;
+    }
+    let content = fs::read_to_string(path).ok()?;
+    let mut data: StoredTokenData = serde_json::from_str(&content).ok()?;
+    // Load actual token from keyring
+    data.token = load_internal_token(account);
+    data.refresh_token = load_internal_refresh_token(account);
+    Some(data)
+}
+
+/// Load token history
+pub fn load_token_history(account: &TokenAccount) -> TokenHistory {
+    let path = get_token_history_path(account);
+    if path.exists() {
+        if let Ok(content) = fs::read_to_string(&path) {
+            if let Ok(history) = serde_json::from_str(&content) {
+                return history;
+            }
+        }
+    }
+    TokenHistory::default()
+}
+
+/// Save token history
+fn save_token_history(account: &TokenAccount, history: &TokenHistory) -> Result<(), AppError> {
+    let path = get_token_history_path(account);
+    let json = serde_json::to_string_pretty(history)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    fs::write(path, json)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    Ok(())
+}
+
+/// Read full credentials from source file (for export)
+fn read_source_credentials(account: &TokenAccount) -> Option<(String, ClaudeCodeCredentials)> {
+    // Custom path first, then the auto-detected ones
+    for path in account.source_paths() {
+        if path.exists() {
+            if let Ok(content) = fs::read_to_string(&path) {
+                if let Ok(creds) = serde_json::from_str::<ClaudeCodeCredentials>(&content) {
+                    return Some((path.to_string_lossy().to_string(), creds));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Hash of the token in the source credentials, if any
+pub fn source_token_hash(account: &TokenAccount) -> Option<String> {
+    read_source_credentials(account)
+        .and_then(|(_, creds)| extract_token_from_creds(&creds))
+        .map(|token| compute_token_hash(&token))
+}
+
+/// Copy token from source to internal storage
+pub fn copy_token_to_internal(account: &TokenAccount) -> Result<TokenStatus, String> {
+    // Read source credentials
+    let (source_path, creds) = read_source_credentials(account)
+        .ok_or_else(|| tr!("error.credentials_not_found"))?;
+
+    // Extract token
+    let token = extract_token_from_creds(&creds)
+        .ok_or_else(|| tr!("error.token_not_in_credentials"))?;
+
+    // Extract refresh token and expiration
+    let (refresh_token, expires_at) = if let Some(ref oauth) = creds.claude_ai_oauth {
+        (oauth.refresh_token.clone(), oauth.expires_at.map(format_expiry))
+    } else {
+        (creds.refresh_token.clone(), creds.expires_at.map(format_expiry))
+    };
+
+    // Compute hash
+    let token_hash = compute_token_hash(&token);
+
+    // Check if this is a change from existing internal token
+    let old_metadata = load_token_metadata(account);
+    let changed = old_metadata.as_ref()
+        .map(|m| m.token_hash != token_hash)
+        .unwrap_or(true);
+
+    // Log change if applicable
+    if changed {
+        let mut history = load_token_history(account);
+        history.entries.push(TokenChangeEntry {
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            changed: true,
+            old_hash: old_metadata.as_ref().map(|m| m.token_hash.clone()),
+            new_hash: Some(token_hash.clone()),
+            source: source_path.clone(),
+            error: None,
+        });
+        // Keep only last 100 entries
+        if history.entries.len() > 100 {
+            history.entries = history.entries.split_off(history.entries.len() - 100);
+        }
+        history.last_check = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+        save_token_history(account, &history).ok();
+    }
+
+    // Save to keyring
+    save_internal_token(account, &token, refresh_token.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    // Save metadata
+    let metadata = StoredTokenData {
+        token: Some(token.clone()),
+        token_hash: token_hash.clone(),
+        copied_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        expires_at: expires_at.clone(),
+        source_path: Some(source_path.clone()),
+        refresh_token,
+    };
+    save_token_metadata(account, &metadata).map_err(|e| e.to_string())?;
+
+    Ok(TokenStatus {
+        has_internal_token: true,
+        token_preview: Some(mask_token(&token)),
+        token_hash: Some(token_hash),
+        copied_at: Some(metadata.copied_at),
+        expires_at,
+        source: source_path,
+        source_differs: false,
+        source_hash: None,
+    })
+}
+
+/// Get current token status
+pub fn token_status(account: &TokenAccount) -> TokenStatus {
+    // Load internal token metadata
+    let internal = load_token_metadata(account);
+
+    // Check source token
+    let source_info = read_source_credentials(account);
+    let source_hash = source_info.as_ref()
+        .and_then(|(_, creds)| extract_token_from_creds(creds))
+        .map(|t| compute_token_hash(&t));
+
+    let source_path = source_info.as_ref()
+        .map(|(p, _)| p.clone())
+        .unwrap_or_else(|| "none".to_string());
+
+    if let Some(meta) = internal {
+        let source_differs = source_hash.as_ref()
+            .map(|sh| sh != &meta.token_hash)
+            .unwrap_or(false);
+
+        TokenStatus {
+            has_internal_token: true,
+            token_preview: meta.token.as_ref().map(|t| mask_token(t)),
+            token_hash: Some(meta.token_hash),
+            copied_at: Some(meta.copied_at),
+            expires_at: meta.expires_at,
+            source: source_path,
+            source_differs,
+            source_hash,
+        }
+    } else {
+        TokenStatus {
+            has_internal_token: false,
+            token_preview: None,
+            token_hash: None,
+            copied_at: None,
+            expires_at: None,
+            source: source_path,
+            source_differs: source_hash.is_some(),
+            source_hash,
+        }
+    }
+}
+
+/// Check if source token has changed and log it
+pub fn check_token_change(account: &TokenAccount) -> TokenChangeEntry {
+    let internal = load_token_metadata(account);
+    let source_info = read_source_credentials(account);
+
+    let source_hash = source_info.as_ref()
+        .and_then(|(_, creds)| extract_token_from_creds(creds))
+        .map(|t| compute_token_hash(&t));
+
+    let source_path = source_info.as_ref()
+        .map(|(p, _)| p.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let internal_hash = internal.as_ref().map(|m| m.token_hash.clone());
+
+    let changed = match (&internal_hash, &source_hash) {
+        (Some(ih), Some(sh)) => ih != sh,
+        (None, Some(_)) => true,
+        _ => false,
+    };
+
+    let entry = TokenChangeEntry {
+        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        changed,
+        old_hash: internal_hash,
+        new_hash: source_hash,
+        source: source_path,
+        error: None,
+    };
+
+    // Log this check
+    let mut history = load_token_history(account);
+    history.entries.push(entry.clone());
+    if history.entries.len() > 100 {
+        history.entries = history.entries.split_off(history.entries.len() - 100);
+    }
+    history.last_check = Some(entry.timestamp.clone());
+    save_token_history(account, &history).ok();
+
+    entry
+}
+
+/// Export token data (for transfer to another PC)
+pub fn export_token_data(account: &TokenAccount) -> Result<String, String> {
+    let metadata = load_token_metadata(account)
+        .ok_or_else(|| tr!("error.no_internal_token"))?;
+
+    let token = metadata.token
+        .ok_or_else(|| tr!("error.token_not_in_keyring"))?;
+
+    // Create export structure (similar to Claude Code credentials format)
+    let export_data = serde_json::json!({
+        "claudeAiOauth": {
+            "accessToken": token,
+            "refreshToken": metadata.refresh_token,
+            "expiresAt": metadata.expires_at.as_deref().and_then(parse_expiry).map(|ts| ts * 1000),
+        },
+        "exportedFrom": "MeterAI",
+        "exportedAt": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    });
+
+    serde_json::to_string_pretty(&export_data)
+        .map_err(|e| e.to_string())
+}
+
+/// Import token data (from another PC)
+pub fn import_token_data(account: &TokenAccount, json_data: &str) -> Result<TokenStatus, String> {
+    // Parse the imported data
+    let creds: ClaudeCodeCredentials = serde_json::from_str(json_data)
+        .map_err(|e| tr!("error.invalid_json", detail = e))?;
+
+    // Extract token
+    let token = extract_token_from_creds(&creds)
+        .ok_or_else(|| tr!("error.no_token_in_import"))?;
+
+    // Extract refresh token and expiration
+    let (refresh_token, expires_at) = if let Some(ref oauth) = creds.claude_ai_oauth {
+        (oauth.refresh_token.clone(), oauth.expires_at.map(format_expiry))
+    } else {
+        (creds.refresh_token.clone(), creds.expires_at.map(format_expiry))
+    };
+
+    // Compute hash
+    let token_hash = compute_token_hash(&token);
+
+    // Save to keyring
+    save_internal_token(account, &token, refresh_token.as_deref())
+        .map_err(|e| e.to_string())?;
+
+    // Save metadata
+    let metadata = StoredTokenData {
+        token: Some(token.clone()),
+        token_hash: token_hash.clone(),
+        copied_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        expires_at: expires_at.clone(),
+        source_path: Some("imported".to_string()),
+        refresh_token,
+    };
+    save_token_metadata(account, &metadata).map_err(|e| e.to_string())?;
+
+    // Log import
+    let mut history = load_token_history(account);
+    history.entries.push(TokenChangeEntry {
+        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        changed: true,
+        old_hash: None,
+        new_hash: Some(token_hash.clone()),
+        source: "imported".to_string(),
+        error: None,
+    });
+    save_token_history(account, &history).ok();
+
+    Ok(TokenStatus {
+        has_internal_token: true,
+        token_preview: Some(mask_token(&token)),
+        token_hash: Some(token_hash),
+        copied_at: Some(metadata.copied_at),
+        expires_at,
+        source: "imported".to_string(),
+        source_differs: false,
+        source_hash: None,
+    })
+}
+
+/// Append to the token history, keeping the last 100 entries
+fn log_token_change(account: &TokenAccount, entry: TokenChangeEntry) {
+    let mut history = load_token_history(account);
+    history.entries.push(entry);
+    if history.entries.len() > 100 {
+        history.entries = history.entries.split_off(history.entries.len() - 100);
+    }
+    save_token_history(account, &history).ok();
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenRefreshResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    /// Lifetime of the access token in seconds
+    expires_in: Option<i64>,
+}
+
+/// Renew the internal token and log the rotation (or the failure) in the
+/// token history
+pub async fn refresh_internal_token(
+    account: &TokenAccount,
+    settings: &AppSettings,
+) -> Result<StoredTokenData, AppError> {
+    let metadata = load_token_metadata(account)
+        .ok_or_else(|| AppError::ConfigError(tr!("error.no_internal_token")))?;
+
+    // Claude Code refreshes its own token: reuse it rather than rotating the
+    // refresh token it shares with us
+    if let Some(data) = copy_newer_source_token(account, &metadata) {
+        return Ok(data);
+    }
+
+    let result = exchange_refresh_token(account, settings, &metadata).await;
+    log_token_change(account, TokenChangeEntry {
+        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        changed: result.is_ok(),
+        old_hash: Some(metadata.token_hash.clone()),
+        new_hash: result.as_ref().ok().map(|data| data.token_hash.clone()),
+        source: "refresh".to_string(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+    result
+}
+
+/// Copy the source token when it differs from the internal one and isn't
+/// about to expire itself
+fn copy_newer_source_token(account: &TokenAccount, metadata: &StoredTokenData) -> Option<StoredTokenData> {
+    let (_, creds) = read_source_credentials(account)?;
+    let token = extract_token_from_creds(&creds)?;
+    let expires_at = creds
+        .claude_ai_oauth
+        .as_ref()
+        .and_then(|oauth| oauth.expires_at)
+        .or(creds.expires_at);
+    let expiring = expires_at
+        .is_some_and(|ts| expiry_seconds(ts) - Utc::now().timestamp() < REFRESH_MARGIN_SECS);
+
+    if expiring || compute_token_hash(&token) == metadata.token_hash {
+        return None;
+    }
+    copy_token_to_internal(account).ok()?;
+    load_token_metadata(account)
+}
+
+async fn exchange_refresh_token(
+    account: &TokenAccount,
+    settings: &AppSettings,
+    metadata: &StoredTokenData,
+) -> Result<StoredTokenData, AppError> {
+    let refresh_token = metadata
+        .refresh_token
+        .as_deref()
+        .ok_or_else(|| AppError::ConfigError(tr!("error.no_refresh_token")))?;
+
+    let endpoint = Endpoint::anthropic_oauth(settings)?;
+    let response = endpoint
+        .post("/v1/oauth/token")
+        .json(&serde_json::json!({
+            "grant_type": "refresh_token",
+            "refresh_token": refresh_token,
+            "client_id": CLAUDE_CODE_CLIENT_ID,
+        }))
+        .send()
+        .await
+        .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+    http::check_rate_limit(&response)?;
+    let status = response.status();
+    // invalid_grant: revoked, or already used by another client
+    if status == StatusCode::BAD_REQUEST || status == StatusCode::UNAUTHORIZED {
+        return Err(AppError::Unauthorized(tr!("error.token_refresh_rejected")));
+    }
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::ApiError(tr!(
+            "error.token_refresh_failed",
+            status = status,
+            body = body
+        )));
+    }
+
+    let refreshed: TokenRefreshResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::ApiError(tr!("error.parse_response", detail = e)))?;
+
+    // The refresh token is rotated by some servers only
+    let refresh_token = refreshed
+        .refresh_token
+        .or_else(|| metadata.refresh_token.clone());
+    save_internal_token(account, &refreshed.access_token, refresh_token.as_deref())?;
+
+    let data = StoredTokenData {
+        token_hash: compute_token_hash(&refreshed.access_token),
+        token: Some(refreshed.access_token),
+        copied_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        expires_at: refreshed
+            .expires_in
+            .map(|secs| format_expiry(Utc::now().timestamp() + secs)),
+        source_path: metadata.source_path.clone(),
+        refresh_token,
+    };
+    save_token_metadata(account, &data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::EndpointConfig;
+    use crate::mock_server;
+    use crate::providers::claude_code::fetch_claude_code_usage_internal;
+    use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, Once};
+
+    /// Access token of `fixtures/mock/claude_oauth_token.json`
+    const REFRESHED_TOKEN: &str = "sk-ant-REDACTED";
+    const REFRESHED_REFRESH_TOKEN: &str = "sk-ant-REDACTED";
+
+    type Passwords = Arc<Mutex<HashMap<String, String>>>;
+
+    /// Keyring kept in memory for the whole test run, so tests never touch the
+    /// user's keychain (the keyring crate's own mock forgets a password with
+    /// its entry)
+    struct MemoryKeyring(Passwords);
+
+    struct MemoryCredential {
+        key: String,
+        passwords: Passwords,
+    }
+
+    impl CredentialApi for MemoryCredential {
+        fn set_password(&self, password: &str) -> keyring::Result<()> {
+            self.passwords.lock().unwrap().insert(self.key.clone(), password.to_string());
+            Ok(())
+        }
+
+        fn get_password(&self) -> keyring::Result<String> {
+            self.passwords.lock().unwrap().get(&self.key).cloned().ok_or(keyring::Error::NoEntry)
+        }
+
+        fn delete_password(&self) -> keyring::Result<()> {
+            self.passwords.lock().unwrap().remove(&self.key).map(|_| ()).ok_or(keyring::Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    impl CredentialBuilderApi for MemoryKeyring {
+        fn build(&self, _target: Option<&str>, service: &str, user: &str) -> keyring::Result<Box<Credential>> {
+            Ok(Box::new(MemoryCredential {
+                key: format!("{}/{}", service, user),
+                passwords: self.0.clone(),
+            }))
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    /// Named Claude account whose endpoints point at a fresh mock server.
+    /// Its files are removed on drop.
+    struct TestAccount {
+        account: TokenAccount,
+        settings: AppSettings,
+    }
+
+    impl TestAccount {
+        async fn new() -> Self {
+            static KEYRING: Once = Once::new();
+            KEYRING.call_once(|| keyring::set_default_credential_builder(Box::new(MemoryKeyring(Passwords::default()))));
+
+            let base_url = mock_server::start(([127, 0, 0, 1], 0).into()).await.unwrap();
+            let mut settings = AppSettings::default();
+            for key in [http::ANTHROPIC, http::ANTHROPIC_OAUTH] {
+                let endpoint = EndpointConfig {
+                    base_url: Some(base_url.clone()),
+                    ..Default::default()
+                };
+                settings.endpoints.insert(key.to_string(), endpoint);
+            }
+
+            let name = format!("test-{}", rand::random::<u32>());
+            let path = std::env::temp_dir().join(format!("meterai-{}.json", name));
+            Self {
+                account: TokenAccount {
+                    provider_id: format!("anthropic:{}", name),
+                    credentials_path: Some(path.to_string_lossy().to_string()),
+                },
+                settings,
+            }
+        }
+
+        /// Internal token expiring in `secs`
+        fn store(&self, token: &str, secs: i64) {
+            import_token_data(&self.account, &credentials(token, secs)).unwrap();
+        }
+
+        /// Claude Code login with a token expiring in `secs`
+        fn log_in(&self, token: &str, secs: i64) {
+            fs::write(self.account.credentials_path.as_ref().unwrap(), credentials(token, secs)).unwrap();
+        }
+
+        /// Make the OAuth endpoint reject the refresh token
+        fn reject_refresh(&mut self) {
+            let endpoint = self.settings.endpoints.get_mut(http::ANTHROPIC_OAUTH).unwrap();
+            endpoint.headers.insert("x-api-key".to_string(), "refresh-401".to_string());
+        }
+
+        fn token(&self) -> Option<String> {
+            load_token_metadata(&self.account).and_then(|meta| meta.token)
+        }
+
+        fn last_change(&self) -> TokenChangeEntry {
+            load_token_history(&self.account).entries.pop().unwrap()
+        }
+
+        async fn fetch(&self) -> Result<(), AppError> {
+            let endpoint = Endpoint::anthropic(&self.settings)?;
+            fetch_claude_code_usage_internal(&self.account, &self.settings, &endpoint).await.map(|_| ())
+        }
+    }
+
+    impl Drop for TestAccount {
+        fn drop(&mut self) {
+            delete_internal_token(&self.account).ok();
+            fs::remove_file(get_token_history_path(&self.account)).ok();
+            fs::remove_file(self.account.credentials_path.as_ref().unwrap()).ok();
+        }
+    }
+
+    fn credentials(token: &str, secs: i64) -> String {
+        serde_json::json!({
+            "claudeAiOauth": {
+                "accessToken": token,
+                "refreshToken": "sk-ant-ort01-mock",
+                "expiresAt": (Utc::now().timestamp() + secs) * 1000,
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn expiry_is_checked_against_the_refresh_margin() {
+        let data = |secs: Option<i64>| StoredTokenData {
+            token: None,
+            token_hash: String::new(),
+            copied_at: String::new(),
+            expires_at: secs.map(|secs| format_expiry(Utc::now().timestamp() + secs)),
+            source_path: None,
+            refresh_token: None,
+        };
+        assert!(data(Some(60)).expires_soon());
+        assert!(data(Some(-60)).expires_soon());
+        assert!(!data(Some(REFRESH_MARGIN_SECS + 60)).expires_soon());
+        assert!(!data(None).expires_soon());
+        // Claude Code writes milliseconds
+        assert_eq!(parse_expiry("1750000000000"), Some(1_750_000_000));
+    }
+
+    #[tokio::test]
+    async fn expiring_token_is_refreshed_before_the_fetch() {
+        let account = TestAccount::new().await;
+        account.store("sk-ant-oat01-mock", 60);
+
+        account.fetch().await.unwrap();
+        let metadata = load_token_metadata(&account.account).unwrap();
+        assert_eq!(metadata.token.as_deref(), Some(REFRESHED_TOKEN));
+        assert_eq!(metadata.refresh_token.as_deref(), Some(REFRESHED_REFRESH_TOKEN));
+        assert!(metadata.seconds_left().is_some_and(|left| left > 28_000));
+
+        let change = account.last_change();
+        assert_eq!(change.source, "refresh");
+        assert!(change.changed);
+        assert_eq!(change.new_hash.as_deref(), Some(metadata.token_hash.as_str()));
+    }
+
+    #[tokio::test]
+    async fn newer_claude_code_token_is_copied_instead_of_refreshed() {
+        let account = TestAccount::new().await;
+        account.store("sk-ant-oat01-old", 60);
+        account.log_in("sk-ant-oat01-new", 3600);
+
+        refresh_internal_token(&account.account, &account.settings).await.unwrap();
+        assert_eq!(account.token().as_deref(), Some("sk-ant-oat01-new"));
+        assert_eq!(Some(account.last_change().source), account.account.credentials_path.clone());
+
+        // A Claude Code token about to expire too isn't worth copying
+        account.store("sk-ant-oat01-old", 60);
+        account.log_in("sk-ant-oat01-new", 60);
+        refresh_internal_token(&account.account, &account.settings).await.unwrap();
+        assert_eq!(account.token().as_deref(), Some(REFRESHED_TOKEN));
+    }
+
+    #[tokio::test]
+    async fn rejected_token_is_refreshed_and_retried() {
+        let account = TestAccount::new().await;
+        account.store("sk-ant-oat01-mock-401", 3600);
+
+        account.fetch().await.unwrap();
+        assert_eq!(account.token().as_deref(), Some(REFRESHED_TOKEN));
+        assert_eq!(account.last_change().source, "refresh");
+    }
+
+    #[tokio::test]
+    async fn rejected_refresh_is_unauthorized_and_logged() {
+        let mut account = TestAccount::new().await;
+        account.store("sk-ant-oat01-mock-401", 3600);
+        account.reject_refresh();
+
+        let error = account.fetch().await.unwrap_err();
+        assert!(matches!(error, AppError::Unauthorized(_)), "{:?}", error);
+        assert_eq!(account.token().as_deref(), Some("sk-ant-oat01-mock-401"));
+
+        let change = account.last_change();
+        assert_eq!(change.source, "refresh");
+        assert!(!change.changed);
+        assert_eq!(change.new_hash, None);
+        assert_eq!(change.error, Some(AppError::Unauthorized(tr!("error.token_refresh_rejected")).to_string()));
+    }
+}
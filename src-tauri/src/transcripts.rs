@@ -0,0 +1,579 @@
+// ============== CLAUDE CODE TRANSCRIPTS ==============
+//
+// Token accounting from the session transcripts Claude Code writes under
+// `~/.claude/projects/<project>/*.jsonl`: every assistant message carries its
+// model and input/output/cache token counts. The indexer reads each file from
+// where it stopped last time and folds the messages into per-day (UTC),
+// per-project, per-model totals kept in `claude_transcripts.json` next to the
+// usage history. Costs are API-equivalent estimates (see `pricing`).
+//
+// A message with several content blocks is logged once per block with the
+// same usage, and a resumed session starts with a copy of the messages before
+// it: every message and request ID pair is counted once across all files. A
+// file that got shorter was rewritten and is counted again from the start,
+// in place of what it contributed before. The pairs are only kept for the
+// files a session could still be resumed from: present and modified within
+// `RESUME_HORIZON`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub use crate::pricing::TokenCounts;
+use crate::pricing::PricingCatalog;
+use crate::{get_data_dir, AppError, ProviderType};
+
+/// Serializes indexing runs (commands, CLI)
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// Format of `claude_transcripts.json`; an index in another format is rebuilt
+const INDEX_VERSION: u32 = 2;
+
+/// Claude Code's default cleanup period: older sessions are gone, so never resumed
+const RESUME_HORIZON: Duration = Duration::from_secs(30 * 86_400);
+
+/// (day, project, model)
+type TotalKey = (i64, String, String);
+/// Grouping key of a report row: (day, project, model)
+type RowKey = (Option<i64>, Option<String>, Option<String>);
+
+/// Tokens of one model in one project on one day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTotal {
+    /// Day start (unix, UTC)
+    pub day: i64,
+    /// Working directory of the session (or the transcript folder name)
+    pub project: String,
+    pub model: String,
+    pub messages: u64,
+    pub tokens: TokenCounts,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FileProgress {
+    /// Bytes already read (always at a line boundary)
+    offset: u64,
+    /// `message.id:requestId` of the messages counted from this file, while
+    /// its session can be resumed
+    #[serde(default)]
+    keys: BTreeSet<String>,
+    /// What this file contributed to the totals
+    #[serde(default)]
+    totals: Vec<TokenTotal>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TranscriptIndex {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    files: HashMap<String, FileProgress>,
+    /// Sum of the totals of every file
+    #[serde(default)]
+    totals: Vec<TokenTotal>,
+}
+
+/// Outcome of an indexing run
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexSummary {
+    pub files: usize,
+    /// Messages counted by this run
+    pub new_messages: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenGroup {
+    Day,
+    Project,
+    Model,
+}
+
+/// Totals of one group (the dimensions that aren't grouped on are None)
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenUsageRow {
+    pub day: Option<i64>,
+    /// Day as "YYYY-MM-DD" for display
+    pub date: Option<String>,
+    pub project: Option<String>,
+    pub model: Option<String>,
+    pub messages: u64,
+    pub tokens: TokenCounts,
+    /// API-equivalent cost (None when a model has no known price)
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenUsageReport {
+    pub rows: Vec<TokenUsageRow>,
+    pub messages: u64,
+    pub tokens: TokenCounts,
+    /// Cost of the priced models
+    pub cost_usd: f64,
+    /// Models left out of `cost_usd`
+    pub unpriced_models: Vec<String>,
+    /// Version of the pricing catalog used
+    pub pricing_version: String,
+}
+
+// Transcript line, only the fields used here
+#[derive(Deserialize)]
+struct TranscriptLine {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    timestamp: Option<String>,
+    cwd: Option<String>,
+    #[serde(rename = "requestId")]
+    request_id: Option<String>,
+    message: Option<TranscriptMessage>,
+}
+
+#[derive(Deserialize)]
+struct TranscriptMessage {
+    id: Option<String>,
+    model: Option<String>,
+    usage: Option<MessageUsage>,
+}
+
+#[derive(Deserialize)]
+struct MessageUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+}
+
+fn get_index_path() -> PathBuf {
+    get_data_dir().join("claude_transcripts.json")
+}
+
+/// Transcript roots: `$CLAUDE_CONFIG_DIR/projects`, `~/.claude/projects` and
+/// `~/.config/claude/projects`
+pub fn transcript_dirs() -> Vec<PathBuf> {
+    let mut dirs_found = Vec::new();
+    if let Ok(config_dir) = env::var("CLAUDE_CONFIG_DIR") {
+        dirs_found.push(PathBuf::from(config_dir).join("projects"));
+    }
+    if let Some(home) = dirs::home_dir() {
+        dirs_found.push(home.join(".claude").join("projects"));
+        dirs_found.push(home.join(".config").join("claude").join("projects"));
+    }
+    dirs_found.dedup();
+    dirs_found
+}
+
+/// Every `*.jsonl` file under the transcript roots
+fn transcript_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = transcript_dirs().into_iter().filter(|d| d.is_dir()).collect();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "jsonl") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn load_index() -> TranscriptIndex {
+    fs::read_to_string(get_index_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_index(index: &TranscriptIndex) -> Result<(), AppError> {
+    let json = serde_json::to_string(index).map_err(|e| AppError::ConfigError(e.to_string()))?;
+    let path = get_index_path();
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, json).map_err(|e| AppError::ConfigError(e.to_string()))?;
+    fs::rename(&tmp_path, &path).map_err(|e| AppError::ConfigError(e.to_string()))
+}
+
+/// Project of a line: its working directory, else the transcript folder name
+fn project_of(line: &TranscriptLine, path: &Path) -> String {
+    line.cwd.clone().unwrap_or_else(|| {
+        path.parent()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    })
+}
+
+fn add_totals(into: &mut BTreeMap<TotalKey, (u64, TokenCounts)>, totals: &[TokenTotal]) {
+    for total in totals {
+        let entry = into
+            .entry((total.day, total.project.clone(), total.model.clone()))
+            .or_default();
+        entry.0 += total.messages;
+        entry.1.add(&total.tokens);
+    }
+}
+
+fn to_totals(totals: BTreeMap<TotalKey, (u64, TokenCounts)>) -> Vec<TokenTotal> {
+    totals
+        .into_iter()
+        .map(|((day, project, model), (messages, tokens))| TokenTotal {
+            day,
+            project,
+            model,
+            messages,
+            tokens,
+        })
+        .collect()
+}
+
+/// Read the new complete lines of `path` into its progress, skipping the
+/// messages in `seen` (counted from any file)
+fn index_file(path: &Path, progress: &mut FileProgress, seen: &mut HashSet<String>) -> Result<u64, AppError> {
+    let mut file = File::open(path).map_err(|e| AppError::ConfigError(e.to_string()))?;
+    let len = file.metadata().map_err(|e| AppError::ConfigError(e.to_string()))?.len();
+    // A shorter file was rewritten: take back what it counted and start over
+    if len < progress.offset {
+        for key in &progress.keys {
+            seen.remove(key);
+        }
+        *progress = FileProgress::default();
+    }
+    file.seek(SeekFrom::Start(progress.offset))
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+
+    let mut totals = BTreeMap::new();
+    add_totals(&mut totals, &progress.totals);
+    let result = read_lines(path, BufReader::new(file), progress, seen, &mut totals);
+    // Keep what was counted before a read error, along with the offset
+    progress.totals = to_totals(totals);
+    result
+}
+
+fn read_lines(
+    path: &Path,
+    mut reader: BufReader<File>,
+    progress: &mut FileProgress,
+    seen: &mut HashSet<String>,
+    totals: &mut BTreeMap<TotalKey, (u64, TokenCounts)>,
+) -> Result<u64, AppError> {
+    let mut buffer = Vec::new();
+    let mut counted = 0;
+    loop {
+        buffer.clear();
+        let read = reader
+            .read_until(b'\n', &mut buffer)
+            .map_err(|e| AppError::ConfigError(e.to_string()))?;
+        // Stop at EOF or at a line Claude Code is still writing
+        if read == 0 || buffer.last() != Some(&b'\n') {
+            break;
+        }
+        progress.offset += read as u64;
+
+        let Ok(line) = serde_json::from_slice::<TranscriptLine>(&buffer) else {
+            continue;
+        };
+        if line.kind.as_deref() != Some("assistant") {
+            continue;
+        }
+        let Some(message) = line.message.as_ref() else {
+            continue;
+        };
+        let (Some(usage), Some(model)) = (message.usage.as_ref(), message.model.as_ref()) else {
+            continue;
+        };
+        let tokens = TokenCounts {
+            input: usage.input_tokens,
+            output: usage.output_tokens,
+            cache_creation: usage.cache_creation_input_tokens,
+            cache_read: usage.cache_read_input_tokens,
+        };
+        if tokens.total() == 0 {
+            continue;
+        }
+
+        let Some(timestamp) = line
+            .timestamp
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        else {
+            continue;
+        };
+        if let Some(id) = message.id.as_deref() {
+            let key = format!("{}:{}", id, line.request_id.as_deref().unwrap_or_default());
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            progress.keys.insert(key);
+        }
+
+        let day = timestamp.timestamp() - timestamp.timestamp().rem_euclid(86_400);
+        let entry = totals
+            .entry((day, project_of(&line, path), model.clone()))
+            .or_default();
+        entry.0 += 1;
+        entry.1.add(&tokens);
+        counted += 1;
+    }
+    Ok(counted)
+}
+
+/// Drop the keys of the files deleted or left untouched past `RESUME_HORIZON`
+fn forget_stale_keys(index: &mut TranscriptIndex, files: &[PathBuf]) {
+    let resumable: HashSet<String> = files
+        .iter()
+        .filter(|path| {
+            fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() < RESUME_HORIZON)
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    for (path, progress) in index.files.iter_mut() {
+        if !resumable.contains(path) {
+            progress.keys = BTreeSet::new();
+        }
+    }
+}
+
+/// Read what Claude Code appended since the last run (`rebuild` starts over)
+pub fn index(rebuild: bool) -> Result<IndexSummary, AppError> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = if rebuild { TranscriptIndex::default() } else { load_index() };
+    if index.version != INDEX_VERSION {
+        index = TranscriptIndex {
+            version: INDEX_VERSION,
+            ..Default::default()
+        };
+    }
+
+    let mut seen: HashSet<String> = index.files.values().flat_map(|p| p.keys.iter().cloned()).collect();
+    let files = transcript_files();
+    let mut new_messages = 0;
+    for path in &files {
+        let progress = index.files.entry(path.to_string_lossy().to_string()).or_default();
+        // Unreadable files are retried on the next run
+        if let Ok(counted) = index_file(path, progress, &mut seen) {
+            new_messages += counted;
+        }
+    }
+
+    forget_stale_keys(&mut index, &files);
+
+    // Files deleted since keep their share
+    let mut totals = BTreeMap::new();
+    for progress in index.files.values() {
+        add_totals(&mut totals, &progress.totals);
+    }
+    index.totals = to_totals(totals);
+    save_index(&index)?;
+
+    Ok(IndexSummary {
+        files: files.len(),
+        new_messages,
+    })
+}
+
+/// Totals of the days in `[from, to]` (unix), grouped on one dimension or
+/// split by day, project and model when `group` is None
+pub fn report(from: i64, to: i64, group: Option<TokenGroup>) -> TokenUsageReport {
+    let index = {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        load_index()
+    };
+
+    let catalog = PricingCatalog::load();
+    let mut rows: BTreeMap<RowKey, TokenUsageRow> = BTreeMap::new();
+    let mut unpriced = BTreeSet::new();
+    let mut report = TokenUsageReport {
+        rows: vec![],
+        messages: 0,
+        tokens: TokenCounts::default(),
+        cost_usd: 0.0,
+        unpriced_models: vec![],
+        pricing_version: catalog.version.clone(),
+    };
+
+    for total in index.totals.iter().filter(|t| t.day + 86_400 > from && t.day <= to) {
+        let date = DateTime::<Utc>::from_timestamp(total.day, 0).unwrap_or_default().date_naive();
+        let cost = catalog
+            .cost(&ProviderType::Anthropic, &total.model, &total.tokens, date, false)
+            .map(|estimate| estimate.total_usd);
+        match cost {
+            Some(cost) => report.cost_usd += cost,
+            None => {
+                unpriced.insert(total.model.clone());
+            }
+        }
+        report.messages += total.messages;
+        report.tokens.add(&total.tokens);
+
+        let key = (
+            matches!(group, None | Some(TokenGroup::Day)).then_some(total.day),
+            matches!(group, None | Some(TokenGroup::Project)).then(|| total.project.clone()),
+            matches!(group, None | Some(TokenGroup::Model)).then(|| total.model.clone()),
+        );
+        let row = rows.entry(key.clone()).or_insert_with(|| TokenUsageRow {
+            day: key.0,
+            date: key.0.map(|_| date.format("%Y-%m-%d").to_string()),
+            project: key.1,
+            model: key.2,
+            messages: 0,
+            tokens: TokenCounts::default(),
+            cost_usd: Some(0.0),
+        });
+        row.messages += total.messages;
+        row.tokens.add(&total.tokens);
+        row.cost_usd = row.cost_usd.zip(cost).map(|(sum, cost)| sum + cost);
+    }
+
+    report.rows = rows.into_values().collect();
+    report.unpriced_models = unpriced.into_iter().collect();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn line(id: &str, request_id: &str, output: u64) -> String {
+        serde_json::json!({
+            "type": "assistant",
+            "timestamp": "2026-05-01T10:00:00Z",
+            "cwd": "/work/app",
+            "requestId": request_id,
+            "message": {
+                "id": id,
+                "model": "claude-sonnet-4-5",
+                "usage": { "input_tokens": 10, "output_tokens": output }
+            }
+        })
+        .to_string()
+            + "\n"
+    }
+
+    /// Transcript file removed when dropped
+    struct Transcript(PathBuf);
+
+    impl Transcript {
+        fn new(content: &str) -> Self {
+            let name = format!("meterai-{}.jsonl", rand::thread_rng().gen::<u32>());
+            let transcript = Self(env::temp_dir().join(name));
+            transcript.write(content);
+            transcript
+        }
+
+        fn write(&self, content: &str) {
+            fs::write(&self.0, content).unwrap();
+        }
+    }
+
+    impl Drop for Transcript {
+        fn drop(&mut self) {
+            fs::remove_file(&self.0).ok();
+        }
+    }
+
+    fn messages(progress: &FileProgress) -> (u64, u64) {
+        progress
+            .totals
+            .iter()
+            .fold((0, 0), |(messages, output), t| (messages + t.messages, output + t.tokens.output))
+    }
+
+    #[test]
+    fn content_blocks_are_counted_once() {
+        let file = Transcript::new(&(line("msg_1", "req_1", 5) + &line("msg_1", "req_1", 5) + &line("msg_2", "req_2", 7)));
+        let mut progress = FileProgress::default();
+        let mut seen = HashSet::new();
+
+        assert_eq!(index_file(&file.0, &mut progress, &mut seen).unwrap(), 2);
+        assert_eq!(messages(&progress), (2, 12));
+    }
+
+    #[test]
+    fn resumed_session_copies_are_not_counted_again() {
+        let first = Transcript::new(&(line("msg_1", "req_1", 5) + &line("msg_2", "req_2", 7)));
+        let resumed = Transcript::new(&(line("msg_1", "req_1", 5) + &line("msg_2", "req_2", 7) + &line("msg_3", "req_3", 11)));
+        let (mut first_progress, mut resumed_progress) = (FileProgress::default(), FileProgress::default());
+        let mut seen = HashSet::new();
+
+        assert_eq!(index_file(&first.0, &mut first_progress, &mut seen).unwrap(), 2);
+        assert_eq!(index_file(&resumed.0, &mut resumed_progress, &mut seen).unwrap(), 1);
+        assert_eq!(messages(&resumed_progress), (1, 11));
+    }
+
+    #[test]
+    fn appended_lines_are_read_from_the_offset() {
+        let file = Transcript::new(&line("msg_1", "req_1", 5));
+        let mut progress = FileProgress::default();
+        let mut seen = HashSet::new();
+        index_file(&file.0, &mut progress, &mut seen).unwrap();
+
+        // The second line is still being written
+        let partial = line("msg_2", "req_2", 7);
+        file.write(&(line("msg_1", "req_1", 5) + partial.trim_end()));
+        assert_eq!(index_file(&file.0, &mut progress, &mut seen).unwrap(), 0);
+
+        file.write(&(line("msg_1", "req_1", 5) + &partial));
+        assert_eq!(index_file(&file.0, &mut progress, &mut seen).unwrap(), 1);
+        assert_eq!(messages(&progress), (2, 12));
+    }
+
+    #[test]
+    fn rewritten_file_replaces_its_totals() {
+        let file = Transcript::new(&(line("msg_1", "req_1", 5) + &line("msg_2", "req_2", 7)));
+        let mut progress = FileProgress::default();
+        let mut seen = HashSet::new();
+        index_file(&file.0, &mut progress, &mut seen).unwrap();
+
+        file.write(&line("msg_2", "req_2", 7));
+        assert_eq!(index_file(&file.0, &mut progress, &mut seen).unwrap(), 1);
+        assert_eq!(messages(&progress), (1, 7));
+        assert_eq!(seen, HashSet::from(["msg_2:req_2".to_string()]));
+    }
+
+    #[test]
+    fn keys_are_dropped_once_sessions_cannot_be_resumed() {
+        let (recent, old, deleted) = (
+            Transcript::new(&line("msg_1", "req_1", 5)),
+            Transcript::new(&line("msg_2", "req_2", 7)),
+            Transcript::new(&line("msg_3", "req_3", 11)),
+        );
+        let modified = std::time::SystemTime::now() - RESUME_HORIZON - Duration::from_secs(60);
+        File::options().write(true).open(&old.0).unwrap().set_modified(modified).unwrap();
+
+        let mut index = TranscriptIndex::default();
+        let mut seen = HashSet::new();
+        for file in [&recent, &old, &deleted] {
+            let progress = index.files.entry(file.0.to_string_lossy().to_string()).or_default();
+            index_file(&file.0, progress, &mut seen).unwrap();
+        }
+        let key = |file: &Transcript| file.0.to_string_lossy().to_string();
+        let (recent_key, old_key, deleted_key) = (key(&recent), key(&old), key(&deleted));
+        drop(deleted);
+
+        forget_stale_keys(&mut index, &[recent.0.clone(), old.0.clone()]);
+        assert_eq!(index.files[&recent_key].keys, BTreeSet::from(["msg_1:req_1".to_string()]));
+        assert!(index.files[&old_key].keys.is_empty());
+        assert!(index.files[&deleted_key].keys.is_empty());
+        // What they counted stays
+        assert_eq!(messages(&index.files[&old_key]), (1, 7));
+        assert_eq!(messages(&index.files[&deleted_key]), (1, 11));
+    }
+}
@@ -0,0 +1,255 @@
+// ============== TRAY ==============
+//
+// Tooltip (and title on macOS) with the active provider's usage, e.g.
+// "Claude 5h 42% · 7d 18%", a generated gauge icon whose fill follows the
+// highest window percentage, colored by the provider's alert thresholds, and a
+// menu rebuilt from the state: one status line per enabled provider plus quick
+// actions, so MeterAI can be used without opening the main window.
+
+use chrono::{Local, TimeZone, Utc};
+use std::sync::Mutex;
+use tauri::{
+    AppHandle, CustomMenuItem, Icon, Manager, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, SystemTraySubmenu,
+};
+
+use meter_ai::i18n::tr;
+use meter_ai::summary::{status_line, summary, window_percents};
+use meter_ai::{save_state, AppState, ProviderType, ProviderUsage};
+
+use crate::{proxy_server, scheduler};
+use crate::{add_requests, emit_usage, pause_notifications, reset_active_usage};
+
+/// How long "Pause notifications" mutes alerts
+const PAUSE_SECS: i64 = 3600;
+
+const ICON_SIZE: usize = 32;
+/// Gauge outline (left, top, right, bottom), 2px border
+const GAUGE: (usize, usize, usize, usize) = (7, 2, 24, 29);
+const BORDER: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    fn color(self) -> [u8; 3] {
+        match self {
+            Level::Normal => [76, 175, 80],
+            Level::Warning => [255, 152, 0],
+            Level::Critical => [244, 67, 54],
+        }
+    }
+}
+
+/// Last rendered (percent, level), so unchanged usage doesn't redraw the icon
+static LAST_ICON: Mutex<Option<(u32, Level)>> = Mutex::new(None);
+
+/// Warning from the lowest threshold, critical from the highest one below 100%
+fn level(percent: f64, thresholds: &[u32]) -> Level {
+    let mut sorted = thresholds.to_vec();
+    sorted.sort_unstable();
+    let warning = sorted.first().copied().unwrap_or(70);
+    let critical = sorted
+        .iter()
+        .rev()
+        .copied()
+        .find(|t| *t < 100 && *t > warning)
+        .unwrap_or(100);
+
+    if percent >= critical as f64 {
+        Level::Critical
+    } else if percent >= warning as f64 {
+        Level::Warning
+    } else {
+        Level::Normal
+    }
+}
+
+/// Vertical gauge filled from the bottom
+fn render_icon(percent: f64, level: Level) -> Icon {
+    let (left, top, right, bottom) = GAUGE;
+    let inner_height = bottom - top + 1 - 2 * BORDER;
+    let fill_height = (inner_height as f64 * percent.clamp(0.0, 100.0) / 100.0).round() as usize;
+    let fill_top = bottom + 1 - BORDER - fill_height;
+    let [r, g, b] = level.color();
+
+    let mut rgba = vec![0u8; ICON_SIZE * ICON_SIZE * 4];
+    for y in top..=bottom {
+        for x in left..=right {
+            // Cut the outer corners for a rounded look
+            if (x == left || x == right) && (y == top || y == bottom) {
+                continue;
+            }
+            let inside = x >= left + BORDER
+                && x <= right - BORDER
+                && y >= top + BORDER
+                && y <= bottom - BORDER;
+            let pixel = if !inside {
+                [220, 220, 220, 255]
+            } else if y >= fill_top {
+                [r, g, b, 255]
+            } else {
+                [60, 60, 60, 140]
+            };
+            let offset = (y * ICON_SIZE + x) * 4;
+            rgba[offset..offset + 4].copy_from_slice(&pixel);
+        }
+    }
+
+    Icon::Rgba {
+        rgba,
+        width: ICON_SIZE as u32,
+        height: ICON_SIZE as u32,
+    }
+}
+
+/// Menu for the current state
+pub fn build_menu(state: &AppState) -> SystemTrayMenu {
+    let mut providers: Vec<(&String, &ProviderUsage)> = state.providers.iter().collect();
+    providers.sort_by(|a, b| a.1.config.name.cmp(&b.1.config.name));
+
+    let mut menu = SystemTrayMenu::new();
+    for (id, provider) in providers.iter().filter(|(_, p)| p.config.enabled) {
+        let item = CustomMenuItem::new(format!("status:{}", id), status_line(provider)).disabled();
+        menu = menu.add_item(item);
+    }
+    menu = menu.add_native_item(SystemTrayMenuItem::Separator);
+
+    let mut switcher = SystemTrayMenu::new();
+    for (id, provider) in &providers {
+        let mut item = CustomMenuItem::new(format!("active:{}", id), provider.config.name.clone());
+        if **id == state.active_provider {
+            item = item.selected();
+        }
+        switcher = switcher.add_item(item);
+    }
+    menu = menu
+        .add_submenu(SystemTraySubmenu::new(tr!("tray.active_provider"), switcher))
+        .add_item(CustomMenuItem::new("refresh".to_string(), tr!("tray.refresh")));
+
+    let active_is_manual = state
+        .providers
+        .get(&state.active_provider)
+        .map(|p| p.config.provider_type == ProviderType::Manual)
+        .unwrap_or(false);
+    if active_is_manual {
+        menu = menu
+            .add_item(CustomMenuItem::new("add_request".to_string(), tr!("tray.add_request")))
+            .add_item(CustomMenuItem::new("reset".to_string(), tr!("tray.reset")));
+    }
+
+    let paused_until = state
+        .settings
+        .notifications_paused_until
+        .filter(|until| *until > Utc::now().timestamp());
+    menu = match paused_until.and_then(|until| Local.timestamp_opt(until, 0).single()) {
+        Some(until) => menu.add_item(CustomMenuItem::new(
+            "resume".to_string(),
+            tr!("tray.resume", time = until.format("%H:%M")),
+        )),
+        None => menu.add_item(CustomMenuItem::new("pause".to_string(), tr!("tray.pause"))),
+    };
+
+    menu.add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("settings".to_string(), tr!("tray.open_settings")))
+        .add_item(CustomMenuItem::new("show".to_string(), tr!("tray.show")))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("quit".to_string(), tr!("tray.quit")))
+}
+
+/// Refresh menu, tooltip, title and icon from the state.
+/// Takes the already-locked state so callers can use it inside their lock.
+pub fn update(app: &AppHandle, state: &AppState) {
+    let tray = app.tray_handle();
+    tray.set_menu(build_menu(state)).ok();
+
+    let provider = match state.providers.get(&state.active_provider) {
+        Some(provider) => provider,
+        None => {
+            tray.set_tooltip("MeterAI").ok();
+            return;
+        }
+    };
+
+    let summary = summary(provider);
+    tray.set_tooltip(&format!("MeterAI\n{}", summary)).ok();
+    #[cfg(target_os = "macos")]
+    tray.set_title(&summary).ok();
+
+    let percent = window_percents(provider)
+        .into_iter()
+        .map(|(_, percent)| percent)
+        .fold(0.0, f64::max);
+    let level = level(percent, &provider.config.alert_thresholds);
+    let key = (percent.round() as u32, level);
+
+    let mut last = LAST_ICON.lock().unwrap();
+    if *last != Some(key) {
+        tray.set_icon(render_icon(percent, level)).ok();
+        *last = Some(key);
+    }
+}
+
+/// `update` for callers that don't hold the state lock
+pub fn refresh(app: &AppHandle) {
+    let state = app.state::<Mutex<AppState>>();
+    let state = state.lock().unwrap();
+    update(app, &state);
+}
+
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        window.show().ok();
+        window.set_focus().ok();
+    }
+}
+
+fn set_active_provider(app: &AppHandle, provider_id: &str) {
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().unwrap();
+    if !state.providers.contains_key(provider_id) {
+        return;
+    }
+    state.active_provider = provider_id.to_string();
+    save_state(&state);
+    update(app, &state);
+
+    app.emit_all("active-provider-changed", provider_id).ok();
+    if let Some(provider) = state.providers.get(provider_id) {
+        emit_usage(app, &provider.usage);
+    }
+}
+
+pub fn handle_event(app: &AppHandle, event: SystemTrayEvent) {
+    match event {
+        SystemTrayEvent::LeftClick { .. } => show_main_window(app),
+        SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            "show" => show_main_window(app),
+            "settings" => {
+                show_main_window(app);
+                app.emit_all("open-settings", ()).ok();
+            }
+            "refresh" => {
+                tauri::async_runtime::spawn(scheduler::poll_all(app.clone()));
+            }
+            "add_request" => add_requests(app, 1),
+            "reset" => reset_active_usage(app),
+            "pause" => pause_notifications(app, Some(Utc::now().timestamp() + PAUSE_SECS)),
+            "resume" => pause_notifications(app, None),
+            "quit" => {
+                proxy_server::save_charges(app);
+                std::process::exit(0)
+            }
+            other => {
+                if let Some(provider_id) = other.strip_prefix("active:") {
+                    set_active_provider(app, provider_id);
+                }
+            }
+        },
+        _ => {}
+    }
+}
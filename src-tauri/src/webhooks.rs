@@ -0,0 +1,447 @@
+// ============== WEBHOOKS ==============
+//
+// Outgoing HTTP alerts, fired by the same events as the desktop notifications:
+// threshold crossings (counters and fetched windows), quota resets, forecasts
+// and budgets. Each target picks a payload format (generic JSON, Slack or
+// Discord incoming webhook), the thresholds it cares about and the other
+// events it opts in to.
+//
+// Deliveries run on a background thread with its own runtime, so alerts can
+// be raised from sync code, and use the headers and proxy of the `webhooks`
+// endpoint. Failed deliveries are retried with exponential backoff (or after
+// `Retry-After` on HTTP 429) and dropped after `MAX_ATTEMPTS`, which shows in
+// the metrics. Webhooks ignore the notification pause.
+
+use chrono::Utc;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::http;
+use crate::i18n::tr;
+use crate::metrics;
+use crate::{AppError, AppSettings};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Attempts per delivery, the first one included
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled on every following one
+const RETRY_BASE: Duration = Duration::from_secs(10);
+/// Wait of the worker when nothing is queued
+const IDLE_WAIT: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The `Alert` as JSON
+    #[default]
+    Generic,
+    /// `{"text": ...}` (Slack and Mattermost incoming webhooks)
+    Slack,
+    /// `{"content": ...}`
+    Discord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookTarget {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    pub enabled: bool,
+    /// Thresholds that fire this target (empty: every threshold)
+    #[serde(default)]
+    pub thresholds: Vec<u32>,
+    /// Also fire on quota resets
+    #[serde(rename = "notifyResets", default)]
+    pub notify_resets: bool,
+    /// Also fire when a window is projected to run out before its reset
+    #[serde(rename = "notifyForecasts", default)]
+    pub notify_forecasts: bool,
+    /// Also fire when a monthly budget is projected to be, or is, exceeded
+    #[serde(rename = "notifyBudgets", default)]
+    pub notify_budgets: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertEvent {
+    Threshold,
+    Reset,
+    /// Projected to run out before the reset
+    Forecast,
+    /// Monthly budget projected to be exceeded, or exceeded
+    Budget,
+}
+
+/// An alert, as shown in the desktop notification and sent to the webhooks
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub event: AlertEvent,
+    pub provider_id: String,
+    pub provider_name: String,
+    /// Window ID for fetched providers (None for request counters)
+    pub window: Option<String>,
+    /// Crossed threshold (None for resets)
+    pub threshold: Option<u32>,
+    pub percent: Option<f64>,
+    pub resets_at: Option<String>,
+    pub title: String,
+    pub message: String,
+    pub timestamp: i64,
+}
+
+impl Alert {
+    fn payload(&self, format: WebhookFormat) -> serde_json::Value {
+        match format {
+            WebhookFormat::Generic => serde_json::to_value(self).unwrap_or_default(),
+            WebhookFormat::Slack => json!({ "text": format!("*{}*\n{}", self.title, self.message) }),
+            WebhookFormat::Discord => json!({ "content": format!("**{}**\n{}", self.title, self.message) }),
+        }
+    }
+}
+
+impl WebhookTarget {
+    fn accepts(&self, alert: &Alert) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match alert.event {
+            AlertEvent::Reset => self.notify_resets,
+            AlertEvent::Forecast => self.notify_forecasts,
+            AlertEvent::Budget => self.notify_budgets,
+            AlertEvent::Threshold => {
+                self.thresholds.is_empty()
+                    || alert.threshold.is_some_and(|t| self.thresholds.contains(&t))
+            }
+        }
+    }
+}
+
+struct Delivery {
+    client: reqwest::Client,
+    target: WebhookTarget,
+    payload: serde_json::Value,
+    attempts: u32,
+}
+
+/// What comes after a delivery attempt
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Sent,
+    Retry(Duration),
+    Dropped,
+}
+
+/// Targets of `AppSettings.webhooks`, kept in sync by `configure`
+static TARGETS: RwLock<Vec<WebhookTarget>> = RwLock::new(Vec::new());
+/// Client with the `webhooks` endpoint settings, kept in sync by `configure`
+static CLIENT: RwLock<Option<reqwest::Client>> = RwLock::new(None);
+/// Sender to the delivery worker, started on the first alert
+static QUEUE: OnceLock<Mutex<mpsc::Sender<Delivery>>> = OnceLock::new();
+/// Deliveries queued or waiting for a retry
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Random ID for a new target
+pub fn new_id() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 4]>())
+}
+
+/// Apply the targets and the `webhooks` endpoint of `settings` to the next alerts
+pub fn configure(settings: &AppSettings) {
+    *TARGETS.write().unwrap() = settings.webhooks.clone();
+    // Invalid endpoint settings are rejected when saved
+    *CLIENT.write().unwrap() = http::client(settings, http::WEBHOOKS).ok();
+}
+
+/// Queue `alert` for every target that accepts it
+pub fn dispatch(alert: &Alert) {
+    let client = CLIENT.read().unwrap().clone().unwrap_or_default();
+    let targets = TARGETS.read().unwrap();
+    for target in targets.iter().filter(|t| t.accepts(alert)) {
+        enqueue(Delivery {
+            client: client.clone(),
+            target: target.clone(),
+            payload: alert.payload(target.format),
+            attempts: 0,
+        });
+    }
+}
+
+fn enqueue(delivery: Delivery) {
+    let queue = QUEUE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run_worker(receiver));
+        Mutex::new(sender)
+    });
+    PENDING.fetch_add(1, Ordering::SeqCst);
+    if queue.lock().unwrap().send(delivery).is_err() {
+        PENDING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wait until every queued delivery is sent or dropped, for at most `timeout`
+/// (the CLI exits right after raising an alert)
+pub fn flush(timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while PENDING.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn run_worker(receiver: mpsc::Receiver<Delivery>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(_) => {
+            // Nothing can be sent: drop every delivery
+            for delivery in receiver {
+                metrics::record_webhook_drop(&delivery.target.id);
+                PENDING.fetch_sub(1, Ordering::SeqCst);
+            }
+            return;
+        }
+    };
+    // (due time, delivery)
+    let mut queued: Vec<(Instant, Delivery)> = Vec::new();
+
+    loop {
+        let wait = queued
+            .iter()
+            .map(|(due, _)| due.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(IDLE_WAIT);
+        match receiver.recv_timeout(wait) {
+            Ok(delivery) => queued.push((Instant::now(), delivery)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        let (due, later): (Vec<_>, Vec<_>) = queued.drain(..).partition(|(at, _)| *at <= now);
+        queued = later;
+
+        for (_, mut delivery) in due {
+            match runtime.block_on(attempt(&mut delivery)) {
+                Outcome::Sent => {
+                    PENDING.fetch_sub(1, Ordering::SeqCst);
+                }
+                Outcome::Retry(delay) => queued.push((Instant::now() + delay, delivery)),
+                Outcome::Dropped => {
+                    metrics::record_webhook_drop(&delivery.target.id);
+                    PENDING.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+}
+
+/// Post `delivery` once and tell whether to retry it
+async fn attempt(delivery: &mut Delivery) -> Outcome {
+    delivery.attempts += 1;
+    match post(&delivery.client, &delivery.target.url, &delivery.payload).await {
+        Ok(()) => Outcome::Sent,
+        Err(_) if delivery.attempts >= MAX_ATTEMPTS => Outcome::Dropped,
+        Err(AppError::RateLimited(secs)) => Outcome::Retry(Duration::from_secs(secs)),
+        Err(_) => Outcome::Retry(RETRY_BASE * 2u32.pow(delivery.attempts - 1)),
+    }
+}
+
+async fn post(client: &reqwest::Client, url: &str, payload: &serde_json::Value) -> Result<(), AppError> {
+    let response = client
+        .post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| AppError::NetworkError(e.to_string()))?;
+
+    http::check_rate_limit(&response)?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AppError::ApiError(tr!(
+            "error.api_status",
+            service = "Webhook",
+            status = status,
+            body = body
+        )));
+    }
+    Ok(())
+}
+
+/// Send a sample threshold alert to `target` right away, without retries
+pub async fn send_test(settings: &AppSettings, target: &WebhookTarget) -> Result<(), AppError> {
+    let client = http::client(settings, http::WEBHOOKS)?;
+    let alert = Alert {
+        event: AlertEvent::Threshold,
+        provider_id: "test".to_string(),
+        provider_name: "MeterAI".to_string(),
+        window: None,
+        threshold: Some(90),
+        percent: Some(90.0),
+        resets_at: None,
+        title: tr!("webhook.test.title"),
+        message: tr!("webhook.test.body", name = target.name),
+        timestamp: Utc::now().timestamp(),
+    };
+    post(&client, &target.url, &alert.payload(target.format)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    type Received = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    /// Local server answering with `statuses` in turn (the last one repeated),
+    /// with `Retry-After: 7` on 429s; returns its URL and the bodies it got
+    async fn stub(statuses: &'static [u16]) -> (String, Received) {
+        let received: Received = Arc::default();
+        let bodies = received.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let bodies = bodies.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let bodies = bodies.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap_or_default();
+                        let mut bodies = bodies.lock().unwrap();
+                        bodies.push(serde_json::from_slice(&body).unwrap_or_default());
+                        let status = statuses[(bodies.len() - 1).min(statuses.len() - 1)];
+                        let mut response = Response::builder().status(status);
+                        if status == 429 {
+                            response = response.header("Retry-After", "7");
+                        }
+                        Ok::<_, Infallible>(response.body(Body::empty()).unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        (url, received)
+    }
+
+    fn target(url: &str, format: WebhookFormat) -> WebhookTarget {
+        WebhookTarget {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            url: url.to_string(),
+            format,
+            enabled: true,
+            thresholds: vec![],
+            notify_resets: false,
+            notify_forecasts: false,
+            notify_budgets: false,
+        }
+    }
+
+    fn delivery(url: &str) -> Delivery {
+        Delivery {
+            client: reqwest::Client::new(),
+            target: target(url, WebhookFormat::Generic),
+            payload: json!({ "event": "threshold" }),
+            attempts: 0,
+        }
+    }
+
+    fn alert(event: AlertEvent, threshold: Option<u32>) -> Alert {
+        Alert {
+            event,
+            provider_id: "anthropic".to_string(),
+            provider_name: "Claude".to_string(),
+            window: Some("five_hour".to_string()),
+            threshold,
+            percent: Some(92.0),
+            resets_at: None,
+            title: "Title".to_string(),
+            message: "Message".to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_back_off_then_drop() {
+        let (url, received) = stub(&[500]).await;
+        let mut delivery = delivery(&url);
+
+        for delay in [10, 20, 40, 80] {
+            assert_eq!(attempt(&mut delivery).await, Outcome::Retry(Duration::from_secs(delay)));
+        }
+        assert_eq!(attempt(&mut delivery).await, Outcome::Dropped);
+        assert_eq!(received.lock().unwrap().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_waits_for_retry_after() {
+        let (url, received) = stub(&[429, 200]).await;
+        let mut delivery = delivery(&url);
+
+        assert_eq!(attempt(&mut delivery).await, Outcome::Retry(Duration::from_secs(7)));
+        assert_eq!(attempt(&mut delivery).await, Outcome::Sent);
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_on_last_attempt_drops() {
+        let (url, _) = stub(&[429]).await;
+        let mut delivery = delivery(&url);
+        delivery.attempts = MAX_ATTEMPTS - 1;
+
+        assert_eq!(attempt(&mut delivery).await, Outcome::Dropped);
+    }
+
+    #[tokio::test]
+    async fn slack_and_discord_payloads() {
+        let (url, received) = stub(&[200]).await;
+        let settings = AppSettings::default();
+        send_test(&settings, &target(&url, WebhookFormat::Slack)).await.unwrap();
+        send_test(&settings, &target(&url, WebhookFormat::Discord)).await.unwrap();
+        send_test(&settings, &target(&url, WebhookFormat::Generic)).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let text = received[0]["text"].as_str().unwrap();
+        assert!(text.starts_with('*') && text.contains("*\n") && text.contains("Test"));
+        assert_eq!(received[0].as_object().unwrap().len(), 1);
+        let content = received[1]["content"].as_str().unwrap();
+        assert!(content.starts_with("**") && content.contains("**\n") && content.contains("Test"));
+        assert_eq!(received[1].as_object().unwrap().len(), 1);
+        assert_eq!(received[2]["event"], "threshold");
+        assert_eq!(received[2]["threshold"], 90);
+    }
+
+    #[test]
+    fn events_need_their_opt_in() {
+        let mut target = target("http://localhost", WebhookFormat::Generic);
+        assert!(target.accepts(&alert(AlertEvent::Threshold, Some(90))));
+        assert!(!target.accepts(&alert(AlertEvent::Reset, None)));
+        assert!(!target.accepts(&alert(AlertEvent::Forecast, None)));
+        assert!(!target.accepts(&alert(AlertEvent::Budget, None)));
+
+        target.thresholds = vec![75];
+        target.notify_forecasts = true;
+        target.notify_budgets = true;
+        assert!(!target.accepts(&alert(AlertEvent::Threshold, Some(90))));
+        assert!(target.accepts(&alert(AlertEvent::Threshold, Some(75))));
+        assert!(target.accepts(&alert(AlertEvent::Forecast, None)));
+        assert!(target.accepts(&alert(AlertEvent::Budget, None)));
+
+        target.enabled = false;
+        assert!(!target.accepts(&alert(AlertEvent::Budget, None)));
+    }
+}
@@ -100,9 +100,22 @@ interface OpenAIUsageResult {
   limit_usd: number | null
   percent: number | null
   is_pay_as_you_go: boolean
+  usage_available: boolean
+  data_source: 'organization' | 'legacy_billing' | 'unavailable'
   daily_usage: Array<{ date: string; cost_usd: number }>
 }
 
+// Report pushed by the background scheduler ('provider-usage-updated')
+interface UsageReport {
+  provider_id: string
+  provider_type: string
+  windows: Array<{ id: string; percent: number | null; used: number | null; limit: number | null; resets_at: string | null }>
+  cost_usd: number | null
+  subscription_type: string | null
+  details: any
+  fetched_at: number
+}
+
 // Token management types
 interface TokenStatus {
   has_internal_token: boolean
@@ -1393,8 +1406,20 @@ function App() {
       }))
     })
 
+    // Background polls from the Rust scheduler (also run while the window is hidden).
+    // Only the built-in accounts feed these panels: added accounts share their type.
+    const unlistenReports = listen<UsageReport>('provider-usage-updated', (event) => {
+      const { provider_id, details } = event.payload
+      if (provider_id === 'anthropic' && details?.claude_code) {
+        setClaudeCodeUsage(details.claude_code)
+      } else if (provider_id === 'openai' && details) {
+        setOpenaiUsage(details)
+      }
+    })
+
     return () => {
       unlisten.then(fn => fn())
+      unlistenReports.then(fn => fn())
     }
   }, [])
 
@@ -1823,6 +1848,21 @@ function App() {
     }
   }, [])
 
+  // Tray menu actions
+  useEffect(() => {
+    const unlistenSettings = listen('open-settings', () => {
+      openSettings()
+    })
+    const unlistenActive = listen<string>('active-provider-changed', (event) => {
+      setActiveProvider(event.payload)
+    })
+
+    return () => {
+      unlistenSettings.then(fn => fn())
+      unlistenActive.then(fn => fn())
+    }
+  }, [openSettings])
+
   const getColor = (percent: number) => {
     if (percent >= 90) return 'red'
     if (percent >= 70) return 'yellow'
@@ -3607,7 +3647,11 @@ function App() {
                           <div className="expanded-usage-block">
                             <div className="expanded-usage-label-row">
                               <span className="expanded-usage-label">Usage</span>
-                              {openaiUsage?.success ? (
+                              {openaiUsage?.success && !openaiUsage.usage_available ? (
+                                <span className="expanded-usage-badge badge-openai" title={openaiUsage.error ?? undefined}>
+                                  Usage unavailable
+                                </span>
+                              ) : openaiUsage?.success ? (
                                 openaiUsage.is_pay_as_you_go ? (
                                   <span className="expanded-usage-badge badge-openai">
                                     ${(openaiUsage.usage_usd ?? 0).toFixed(2)} (Pay as you go)